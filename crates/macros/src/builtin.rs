@@ -21,12 +21,14 @@ fn append_builtin(name: String) {
 
 pub struct Builtin {
     func: Function,
+    /// Overrides the camelCase name, for acronyms like `fromTOML`
+    nix_name: Option<String>,
     params: NixBuiltinParams,
     struct_name: Ident,
 }
 
 impl Builtin {
-    pub fn new(func: Function) -> Result<Self, Error> {
+    pub fn new(func: Function, nix_name: Option<String>) -> Result<Self, Error> {
         let func_name = func.name.to_string();
         let struct_name = func_name
             .strip_prefix("r#")
@@ -41,13 +43,16 @@ impl Builtin {
 
         Ok(Self {
             func,
+            nix_name,
             struct_name,
             params,
         })
     }
 
     fn nix_ident(&self) -> String {
-        self.struct_name.to_string().to_case(Case::Camel)
+        self.nix_name
+            .clone()
+            .unwrap_or_else(|| self.struct_name.to_string().to_case(Case::Camel))
     }
 
     fn generate_builtin(&self) -> TokenStream {
@@ -63,6 +68,12 @@ impl Builtin {
                     #nix_ident
                 }
 
+                fn is_partial(&self) -> bool {
+                    let Self(#(#params_list),*) = &self;
+                    let applied: &[bool] = &[#(#params_list.is_some()),*];
+                    applied.contains(&true)
+                }
+
                 fn run(
                     &self,
                     backtrace: &crate::NixBacktrace,
@@ -8,7 +8,7 @@ use venial::{parse_item, Error, Item};
 
 #[proc_macro_attribute]
 pub fn builtin(
-    _: proc_macro::TokenStream,
+    attr: proc_macro::TokenStream,
     body: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     let func = match parse_item(body.into()) {
@@ -17,7 +17,16 @@ pub fn builtin(
         Ok(_) => Err(Error::new("")),
     };
 
-    func.and_then(Builtin::new)
+    // `#[builtin("fromTOML")]`
+    let nix_name = attr.into_iter().find_map(|token| match token {
+        proc_macro::TokenTree::Literal(lit) => {
+            let lit = lit.to_string();
+            Some(lit.trim_matches('"').to_owned())
+        }
+        _ => None,
+    });
+
+    func.and_then(|func| Builtin::new(func, nix_name))
         .and_then(Builtin::generate)
         .unwrap_or_else(|e| e.to_compile_error())
         .into()
@@ -0,0 +1,33 @@
+# Test values that reach themselves while being evaluated
+#@@@
+# true
+
+let
+  s = rec {
+    a = { x = 1; };
+    b = s // s.a;
+    c = builtins.mapAttrs (name: value: s.a.x) s;
+  };
+
+  t = { a = 1; b = 2; };
+in
+
+# Rec attrset mapped and updated with itself
+assert s.b.x == 1;
+assert s.c.a == 1;
+assert s.c.c == 1;
+assert builtins.length (builtins.attrNames (builtins.mapAttrs (name: value: value) s)) == 3;
+
+# `let` inside `with` while the namespace is being mapped
+assert builtins.mapAttrs (name: value: with t; let z = 1; in value + z) t == { a = 2; b = 3; };
+
+# `let` bindings don't leak into the `with` namespace
+assert (with t; let a = 5; in a) == 5;
+assert t.a == 1;
+assert !(t ? z);
+
+# Inherit from a set that refers to itself
+assert { inherit (s) b; }.b.x == 1;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,84 @@
+# Test the arithmetic operators and builtins, they share the same semantics
+#@@@
+# true
+
+let
+  # Every operation as operator and builtin, with the result and its type
+  check = { op, builtin, lhs, rhs, expected }:
+    let
+      a = op lhs rhs;
+      b = builtin lhs rhs;
+    in
+    a == expected && b == expected
+    && builtins.isFloat a == builtins.isFloat expected
+    && builtins.isFloat b == builtins.isFloat expected;
+
+  add = { op = a: b: a + b; builtin = builtins.add; };
+  sub = { op = a: b: a - b; builtin = builtins.sub; };
+  mul = { op = a: b: a * b; builtin = builtins.mul; };
+  div = { op = a: b: a / b; builtin = builtins.div; };
+  lessThan = { op = a: b: a < b; builtin = builtins.lessThan; };
+in
+
+# int op int is an int, anything involving a float is a float
+assert builtins.all check [
+  (add // { lhs = 1; rhs = 2; expected = 3; })
+  (add // { lhs = 1; rhs = 2.5; expected = 3.5; })
+  (add // { lhs = 1.5; rhs = 2; expected = 3.5; })
+  (add // { lhs = 1.5; rhs = 2.5; expected = 4.0; })
+
+  (sub // { lhs = 1; rhs = 3; expected = -2; })
+  (sub // { lhs = 1; rhs = 0.5; expected = 0.5; })
+  (sub // { lhs = 1.5; rhs = 1; expected = 0.5; })
+  (sub // { lhs = 1.5; rhs = 0.5; expected = 1.0; })
+
+  (mul // { lhs = 3; rhs = -4; expected = -12; })
+  (mul // { lhs = 3; rhs = 0.5; expected = 1.5; })
+  (mul // { lhs = 0.5; rhs = 3; expected = 1.5; })
+  (mul // { lhs = 0.5; rhs = 0.5; expected = 0.25; })
+
+  # Integer division truncates toward zero
+  (div // { lhs = 7; rhs = 2; expected = 3; })
+  (div // { lhs = -7; rhs = 2; expected = -3; })
+  (div // { lhs = 7; rhs = -2; expected = -3; })
+  (div // { lhs = 7; rhs = 2.0; expected = 3.5; })
+  (div // { lhs = 7.0; rhs = 2; expected = 3.5; })
+  (div // { lhs = 1.0; rhs = 4.0; expected = 0.25; })
+];
+
+# Comparisons mix numbers and also work on strings
+assert builtins.all check [
+  (lessThan // { lhs = 1; rhs = 2; expected = true; })
+  (lessThan // { lhs = 2; rhs = 1; expected = false; })
+  (lessThan // { lhs = 1; rhs = 1.5; expected = true; })
+  (lessThan // { lhs = 1.5; rhs = 1; expected = false; })
+  (lessThan // { lhs = "abc"; rhs = "abd"; expected = true; })
+  (lessThan // { lhs = "b"; rhs = "a"; expected = false; })
+  (lessThan // { lhs = ""; rhs = "a"; expected = true; })
+  (lessThan // { lhs = /a/b; rhs = /a/c; expected = true; })
+  (lessThan // { lhs = [ 1 2 ]; rhs = [ 1 3 ]; expected = true; })
+  (lessThan // { lhs = [ 1 ]; rhs = [ 1 2 ]; expected = true; })
+  (lessThan // { lhs = [ ]; rhs = [ ]; expected = false; })
+];
+
+assert 1 + 2.0 == 3.0 && builtins.isFloat (1 + 2.0);
+assert 7 / 2 == 3 && 7.0 / 2 == 3.5 && 2 * 0.5 == 1.0;
+
+# Overflowing is an error, the largest values still work
+assert 9223372036854775806 + 1 == 9223372036854775807;
+assert -9223372036854775807 - 1 < 0;
+assert -(-9223372036854775807) == 9223372036854775807;
+assert -(-1.5) == 1.5;
+
+assert 2 > 1 && !(1 > 2);
+assert 2 >= 2 && 2.5 >= 2 && !(1 >= 2);
+assert 2 <= 2 && 1 <= 1.5 && !(2 <= 1);
+assert "b" > "a";
+assert "b" >= "a" && "a" >= "a" && !("a" >= "b");
+assert 1.5 > 1 && 2 > 1.5 && 2.0 >= 2;
+assert /a/c > /a/b && /a >= /a;
+assert [ 1 2 ] > [ 1 ] && [ 1 2 ] >= [ 1 2 ] && !([ 1 ] > [ 1 2 ]);
+assert [ "a" [ 2 ] ] < [ "a" [ 3 ] ];
+assert [ 1 (throw "only the first difference is compared") ] < [ 2 (throw "x") ];
+
+true
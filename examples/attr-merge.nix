@@ -0,0 +1,21 @@
+# Test merging the definitions of nested attributes
+#@@@
+# true
+
+# Paths and sets written in place merge, in any order
+assert { a.b = 1; a.c = 2; } == { a = { b = 1; c = 2; }; };
+assert { a = { b = 1; }; a.c = 2; } == { a = { b = 1; c = 2; }; };
+assert { a.c = 2; a = { b = 1; }; } == { a = { b = 1; c = 2; }; };
+assert { a = { b = 1; }; a = { c = 2; }; } == { a = { b = 1; c = 2; }; };
+assert { a.b.c = 1; a.b.d = 2; } == { a = { b = { c = 1; d = 2; }; }; };
+assert { a.b.c = 1; a = { b.d = 2; }; } == { a = { b = { c = 1; d = 2; }; }; };
+
+# The same in `let` and `rec`
+assert (let a.b = 1; a.c = 2; in a) == { b = 1; c = 2; };
+assert (rec { a.b = 1; a.c = a.b + 1; }).a.c == 2;
+
+# Values are still lazy
+assert ({ a.b = throw "x"; a.c = 2; }).a.c == 2;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,16 @@
+# Test `-A`, selecting in this set with quoted keys and missing attributes:
+#   -A 'a."b.c".d'   is true
+#   -A 'a."b.c".e'   fails, the set has `d` and `f`
+#   -A 'a.x.y'       fails naming the whole path
+#   -A 'a."b.c".d.e' fails, `d` is a bool
+#@@@
+# { a = { "b.c" = { d = true; f = 1; }; }; }
+
+{
+  a = {
+    "b.c" = {
+      d = true;
+      f = 1;
+    };
+  };
+}
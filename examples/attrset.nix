@@ -0,0 +1,104 @@
+# Test attribute set specific builtins
+#@@@
+# true
+
+# getAttr
+assert builtins.getAttr "a" { a = 1; } == 1;
+assert builtins.getAttr ("a" + "b") { ab = 1; } == 1;
+
+# hasAttr
+assert builtins.hasAttr "a" { a = throw "hasAttr must not force the value"; };
+assert builtins.hasAttr ("a" + "b") { ab = 1; };
+assert !(builtins.hasAttr "b" { a = 1; });
+assert
+  let
+    drv = derivation { name = "x"; builder = "/bin/sh"; system = "x86_64-linux"; };
+  in
+  builtins.hasAttr "out" drv && builtins.hasAttr "drvPath" drv && !(builtins.hasAttr "dev" drv);
+
+# intersectAttrs
+assert builtins.intersectAttrs { a = 0; b = 0; } { b = 2; c = 3; } == { b = 2; };
+assert builtins.intersectAttrs { a = 0; b = 0; c = 0; } { c = 3; } == { c = 3; };
+assert builtins.intersectAttrs { a = 1; } { b = 2; } == { };
+assert
+  let
+    drv = {
+      out = throw "intersectAttrs must not force the values";
+      name = "drv";
+    };
+    result = builtins.intersectAttrs { out = null; } drv;
+  in
+  builtins.length (builtins.attrNames result) == 1 && builtins.hasAttr "out" result;
+assert
+  let
+    drv = derivation { name = "x"; builder = "/bin/sh"; system = "x86_64-linux"; };
+    result = builtins.intersectAttrs { out = null; } drv;
+  in
+  builtins.attrNames result == [ "out" ]
+  && result.out.outPath == drv.out.outPath
+  && result.out.drvPath == drv.drvPath
+  && result.out.outputName == "out";
+
+# zipAttrsWith
+assert
+  let
+    zipped = builtins.zipAttrsWith (name: values: values) [ { a = 1; } { a = 2; b = 3; } { } ];
+  in
+  builtins.length (builtins.attrNames zipped) == 2
+  && builtins.length zipped.a == 2
+  && builtins.elemAt zipped.a 0 == 1
+  && builtins.elemAt zipped.a 1 == 2
+  && builtins.elemAt zipped.b 0 == 3;
+assert builtins.zipAttrsWith (name: values: name) [ { a = 1; } { b = 2; } ] == { a = "a"; b = "b"; };
+assert builtins.zipAttrsWith (name: values: values) [ ] == { };
+assert (builtins.zipAttrsWith (name: values: throw "not forced") [ { a = 1; } ]) ? a;
+assert
+  let
+    # The merge idiom of nixpkgs
+    zipAttrs = builtins.zipAttrsWith (n: v: v);
+    merged = zipAttrs [ { x = 1; y = 2; } { x = 3; } ];
+  in
+  builtins.length merged.x == 2 && builtins.elemAt merged.x 1 == 3 && builtins.elemAt merged.y 0 == 2;
+
+# listToAttrs, the first binding of a name wins and values stay lazy
+assert builtins.listToAttrs [ { name = "a"; value = 1; } { name = "a"; value = throw "x"; } ] == { a = 1; };
+assert builtins.listToAttrs [ ] == { };
+assert
+  let
+    listed = builtins.listToAttrs [
+      { name = "a"; value = 1; }
+      { name = "b"; value = throw "listToAttrs must not force the values"; }
+    ];
+  in
+  listed.a == 1 && builtins.hasAttr "b" listed;
+
+# mapAttrs, the callback is only applied when a value is forced
+assert builtins.mapAttrs (name: value: name + value) { a = "1"; b = "2"; } == { a = "a1"; b = "b2"; };
+assert (builtins.mapAttrs (name: value: value + 1) { a = 1; b = throw "x"; }).a == 2;
+assert
+  let
+    set = { a = throw "x"; b = throw "y"; };
+    mapped = builtins.mapAttrs (name: throw "mapAttrs must not call the callback") set;
+  in
+  # `==` on lists only compares the references, compare the joined names
+  builtins.concatStringsSep "," (builtins.attrNames mapped) == "a,b";
+
+# removeAttrs, kept values stay lazy
+assert builtins.removeAttrs { a = 1; b = 2; c = 3; } [ "a" "c" ] == { b = 2; };
+assert builtins.removeAttrs { a = 1; } [ "b" ] == { a = 1; };
+assert builtins.removeAttrs { a = 1; } [ ] == { a = 1; };
+assert builtins.hasAttr "b" (builtins.removeAttrs { a = 1; b = throw "removeAttrs must not force the values"; } [ "a" ]);
+assert
+  let
+    drv = derivation {
+      name = "multi";
+      system = "x86_64-linux";
+      builder = "/bin/sh";
+      outputs = [ "out" "dev" ];
+    };
+    result = builtins.removeAttrs drv [ "dev" ];
+  in
+  !(builtins.hasAttr "dev" result) && result.type == "derivation" && result.out.outPath == drv.out.outPath;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,21 @@
+# Test bitAnd, bitOr and bitXor, negative numbers are two's complement
+#@@@
+# true
+
+assert builtins.bitAnd 12 10 == 8;
+assert builtins.bitOr 12 10 == 14;
+assert builtins.bitXor 12 10 == 6;
+
+# Negative operands
+assert builtins.bitAnd (-6) (-3) == -8;
+assert builtins.bitOr (-6) (-3) == -1;
+assert builtins.bitXor (-6) (-3) == 7;
+
+# Mixed sign operands
+assert builtins.bitAnd (-1) 5 == 5;
+assert builtins.bitOr (-8) 3 == -5;
+assert builtins.bitXor (-6) 3 == -7;
+assert builtins.bitAnd 9223372036854775807 (-1) == 9223372036854775807;
+assert builtins.bitXor 9223372036854775807 (-1) == -9223372036854775807 - 1;
+
+true
@@ -0,0 +1,14 @@
+# Test deep recursions below the max-call-depth (10000 by default)
+#@@@
+# true
+
+let
+  count = n: if n == 0 then 0 else 1 + count (n - 1);
+  nest = n: if n == 0 then [ ] else [ (nest (n - 1)) ];
+in
+
+assert count 5000 == 5000;
+assert builtins.deepSeq (nest 5000) true;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,13 @@
+# Check: every reference here is resolvable without evaluation
+let
+  f = { a, b ? a, ... }@args: a + b + args.a;
+  set = rec {
+    x = y;
+    y = 1;
+    inherit f;
+  };
+in
+{
+  inherit (set) x;
+  y = f { a = set.y; };
+}
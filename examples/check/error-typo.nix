@@ -0,0 +1,5 @@
+# Check: `nix-compiler check examples/check` must flag `valeu`
+let
+  value = 1;
+in
+value + valeu
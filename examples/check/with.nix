@@ -0,0 +1,5 @@
+# Check: `length` only comes from `with`, so it must not be flagged
+let
+  list = [ 1 2 3 ];
+in
+with builtins; length list
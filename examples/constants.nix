@@ -0,0 +1,42 @@
+# Test that constant literals served from the per-file table stay independent
+#@@@
+# true
+
+let
+  # Each call extends the same literal attrset
+  extend = value: { a = { b = 1; }; a.c = value; };
+
+  first = extend 1;
+  second = extend 2;
+  third = extend 3;
+
+  defaults = { set ? { x = 1; }, list ? [ { y = 2; } ], str ? "s" }: { inherit set list str; };
+
+  called = builtins.genList (i: defaults { }) 3;
+
+  literal = _: { a = 1; };
+
+  # Not constant, they depend on the scope
+  scoped = x: [ rec { a = x; b = a; } "${x}" ];
+in
+
+assert first.a.c == 1;
+assert second.a.c == 2;
+assert third.a.c == 3;
+assert first.a.c == 1;
+assert second.a.c == 2;
+assert first.a.b == second.a.b;
+assert builtins.length (builtins.attrNames first.a) == 2;
+
+assert builtins.all (call: call.set.x == 1 && (builtins.elemAt call.list 0).y == 2 && call.str == "s") called;
+
+# Every copy keeps the positions of its attributes
+assert builtins.unsafeGetAttrPos "a" (literal 1) != null;
+assert builtins.unsafeGetAttrPos "a" (literal 2) != null;
+assert builtins.unsafeGetAttrPos "a" (literal 2) == builtins.unsafeGetAttrPos "a" (literal 1);
+
+assert (builtins.elemAt (scoped "a") 0).b == "a";
+assert (builtins.elemAt (scoped "b") 0).b == "b";
+assert builtins.elemAt (scoped "c") 1 == "c";
+
+true
@@ -0,0 +1,38 @@
+let
+  # sha256 of "abc"
+  base16 = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+  nix32 = "1b8m03r63zqhnjf7l5wnldhh7c134ap5vpj0850ymkq1iyzicy5s";
+  base64 = "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0=";
+  sri = "sha256-${base64}";
+
+  convert = hash: toHashFormat:
+    builtins.convertHash { inherit hash toHashFormat; hashAlgo = "sha256"; };
+
+  roundTrip = from: builtins.all (to: convert (convert from to) "base16" == base16) [
+    "base16"
+    "nix32"
+    "base64"
+    "sri"
+  ];
+in
+assert convert base16 "nix32" == nix32;
+assert convert base16 "base32" == nix32;
+assert convert nix32 "base64" == base64;
+assert convert base64 "sri" == sri;
+assert convert sri "base16" == base16;
+
+assert roundTrip base16;
+assert roundTrip nix32;
+assert roundTrip base64;
+assert roundTrip sri;
+
+# The algorithm comes from the hash itself
+assert builtins.convertHash { hash = sri; toHashFormat = "nix32"; } == nix32;
+assert builtins.convertHash { hash = "sha256:${nix32}"; toHashFormat = "base16"; } == base16;
+assert builtins.convertHash {
+  hash = builtins.hashString "md5" "abc";
+  hashAlgo = "md5";
+  toHashFormat = "sri";
+} == "md5-kAFQmDzST7DWlj99KOF/cg==";
+
+true
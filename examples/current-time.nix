@@ -0,0 +1,19 @@
+# Test `builtins.currentTime` and the other constants
+#@@@
+# true
+
+let
+  first = builtins.currentTime;
+in
+
+# One timestamp for the whole evaluation, even across files
+assert builtins.isInt first;
+assert first > 1700000000;
+assert builtins.currentTime == first;
+assert import ./current-time/time.nix == first;
+
+assert builtins.langVersion == 6;
+assert builtins.isList builtins.nixPath;
+
+# If everything is ok, then return true
+true
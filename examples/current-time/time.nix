@@ -0,0 +1 @@
+builtins.currentTime
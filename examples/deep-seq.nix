@@ -0,0 +1,27 @@
+# Test `builtins.deepSeq`
+#@@@
+# true
+
+let
+  buried = { a = [ { b = throw "buried"; } ]; };
+  shared = let a = { b = 1; }; in [ a a { c = a; } ];
+
+  # Attributes are lazy, so `tryEval` sees their errors
+  forced = {
+    buried = builtins.deepSeq buried true;
+  };
+in
+
+assert builtins.deepSeq { a.b.c = 1; } true;
+assert builtins.deepSeq shared true;
+
+# `seq` only forces the outer set
+assert builtins.seq buried true;
+
+# Errors deep inside are reached
+assert !(builtins.tryEval forced.buried).success;
+
+# Self-referential values fail instead of looping, see error-deep-seq.nix
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,35 @@
+# Test the attributes `derivation` adds to the ones it's given
+#@@@
+# true
+
+let
+  drv = derivation {
+    name = "attrs";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    outputs = [ "out" "dev" ];
+  };
+
+  getOutput = output: pkg: pkg.${output} or pkg;
+in
+
+assert builtins.attrNames drv == [
+  "all" "builder" "dev" "drvAttrs" "drvPath" "name" "out" "outPath" "outputName" "outputs" "system" "type"
+];
+assert builtins.attrNames drv.dev == builtins.attrNames drv;
+
+assert (drv.type or null) == "derivation";
+assert drv.outputName == "out";
+assert drv.dev.outputName == "dev";
+assert map (output: output.outputName) drv.all == drv.outputs;
+assert drv.drvAttrs.name == "attrs";
+
+# A derivation is its output path wherever a string is needed
+assert toString drv == drv.outPath;
+assert toString drv.dev == drv.dev.outPath;
+assert "${drv.dev}" == drv.dev.outPath;
+assert (getOutput "dev" drv).outPath == drv.dev.outPath;
+assert (getOutput "man" drv).outPath == drv.outPath;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,32 @@
+# `meta` and `passthru` are kept on a derivation without forcing them, and
+# they aren't part of its `.drv`
+let
+  base = {
+    name = "meta";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  };
+
+  plain = derivation base;
+
+  described = derivation (base // {
+    meta = {
+      description = "A package";
+      broken = throw "not forced";
+    };
+    passthru = throw "not forced";
+  });
+
+  throwing = derivation (base // { meta = throw "not forced"; });
+in
+
+# A throwing `meta` still gives a `.drv`, the same as without it
+assert throwing.drvPath == plain.drvPath;
+assert described.drvPath == plain.drvPath;
+assert described.outPath == plain.outPath;
+
+# And `meta` can be read, without forcing what isn't selected
+assert described.meta.description == "A package";
+assert !(builtins.tryEval throwing.meta).success;
+
+true
@@ -0,0 +1,90 @@
+# Test the outputs of derivations: several of them, fixed by `outputHash`,
+# content addressed once built and impure
+#@@@
+# true
+
+let
+  empty = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+  base = {
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  };
+
+  withPlaceholder = script: derivation (base // {
+    name = "placeholder";
+    inherit script;
+  });
+
+  three = derivation (base // {
+    name = "three";
+    outputs = [ "out" "dev" "doc" ];
+  });
+
+  fixed = derivation (base // {
+    name = "fixed";
+    outputHash = empty;
+    outputHashAlgo = "sha256";
+    outputHashMode = "recursive";
+  });
+
+  # The same contents, built in another way
+  fixedOther = derivation (base // {
+    name = "fixed";
+    args = [ "-c" "true" ];
+    outputHash = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+    outputHashMode = "recursive";
+  });
+
+  user = fixed: derivation (base // {
+    name = "user";
+    inherit fixed;
+  });
+
+  floating = derivation (base // {
+    name = "floating";
+    __contentAddressed = true;
+  });
+
+  impure = derivation (base // {
+    name = "impure";
+    __impure = true;
+  });
+in
+
+# Every output has its own path, and all of them are in `all`
+assert three.dev.outPath != three.out.outPath;
+assert three.doc.outPath != three.out.outPath;
+assert builtins.length three.all == 3;
+assert map (drv: drv.outputName) three.all == [ "out" "dev" "doc" ];
+assert three.doc.drvPath == three.drvPath;
+
+# A fixed output is addressed by what it contains, NAR SHA-256 ones are sources.
+# The store directory is hashed too, these are the paths of the default one.
+assert builtins.storeDir == "/nix/store";
+assert fixed.drvPath == "/nix/store/4b9s5hl4x73j5ysrl2q5mgd6ah3rbplq-fixed.drv";
+assert fixed.outPath == "/nix/store/kp31qdxv2x30cky0bvvf88vv91b0sfjy-fixed";
+assert fixedOther.outPath == fixed.outPath;
+assert fixedOther.drvPath != fixed.drvPath;
+
+# So the ones depending on it don't change either
+assert (user fixed).outPath == (user fixedOther).outPath;
+assert (user fixed).drvPath != (user fixedOther).drvPath;
+
+# Paths that are only known once built are placeholders
+assert floating.drvPath == "/nix/store/slwdqsvhyvi8znyrrmz5cspaif7nasbw-floating.drv";
+assert floating.outPath == "/1s3rmibm445xqdm4hj9jrjbgd1llzyhafm3lkcj2yd6vs7gychkg";
+assert builtins.substring 0 11 impure.outPath != "/nix/store/";
+assert builtins.substring 0 11 (user floating).outPath != "/nix/store/";
+assert builtins.getContext floating.outPath == {
+  ${builtins.unsafeDiscardStringContext floating.drvPath} = { outputs = [ "out" ]; };
+};
+
+# The environment keeps placeholders as they are, it's the same as writing
+# the string itself
+assert builtins.placeholder "out" == "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9";
+assert (withPlaceholder "echo > ${builtins.placeholder "out"}").drvPath
+  == (withPlaceholder "echo > /1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9").drvPath;
+
+# If everything is ok, then return true
+true
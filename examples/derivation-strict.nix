@@ -0,0 +1,92 @@
+# `derivation` and `derivationStrict` give the same store paths as Nix
+let
+  hello = derivation {
+    name = "hello";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    args = [ "-c" "echo hi > $out" ];
+  };
+
+  multi = derivation {
+    name = "multi";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    outputs = [ "out" "dev" ];
+    dependency = hello;
+    flags = [ 1 [ ] "x" ];
+    enable = true;
+    disable = false;
+    missing = null;
+    __ignoreNulls = true;
+  };
+
+  split = derivation {
+    name = "split";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    outputs = [ "out" "dev" ];
+  };
+
+  # Non-ASCII characters are kept as they are, only `"`, `\` and the
+  # newline, return and tab characters are escaped
+  escapes = derivation {
+    name = "escapes";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    text = "café\t\"q\"\r\n\\ $";
+  };
+
+  # The derivations and files the environment refers to are inputs, with
+  # the outputs used of each
+  a = derivation {
+    name = "a";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  };
+
+  b = derivation {
+    name = "b";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    outputs = [ "out" "dev" ];
+  };
+
+  inputs = derivation {
+    name = "c";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    buildInputs = [ a b.dev ];
+    src = ./read-dir/file.txt;
+  };
+
+  strict = builtins.derivationStrict multi.drvAttrs;
+in
+assert hello.type == "derivation";
+assert hello.drvPath == "/nix/store/76w21n1f03fs5kw8fnffphx7qrqffw6r-hello.drv";
+assert hello.outPath == "/nix/store/mjs27ix6ig2bkbi3s3sm470vrv4lf7ic-hello";
+assert hello.outputName == "out";
+
+assert split.drvPath == "/nix/store/lkjjmmrac7gfac7g2pfhyr0qy50kp7c1-split.drv";
+assert split.out.outPath == "/nix/store/mqspbkwzwrszwaam2k85px1s9n5nscl4-split";
+assert split.dev.outPath == "/nix/store/ly0z570q7h7d3qg33yiq8qs9pkvd3q7m-split-dev";
+
+assert escapes.drvPath == "/nix/store/05l8f9rs8pqnfy1mp1lsgnf61dpnj6ns-escapes.drv";
+assert escapes.outPath == "/nix/store/cg6i1x5llcb6k7xl0ivchxcksjzqjcd0-escapes";
+
+assert a.drvPath == "/nix/store/7g5giqf764p3y3zv7a8rqsy9sqqq5kw4-a.drv";
+assert b.drvPath == "/nix/store/kjpbdz27sy630ysil9xrwsi9aibm93g0-b.drv";
+assert inputs.drvPath == "/nix/store/2gxa1bd3a9yj6kyrx44vdj3i68pn7fkp-c.drv";
+assert inputs.outPath == "/nix/store/h724sc1gcblkd9hy932npdj3yb519pyj-c";
+
+assert multi.dev.outputName == "dev";
+assert multi.dev.drvPath == multi.drvPath;
+assert multi.out.outPath == multi.outPath;
+assert builtins.length multi.all == 2;
+
+assert strict.drvPath == multi.drvPath;
+assert strict.out == multi.outPath;
+assert strict.dev == multi.dev.outPath;
+assert builtins.hasContext strict.drvPath;
+assert builtins.elemAt (builtins.attrNames (builtins.getContext multi.outPath)) 0 == multi.drvPath;
+
+true
@@ -0,0 +1,44 @@
+# Test depending on derivations that are only known by their `.drv` file
+#@@@
+# true
+
+let
+  base = {
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  };
+
+  hello = derivation (base // {
+    name = "hello";
+    args = [ "-c" "echo hi > $out" ];
+  });
+
+  # The same derivation, written by Nix
+  helloFile = builtins.unsafeDiscardStringContext (builtins.path { path = ./drv/hello.drv; });
+
+  user = input: derivation (base // {
+    name = "user";
+    inherit input;
+  });
+
+  fromFile = user (builtins.appendContext (builtins.unsafeDiscardStringContext hello.outPath) {
+    ${helloFile} = { outputs = [ "out" ]; };
+  });
+
+  # Nothing in its environment is needed, not even the name
+  namelessFile = builtins.unsafeDiscardStringContext (builtins.path { path = ./drv/nameless.drv; });
+
+  fromNameless = user (builtins.appendContext "" {
+    ${namelessFile} = { outputs = [ "out" ]; };
+  });
+in
+
+# The `.drv` is read back to hash it, only its contents matter
+assert fromFile.outPath == (user hello).outPath;
+assert fromFile.drvPath != (user hello).drvPath;
+
+assert builtins.isString fromNameless.drvPath;
+assert fromNameless.outPath != fromFile.outPath;
+
+# If everything is ok, then return true
+true
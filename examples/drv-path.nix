@@ -0,0 +1,34 @@
+# Test `drvPath`, the store path of the `.drv` file of a derivation
+#@@@
+# true
+
+let
+  mkDrv = args: derivation ({
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  } // args);
+
+  dep = mkDrv { name = "dep"; };
+  top = mkDrv { name = "top"; src = dep; };
+  broken = mkDrv { name = "broken"; env = throw "not forced"; };
+
+  isDrvPath = name: path:
+    builtins.match "/nix/store/[0-9a-df-np-sv-z]{32}-${name}[.]drv" path != null;
+in
+
+assert isDrvPath "dep" dep.drvPath;
+assert isDrvPath "top" top.drvPath;
+
+# It's the same each time, and it changes with the inputs
+assert dep.drvPath == (mkDrv { name = "dep"; }).drvPath;
+assert top.drvPath != (mkDrv { name = "top"; src = "other"; }).drvPath;
+
+# The string depends on the whole `.drv`
+assert builtins.getContext top.drvPath == { ${builtins.unsafeDiscardStringContext top.drvPath} = { allOutputs = true; }; };
+
+# It's only computed when needed
+assert broken.name == "broken";
+assert !(builtins.tryEval broken.drvPath).success;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,48 @@
+# Test `==` on lists and sets, compared by value
+#@@@
+# true
+
+let
+  f = x: x;
+
+  drv = derivation {
+    name = "eq";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  };
+in
+
+# Lists compare their elements, not their identity
+assert [ 1 2 3 ] == [ 1 2 3 ];
+assert [ [ 1 ] ] == [ [ 1 ] ];
+assert [ 1 2.0 ] == [ 1.0 2 ];
+assert [ { a = [ "x" ]; } ] == [ { a = [ "x" ]; } ];
+assert [ f ] == [ f ];
+assert [ ] == [ ];
+assert [ 1 2 ] != [ 1 2 3 ];
+assert [ 1 2 ] != [ 2 1 ];
+assert [ [ 1 ] ] != [ [ 2 ] ];
+
+# Lengths are checked before forcing any element
+assert [ 1 (throw "forced") ] != [ 1 ];
+
+# Sets compare their names and values
+assert { a = 1; } == { a = 1; };
+assert { a = 1; b.c = [ 2 ]; } == { b = { c = [ 2 ]; }; a = 1; };
+assert { a = 1; } != { b = 1; };
+assert { a = 1; } != { a = 1; b = 2; };
+assert { } == { };
+
+# Derivations compare their outPaths
+assert drv == drv;
+assert drv == { type = "derivation"; inherit (drv) outPath; };
+assert drv == drv // { extra = 1; };
+assert drv != { inherit (drv) outPath; };
+assert drv != derivation { name = "other"; system = "x86_64-linux"; builder = "/bin/sh"; };
+
+# Without outPaths they are compared as sets
+assert { type = "derivation"; a = 1; } == { type = "derivation"; a = 1; };
+assert { type = "derivation"; outPath = "x"; } != { type = "derivation"; };
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,2 @@
+# Strings with context cannot be appended to paths
+./read-dir + "/${builtins.toFile "file" "content"}"
@@ -0,0 +1,2 @@
+# Only strings and paths can be appended to a string, numbers need toString
+"a" + 1
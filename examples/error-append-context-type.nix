@@ -0,0 +1 @@
+builtins.appendContext "x" 1
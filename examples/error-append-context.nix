@@ -0,0 +1 @@
+builtins.appendContext "" { "/nix/store/gggggggggggggggggggggggggggggggg-source" = { allOutputs = true; }; }
@@ -0,0 +1,2 @@
+# The failing assertion shows both sides of the comparison
+assert 1 == 2; true
@@ -0,0 +1 @@
+assert "yes"; true
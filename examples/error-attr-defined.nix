@@ -0,0 +1,2 @@
+# Only sets written in place can get more attributes
+{ a = 1; a.c = 2; }
@@ -0,0 +1,2 @@
+# Only functions and sets with `__functor` can be called
+1 2
@@ -0,0 +1,2 @@
+# Incomparable types
+1 > "a"
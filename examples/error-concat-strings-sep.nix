@@ -0,0 +1 @@
+builtins.concatStringsSep "," [ 1 2 ]
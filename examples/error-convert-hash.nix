@@ -0,0 +1 @@
+builtins.convertHash { hash = "ba7816bf8f01cfea"; hashAlgo = "sha256"; toHashFormat = "sri"; }
@@ -0,0 +1,4 @@
+let
+  x = { a = { b = x; }; };
+in
+builtins.deepSeq x true
@@ -0,0 +1,7 @@
+# Only `__structuredAttrs` lets the attributes be sets
+(derivation {
+  name = "flat";
+  system = "x86_64-linux";
+  builder = "/bin/sh";
+  nested = { };
+}).drvPath
@@ -0,0 +1,2 @@
+# Like Nix, dividing floats by zero is an error instead of nan
+0.0 / 0.0
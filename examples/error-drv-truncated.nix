@@ -0,0 +1,9 @@
+let
+  drv = builtins.unsafeDiscardStringContext (builtins.path { path = ./drv/truncated.drv; });
+in
+(derivation {
+  name = "user";
+  system = "x86_64-linux";
+  builder = "/bin/sh";
+  input = builtins.appendContext "" { ${drv} = { outputs = [ "out" ]; }; };
+}).drvPath
@@ -0,0 +1,5 @@
+# Attribute names can't refer to store paths, like the output of a derivation
+let
+  drv = derivation { name = "x"; builder = "/bin/sh"; system = "x86_64-linux"; };
+in
+{ ${toString drv} = 1; }
@@ -0,0 +1,2 @@
+# Attribute names are strings, numbers need toString
+{ ${1} = 2; }
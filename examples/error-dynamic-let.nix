@@ -0,0 +1,2 @@
+# Like Nix, the names of let bindings must be known without evaluating
+let ${"a"} = 1; in a
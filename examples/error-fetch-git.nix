@@ -0,0 +1,4 @@
+builtins.fetchGit {
+  url = ./fetch-git/repo.bundle;
+  ref = "missing";
+}
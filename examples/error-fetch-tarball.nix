@@ -0,0 +1,2 @@
+# Tarballs must have a single top-level entry
+builtins.fetchTarball "file://${toString ./fetch-tarball/multiple.tar.xz}"
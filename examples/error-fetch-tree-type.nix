@@ -0,0 +1 @@
+builtins.fetchTree 1
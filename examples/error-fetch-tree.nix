@@ -0,0 +1 @@
+builtins.fetchTree { type = "svn"; url = "svn://example.org/repo"; }
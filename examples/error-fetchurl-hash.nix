@@ -0,0 +1,4 @@
+builtins.fetchurl {
+  url = "file://${toString ./read-dir/file.txt}";
+  sha256 = "sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+}
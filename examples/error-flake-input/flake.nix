@@ -0,0 +1,9 @@
+{
+  description = "`flake metadata` and `flake show` fail on an input that isn't a set";
+
+  inputs = {
+    utils = "path:./utils";
+  };
+
+  outputs = { utils, ... }: { };
+}
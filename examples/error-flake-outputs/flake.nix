@@ -0,0 +1,5 @@
+{
+  description = "`flake show` fails when the outputs don't return a set";
+
+  outputs = { ... }: [ ];
+}
@@ -0,0 +1,2 @@
+# NaN and infinity have no integer value
+builtins.floor (builtins.fromTOML "x = nan").x
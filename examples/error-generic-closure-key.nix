@@ -0,0 +1 @@
+builtins.genericClosure { startSet = [ { } ]; operator = _: [ ]; }
@@ -0,0 +1,4 @@
+builtins.genericClosure {
+  startSet = [ { key = { }; } ];
+  operator = _: [ ];
+}
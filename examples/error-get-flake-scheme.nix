@@ -0,0 +1 @@
+builtins.getFlake "github:NixOS/nixpkgs"
@@ -0,0 +1,7 @@
+(derivation {
+  name = "git";
+  system = "x86_64-linux";
+  builder = "/bin/sh";
+  outputHashMode = "git";
+  outputHash = "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=";
+}).outPath
@@ -0,0 +1 @@
+builtins.hashString "sha3" ""
@@ -0,0 +1 @@
+if 1 then "one" else "other"
@@ -0,0 +1 @@
+"${builtins.map}"
@@ -0,0 +1,2 @@
+# Only toString coerces numbers, interpolation doesn't
+"version ${1}"
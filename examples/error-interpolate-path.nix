@@ -0,0 +1,2 @@
+# Interpolating into a path has the same rules as into a string
+./read-dir/${x: x}
@@ -0,0 +1,2 @@
+# Defaults that need each other are an infinite recursion
+({ a ? b, b ? a }: a) { }
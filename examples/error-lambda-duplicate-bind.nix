@@ -0,0 +1,2 @@
+# The `@` binding cannot have the name of a parameter
+(args@{ args }: args) { args = 1; }
@@ -0,0 +1 @@
+({ a, b }: a) { b = 1; }
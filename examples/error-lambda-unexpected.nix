@@ -0,0 +1,2 @@
+# Without `...`, every attribute must be a parameter
+({ a }: a) { a = 1; b = 2; c = 3; }
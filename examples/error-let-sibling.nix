@@ -0,0 +1,2 @@
+# Bindings of a let are not seen by the next one
+[ (let a = 1; in a) (let b = 2; in a) ]
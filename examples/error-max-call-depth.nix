@@ -0,0 +1,2 @@
+# Runaway recursions stop at the max-call-depth instead of overflowing the stack
+let f = n: if n == 0 then 0 else 1 + f (n - 1); in f 50000
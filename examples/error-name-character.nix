@@ -0,0 +1,5 @@
+(derivation {
+  name = "hello world";
+  system = "x86_64-linux";
+  builder = "/bin/sh";
+}).drvPath
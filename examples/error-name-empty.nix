@@ -0,0 +1,5 @@
+(derivation {
+  name = "";
+  system = "x86_64-linux";
+  builder = "/bin/sh";
+}).drvPath
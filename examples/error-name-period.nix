@@ -0,0 +1 @@
+builtins.toFile ".hidden" "content"
@@ -0,0 +1,5 @@
+# 212 characters, one more than the limit
+builtins.path {
+  path = ./read-dir;
+  name = builtins.concatStringsSep "" (builtins.genList (_: "a") 212);
+}
@@ -0,0 +1,2 @@
+# The rhs of a logical operator must be a bool too
+false || "x"
@@ -0,0 +1,2 @@
+# Negating is subtracting from zero, like in Nix
+-(-9223372036854775807 - 1)
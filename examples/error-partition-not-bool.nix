@@ -0,0 +1 @@
+builtins.partition (x: x) [ true 1 ]
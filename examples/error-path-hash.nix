@@ -0,0 +1,4 @@
+builtins.path {
+  path = ./read-dir;
+  sha256 = "sha256-AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=";
+}
@@ -0,0 +1 @@
+builtins.readDir ./does-not-exist
@@ -0,0 +1,2 @@
+# Nix strings cannot contain NUL bytes
+builtins.readFile ./read-file/nul.bin
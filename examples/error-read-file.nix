@@ -0,0 +1 @@
+builtins.readFile ./read-file/missing.txt
@@ -0,0 +1,2 @@
+# Values that depend on each other are an infinite recursion
+(rec { a = b; b = a; }).a
@@ -0,0 +1 @@
+builtins.split "(a" "abc"
@@ -0,0 +1 @@
+builtins.removeAttrs { a = 1; } [ 1 ]
@@ -0,0 +1,2 @@
+# NIX_PATH and -I have no entry for it
+<nonexistent-channel>
@@ -0,0 +1,2 @@
+# Without `or`, selecting in something else than a set is an error
+{ a = 1; }.a.b
@@ -0,0 +1 @@
+builtins.storePath "/nix/store/eoutvab4q9c7wbsvbfsvgaa15j9p6206-hello"
@@ -0,0 +1 @@
+builtins.stringLength 1
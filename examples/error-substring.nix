@@ -0,0 +1 @@
+builtins.substring (-1) 2 "nixos"
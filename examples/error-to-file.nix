@@ -0,0 +1,2 @@
+# Files added with toFile cannot depend on derivations
+builtins.toFile "script" (derivation { name = "hello"; system = "x86_64-linux"; builder = "/bin/sh"; }).outPath
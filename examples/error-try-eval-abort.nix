@@ -0,0 +1,2 @@
+# tryEval doesn't catch aborts
+builtins.tryEval (abort "x")
@@ -0,0 +1,2 @@
+# tryEval doesn't catch type errors either, like in Nix
+builtins.tryEval (1 + "a")
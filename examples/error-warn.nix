@@ -0,0 +1 @@
+builtins.warn { message = "not a string"; } true
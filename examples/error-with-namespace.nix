@@ -0,0 +1 @@
+with 1; 2
@@ -0,0 +1,35 @@
+# fetch-git/repo.bundle is a two commit repository, see `git bundle list-heads`
+let
+  latest = builtins.fetchGit ./fetch-git/repo.bundle;
+
+  first = builtins.fetchGit {
+    url = ./fetch-git/repo.bundle;
+    rev = "cc601302ed9f1e2fb9fcabf144ccba7b8ce8de74";
+  };
+
+  shallow = builtins.fetchGit {
+    url = ./fetch-git/repo.bundle;
+    ref = "main";
+    shallow = true;
+  };
+in
+assert latest.rev == "7f77dcd4ba623c19b1ff6b86d42629d5e4f00dc5";
+assert latest.shortRev == "7f77dcd";
+assert latest.revCount == 2;
+assert latest.lastModified == 1706788800;
+assert latest.lastModifiedDate == "20240201120000";
+assert latest.submodules == false;
+assert (import latest.outPath).version == "1.1";
+assert builtins.substring 0 7 (builtins.readFile "${latest.outPath}/README") == "Fixture";
+
+assert first.revCount == 1;
+assert first.lastModified == 1704067200;
+assert (import first.outPath).version == "1.0";
+assert !builtins.pathExists "${first.outPath}/README";
+assert first.outPath != latest.outPath;
+
+assert shallow.rev == latest.rev;
+assert shallow.narHash == latest.narHash;
+assert !shallow ? revCount;
+
+true
@@ -0,0 +1,25 @@
+# Test `builtins.fetchTarball` with a local `file://` URL
+#@@@
+# true
+
+let
+  url = "file://${toString ./fetch-tarball/single.tar.gz}";
+  sha256 = "sha256-QfwLkHrD5rZKXljbW0Z//FDaPKU3IfYDpMlOiILsx/E=";
+
+  source = builtins.fetchTarball url;
+  pinned = builtins.fetchTarball { inherit url sha256; };
+in
+
+# The top-level directory is stripped, `sha256` is the hash of the tree
+assert pinned == source;
+assert builtins.fetchTarball { inherit url sha256; name = "project"; } != source;
+assert builtins.match "/nix/store/.*-source" source != null;
+assert builtins.hasContext source;
+
+# `import (fetchTarball ...) { }` pinning
+assert (import source { }).message == "hello from a tarball";
+assert (import pinned { greeting = "hi"; }).message == "hi from a tarball";
+assert builtins.readDir "${source}/bin" == { run = "regular"; };
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,29 @@
+# Test `builtins.fetchTree`
+#@@@
+# true
+
+let
+  source = builtins.fetchTree { type = "path"; path = ./read-dir; };
+  pinned = builtins.fetchTree {
+    type = "path";
+    path = ./read-dir;
+    narHash = "sha256-tcADtxXke/JJCErZloH6HfynEQ7D8i5taNyNiWQapCE=";
+    lastModified = 1700000000;
+  };
+in
+
+assert builtins ? fetchTree;
+
+# Path inputs work without network
+assert source.outPath == ./read-dir;
+assert source.narHash == "sha256-tcADtxXke/JJCErZloH6HfynEQ7D8i5taNyNiWQapCE=";
+assert builtins.isInt source.lastModified;
+assert builtins.stringLength source.lastModifiedDate == 14;
+assert builtins.readFile "${source.outPath}/file.txt" == builtins.readFile ./read-dir/file.txt;
+
+# Given attributes are checked or kept
+assert pinned.lastModified == 1700000000;
+assert pinned.lastModifiedDate == "20231114221320";
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,25 @@
+# Test `builtins.fetchurl` with a local `file://` URL, the same works over HTTP(S)
+#@@@
+# true
+
+let
+  url = "file://${toString ./read-dir/file.txt}";
+  sha256 = "5891b5b522d5df086d0ff0b110fbd9d21bb4fc7163af34d08286a2e846f6be03";
+
+  plain = builtins.fetchurl url;
+  pinned = builtins.fetchurl { inherit url sha256; };
+in
+
+# A flat fixed-output path named after the URL
+assert plain == "/nix/store/z1rahp9fy11h8yvihxhgj8vr2pb6db1a-file.txt";
+assert builtins.getContext plain == { ${builtins.unsafeDiscardStringContext plain} = { path = true; }; };
+
+# The hash can be given in any form, the path only depends on the content
+assert pinned == plain;
+assert builtins.fetchurl { inherit url; sha256 = "sha256-WJG1tSLV3whtD/CxEPvZ0hu0/HFjrzTQgoai6Eb2vgM="; } == plain;
+assert builtins.fetchurl { inherit url sha256; name = "renamed"; } != plain;
+
+assert builtins.readFile pinned == builtins.readFile ./read-dir/file.txt;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,37 @@
+# Test `builtins.path` and `builtins.filterSource`
+#@@@
+# true
+
+let
+  types = {
+    dangling = "symlink";
+    "file.txt" = "regular";
+    subdir = "directory";
+    ".keep" = "regular";
+  };
+
+  whole = builtins.path { path = ./read-dir; };
+  noDirectories = builtins.filterSource (path: type: type != "directory") ./read-dir;
+in
+
+# Without a filter it's the same as copying the path
+assert whole == "${./read-dir}";
+assert builtins.getContext whole == { ${builtins.unsafeDiscardStringContext whole} = { path = true; }; };
+
+# Filtering out a subdirectory changes the hash
+assert noDirectories == "/nix/store/kks6fipbfm15bxhw96ghxbsb47fib021-read-dir";
+assert noDirectories != whole;
+
+# The filter gets the type of every entry, if one was wrong the entry would be
+# filtered out and the hash would change
+assert builtins.filterSource (path: type: types.${baseNameOf path} == type) ./read-dir == whole;
+
+# `name` and `sha256`
+assert builtins.path { path = ./read-dir; name = "renamed"; sha256 = "sha256-tcADtxXke/JJCErZloH6HfynEQ7D8i5taNyNiWQapCE="; }
+  == "/nix/store/g9ymcjm560n5dbckjbmvsvyvvl1vk2i3-renamed";
+
+# Reading the result goes to the original files
+assert builtins.readFile "${whole}/file.txt" == builtins.readFile ./read-dir/file.txt;
+
+# If everything is ok, then return true
+true
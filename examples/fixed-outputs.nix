@@ -0,0 +1,42 @@
+# Test the paths of fixed outputs for every ingestion method and algorithm
+#@@@
+# true
+
+let
+  fixed = name: outputHashMode: outputHashAlgo: outputHash: derivation {
+    inherit name outputHashMode outputHashAlgo outputHash;
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+  };
+
+  # Of the empty file
+  md5 = "d41d8cd98f00b204e9800998ecf8427e";
+  sha1 = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+  sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+  sha512 = "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e";
+
+  flatMd5 = fixed "flat-md5" "flat" "md5" md5;
+  flatSha256 = fixed "flat-sha256" "flat" "sha256" sha256;
+  gitSha1 = fixed "git-sha1" "git" "sha1" sha1;
+  narSha1 = fixed "nar-sha1" "recursive" "sha1" sha1;
+  narSha512 = fixed "nar-sha512" "recursive" "sha512" sha512;
+in
+
+# Everything but NAR SHA-256 is hashed from `fixed:out:<method><algo>:<hash>:`
+assert flatMd5.drvPath == "/nix/store/gj7bgmzgjvy5bxv6770qik9c3vg4nk54-flat-md5.drv";
+assert flatMd5.outPath == "/nix/store/ykwpk96mbzdp28kf8w0ps4yqy2i1r56v-flat-md5";
+assert flatSha256.drvPath == "/nix/store/7dz7h6120annm7nryknq1zhdanwbbgmm-flat-sha256.drv";
+assert flatSha256.outPath == "/nix/store/lg724jggrrh3bb0fd34ma56dpl6i3g2j-flat-sha256";
+assert gitSha1.drvPath == "/nix/store/25jcnsdrv2v1b8c7xdi6q8a91hfiynw2-git-sha1.drv";
+assert gitSha1.outPath == "/nix/store/rinhj6zwwa2ffvsyngqnvn6cjq9xdlp5-git-sha1";
+assert narSha1.drvPath == "/nix/store/3il90afapijnq8qy76p6r8zvaxgdkxcq-nar-sha1.drv";
+assert narSha1.outPath == "/nix/store/f8h07rza9ywiyqa5isqm7r8ski7hq666-nar-sha1";
+assert narSha512.drvPath == "/nix/store/510n35pjicprz0gjanf2ggwh4sz28awl-nar-sha512.drv";
+assert narSha512.outPath == "/nix/store/57xn8cb2jbaldg63pbv0isf2481swwj2-nar-sha512";
+
+# The hash can be written in any encoding, or as SRI without an algorithm
+assert (fixed "flat-md5" "flat" "md5" (builtins.convertHash { hash = md5; hashAlgo = "md5"; toHashFormat = "nix32"; })).outPath == flatMd5.outPath;
+assert (fixed "flat-sha256" "flat" "" "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=").outPath == flatSha256.outPath;
+
+# If everything is ok, then return true
+true
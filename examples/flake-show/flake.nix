@@ -0,0 +1,23 @@
+{
+  description = "Fixture for `nix-compiler flake metadata` and `flake show`";
+
+  inputs = {
+    utils.url = "path:./utils";
+    utils.path = ./utils;
+  };
+
+  outputs = { utils, ... }: let
+    system = "x86_64-linux";
+
+    mkPackage = name: derivation { inherit name system; builder = "/bin/sh"; };
+  in {
+    packages.${system} = {
+      hello = mkPackage "hello-2.12";
+      broken = { }.missing;
+    };
+
+    devShells.${system}.default = mkPackage "nix-shell";
+
+    lib = utils.outputs.lib;
+  };
+}
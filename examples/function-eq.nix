@@ -0,0 +1,40 @@
+# Test `==` on functions, it's false unless both sides are the same value
+#@@@
+# true
+
+let
+  f = x: x;
+  g = x: x;
+  makeId = _: x: x;
+
+  add = a: b: a + b;
+  inc = add 1;
+
+  mapId = builtins.map f;
+in
+
+# The same function is equal to itself, even through other values
+assert f == f;
+assert inc == inc;
+assert mapId == mapId;
+assert { a = f; } == { a = f; };
+
+# Distinct functions are not, even with the same body
+assert !(f == g);
+assert !((x: x) == (x: x));
+assert !(makeId 1 == makeId 2);
+assert !(add 1 == add 1);
+assert f != g;
+
+# Builtins are equal by name, partial applications are not
+assert builtins.map == builtins.map;
+assert map == builtins.map;
+assert !(builtins.map == builtins.filter);
+assert !(builtins.map f == builtins.map f);
+assert !(builtins.map f == builtins.map g);
+
+# Functions are never equal to other types
+assert !(f == null);
+assert !(builtins.map == "map");
+
+true
@@ -0,0 +1,18 @@
+# Test calling sets with `__functor`, they get themselves as first argument
+#@@@
+# true
+
+let
+  add = { __functor = self: x: self.n + x; n = 1; };
+  countdown = { __functor = self: x: if x == 0 then 0 else 1 + self (x - 1); };
+  # `__functor` can return another callable set
+  nested = { __functor = self: add; };
+in
+
+assert add 2 == 3;
+assert (add // { n = 10; }) 2 == 12;
+assert countdown 5 == 5;
+assert nested 2 == 3;
+
+# If everything is ok, then return true
+true
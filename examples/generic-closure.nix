@@ -0,0 +1,53 @@
+# Test `builtins.genericClosure`
+#@@@
+# true
+
+let
+  keys = list: map (item: item.key) list;
+
+  listEq = a: b:
+    builtins.length a == builtins.length b
+    && builtins.all (idx: builtins.elemAt a idx == builtins.elemAt b idx)
+      (builtins.genList (idx: idx) (builtins.length a));
+
+  # Store path like keys, each one refers to the next ones
+  references = {
+    a = [ "b" "c" ];
+    b = [ "c" ];
+    c = [ "d" ];
+    d = [ ];
+  };
+
+  strings = builtins.genericClosure {
+    startSet = [ { key = "a"; } ];
+    operator = item: map (key: { inherit key; }) references.${item.key};
+  };
+
+  # New elements for several generations, up to 10
+  generations = builtins.genericClosure {
+    startSet = [ { key = 1; } ];
+    operator = item: if item.key >= 10 then [ ] else [ { key = item.key + 1; } { key = item.key; } ];
+  };
+
+  numbers = builtins.genericClosure {
+    startSet = [ { key = 1; } { key = 1.0; } { key = 2.0; } { key = 2; } ];
+    operator = _: [ ];
+  };
+
+  lists = builtins.genericClosure {
+    startSet = [ { key = [ 1 "a" ]; } { key = [ 1.0 "a" ]; } { key = [ 1 "b" ]; } ];
+    operator = _: [ ];
+  };
+in
+
+assert listEq (keys strings) [ "a" "b" "c" "d" ];
+assert listEq (keys generations) (builtins.genList (idx: idx + 1) 10);
+
+# `1` and `1.0` are the same key, the first one is kept
+assert builtins.length numbers == 2;
+assert builtins.isInt (builtins.elemAt numbers 0).key;
+assert builtins.isFloat (builtins.elemAt numbers 1).key;
+assert builtins.length lists == 2;
+
+# If everything is ok, then return true
+true
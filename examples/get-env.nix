@@ -0,0 +1,13 @@
+# Test `builtins.getEnv`, with --pure-eval every variable is empty
+#@@@
+# true
+
+# Unset variables are empty, not an error
+assert builtins.getEnv "NIX_COMPILER_UNSET_VARIABLE" == "";
+
+# Set ones are read as they are
+assert builtins.getEnv "PATH" != "";
+assert builtins.isString (builtins.getEnv "PATH");
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,13 @@
+let
+  flake = builtins.getFlake ("path:" + toString ./get-flake);
+  absolute = builtins.getFlake (toString ./get-flake);
+in
+assert flake._type == "flake";
+assert flake.lib.answer == 42;
+assert flake.lib.double 21 == 42;
+assert flake.outputs.lib.answer == 42;
+assert flake.outPath == flake.sourceInfo.outPath;
+assert flake.narHash == flake.sourceInfo.narHash;
+assert absolute.narHash == flake.narHash;
+
+true
@@ -0,0 +1,6 @@
+{
+  outputs = { self }: {
+    lib.answer = 42;
+    lib.double = x: x * 2;
+  };
+}
@@ -0,0 +1,32 @@
+# Test the `?` operator
+#@@@
+# true
+
+let
+  name = "b";
+  set = { a = { b = 1; c = throw "c"; }; d = throw "d"; e = 1; };
+in
+
+# The last attribute is never forced
+assert { a = throw "x"; } ? a;
+assert set ? d;
+assert set ? a.c;
+
+# A step that isn't a set is false
+assert !({ a = 1; } ? a.b);
+assert !(set ? e.f.g);
+assert !(1 ? a);
+assert !(null ? a);
+
+# Dynamic attributes
+assert !({ } ? ${"a" + "b"});
+assert { ab = 1; } ? ${"a" + "b"};
+assert set ? a.${name};
+assert set ? "a".${name};
+assert !(set ? a.${name}.c);
+
+# Errors of the steps aren't hidden
+assert !(builtins.tryEval (set ? d.x)).success;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,20 @@
+# Test hashString and hashFile against known digests
+#@@@
+# true
+
+# Empty string
+assert builtins.hashString "md5" "" == "d41d8cd98f00b204e9800998ecf8427e";
+assert builtins.hashString "sha1" "" == "da39a3ee5e6b4b0d3255bfef95601890afd80709";
+assert builtins.hashString "sha256" "" == "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+assert builtins.hashString "sha512" "" == "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e";
+
+# "abc"
+assert builtins.hashString "md5" "abc" == "900150983cd24fb0d6963f7d28e17f72";
+assert builtins.hashString "sha1" "abc" == "a9993e364706816aba3e25717850c26c9cd0d89d";
+assert builtins.hashString "sha256" "abc" == "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+assert builtins.hashString "sha512" "abc" == "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f";
+
+# hashFile hashes the contents
+assert builtins.hashFile "sha256" ./read-dir/file.txt == builtins.hashString "sha256" (builtins.readFile ./read-dir/file.txt);
+
+true
@@ -0,0 +1,15 @@
+# Test `~/` paths, relative to $HOME
+#@@@
+# true
+
+let
+  home = builtins.getEnv "HOME";
+in
+
+assert ~/file == /. + "${home}/file";
+assert ~/a/b.nix == /. + "${home}/a/b.nix";
+assert ~/a/${"b"} == /. + "${home}/a/b";
+assert builtins.isPath ~/file;
+
+# If everything is ok, then return true
+true
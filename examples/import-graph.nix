@@ -0,0 +1,13 @@
+# Test the edges recorded by `--import-graph <output>`
+#@@@
+# import-graph.nix:7:14 -> import-graph/a.nix
+# import-graph/a.nix:3:14 -> import-graph/b.nix
+# import-graph.nix:8:14 -> import-graph/b.nix
+let
+  a = import ./import-graph/a.nix;
+  b = import ./import-graph/b.nix;
+in
+assert a.a == "a";
+assert a.b == "b";
+assert b == "b";
+true
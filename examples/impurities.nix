@@ -0,0 +1,16 @@
+# Test `--print-impurities`, the report is in `impurities.txt`
+#@@@
+# true
+
+let
+  home = builtins.getEnv "HOME";
+  hostname = builtins.readFile /etc/hostname;
+  local = builtins.readFile ./impurities.txt;
+in
+
+assert builtins.isString home;
+assert builtins.isString hostname;
+assert builtins.isString local;
+
+# If everything is ok, then return true
+true
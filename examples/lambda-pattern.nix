@@ -0,0 +1,19 @@
+# Test pattern parameters, defaults, `...` and `@` bindings
+#@@@
+# true
+
+assert ({ a, b ? 2 }: a + b) { a = 1; } == 3;
+assert ({ a, b ? 2 }: a + b) { a = 1; b = 3; } == 4;
+assert ({ a, ... }: a) { a = 1; b = 2; c = 3; } == 1;
+assert ({ a, ... }@args: args.b) { a = 1; b = 2; } == 2;
+assert (args@{ a ? args.b, b }: a) { b = 4; } == 4;
+
+# Defaults see the other parameters, the `@` binding is the argument as given
+assert ({ a, b ? a * 2 }: b) { a = 3; } == 6;
+assert ({ a ? b + 1, b ? 1 }: a) { } == 2;
+assert (args@{ x ? args.y, y ? 1 }: x) { y = 5; } == 5;
+assert (args@{ x ? 1 }: args) { } == { };
+assert (args@{ x ? args.y or 0, y ? 1 }: x) { } == 0;
+
+# If everything is ok, then return true
+true
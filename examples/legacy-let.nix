@@ -0,0 +1,18 @@
+# Test the legacy `let { }`, a recursive set that evaluates to its `body`
+#@@@
+# true
+
+let
+  outer = 10;
+in
+
+assert (let { body = a + 1; a = 2; }) == 3;
+assert (let { body = { inherit a b; }; a = b * 2; b = outer; }) == { a = 20; b = 10; };
+assert (let { x.y = 1; x.z = 2; body = x; }) == { y = 1; z = 2; };
+assert (let { body = f 2; f = n: n * n; }) == 4;
+
+# `body` is lazy like any other attribute
+assert (let { body = 1; unused = throw "not forced"; }) == 1;
+
+# If everything is ok, then return true
+true
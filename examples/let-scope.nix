@@ -0,0 +1,27 @@
+# Test the scope of let bindings
+#@@@
+# true
+
+let
+  attrs = { x = 1; z = 3; };
+  x = "outer";
+in
+
+# Inner lets shadow outer ones
+assert (let x = 1; in let x = 2; in x) == 2;
+assert (let x = 2; in x) == 2 && x == "outer";
+
+# Bindings don't leak into sibling expressions
+assert [ (let a = 1; in a) (let b = 2; in b) ] == [ 1 2 ];
+assert [ (let a = 1; in a) (let a = 2; in a) ] == [ 1 2 ];
+
+# `inherit (e)` sees the other bindings of the same let
+assert (let inherit (attrs) x; y = x + 1; in y) == 2;
+assert (let inherit (s) z; s = attrs; in z) == 3;
+assert (let inherit (attrs) x z; in x + z) == 4;
+
+# Bindings see each other, whatever the order
+assert (let a = b + 1; b = 1; in a) == 2;
+
+# If everything is ok, then return true
+true
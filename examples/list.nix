@@ -9,5 +9,19 @@ assert builtins.elem 2 [1 2 3];
 assert builtins.elem 3 [1 2 3];
 assert !(builtins.elem 4 [1 2 3]); 
 
+# partition
+assert (builtins.partition (x: 2 < x) [ ]).right == [ ];
+assert (builtins.partition (x: 2 < x) [ ]).wrong == [ ];
+assert (builtins.partition (x: 2 < x) [ 1 3 2 4 ]).right == [ 3 4 ];
+assert (builtins.partition (x: 2 < x) [ 1 3 2 4 ]).wrong == [ 1 2 ];
+assert builtins.length (builtins.partition (x: true) [ (throw "partition must not force the elements") ]).right == 1;
+
+# groupBy
+assert builtins.groupBy (x: x) [ ] == { };
+assert builtins.attrNames (builtins.groupBy (x: "all") [ 1 2 3 ]) == [ "all" ];
+assert (builtins.groupBy (x: "all") [ 1 2 3 ]).all == [ 1 2 3 ];
+assert (builtins.groupBy (x: if 2 < x then "big" else "small") [ 3 1 4 2 5 ]).big == [ 3 4 5 ];
+assert (builtins.groupBy (x: if 2 < x then "big" else "small") [ 3 1 4 2 5 ]).small == [ 1 2 ];
+
 # If everything is ok, then return true
 true
@@ -0,0 +1,35 @@
+# Test dynamic attributes with a `null` key
+#@@@
+# true
+
+let
+  optionalAttrs = cond: set: if cond then set else { };
+  withName = cond: { ${if cond then "name" else null} = "value"; };
+in
+
+# Definitions with a `null` key are skipped
+assert { ${null} = 1; } == { };
+assert { ${null} = 1; a = 2; } == { a = 2; };
+assert { ${null}.a = 1; } == { };
+assert { a.${null} = 1; } == { a = { }; };
+assert rec { ${null} = 1; b = 2; } == { b = 2; };
+
+# Other keys are any string, even the empty one
+assert { "" = 1; } ? "";
+assert { "" = 1; }."" == 1;
+assert { ${""} = 1; } == { "" = 1; };
+assert builtins.attrNames { "" = 0; } == [ "" ];
+assert builtins.attrNames { ${null} = 0; "" = 1; } == [ "" ];
+assert { ${"a" + "b"} = 1; }.ab == 1;
+assert { a = 1; }.${"a"} == 1;
+assert { ${"a"}.${"b"} = 1; } == { a = { b = 1; }; };
+assert (let a.${"b"} = 1; in a) == { b = 1; };
+
+# The `optionalAttrs`-like patterns
+assert withName true == { name = "value"; };
+assert withName false == { };
+assert optionalAttrs false { a = 1; } // { b = 2; } == { b = 2; };
+assert { ${if false then "a" else null} = throw "must not be forced"; } == { };
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,41 @@
+# Test `or` used as an attribute name
+#@@@
+# true
+
+let
+  or = "variable";
+  id = x: x;
+  set = { or = 1; a.or = 2; };
+in
+
+# Definition and selection
+assert { or = 1; }.or == 1;
+assert set.a.or == 2;
+assert { or.b = 3; }.or.b == 3;
+assert { "or" = 4; }.or == 4;
+assert set.${"or"} == 1;
+
+# Inherit
+assert { inherit or; }.or == "variable";
+assert { inherit (set) or; }.or == 1;
+
+# Has attribute
+assert set ? or;
+assert set ? a.or;
+assert !({ } ? or);
+
+# `or` after a selection is still the default value
+assert set.b or 5 == 5;
+assert set.or or 5 == 1;
+assert set.a.b or 6 == 6;
+
+# Any missing attribute or value that isn't a set gives the default
+assert { }.a.b.c or 3 == 3;
+assert (1).a or 4 == 4;
+assert set.or.b or 7 == 7;
+
+# `f or` applies `f` to the variable `or`
+assert id or == "variable";
+
+# If everything is ok, then return true
+true
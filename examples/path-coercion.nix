@@ -0,0 +1,42 @@
+# Test coercing paths to strings, some coercions copy them into the store
+#@@@
+# true
+
+let
+  file = "/nix/store/qjqcrhqkjmdgv3yqcgcd9fa7qi234hga-file.txt";
+  dir = "/nix/store/sc2ldww4wykfy6vn3jx7g3w187p3g4ha-read-dir";
+in
+
+# Interpolation and appending to a string copy the path
+assert "${./read-dir/file.txt}" == file;
+assert "${./read-dir}" == dir;
+assert "" + ./read-dir == dir;
+assert builtins.getContext "${./read-dir}" == { ${dir} = { path = true; }; };
+
+# The copy is what gets read
+assert builtins.readFile "${./read-dir}/file.txt" == builtins.readFile ./read-dir/file.txt;
+
+# The copy is referenced by files that mention it
+assert builtins.getContext (builtins.toFile "script" "cat ${./read-dir/file.txt}") != { };
+
+# Other coercions keep the local path
+assert builtins.substring 0 1 (toString ./read-dir) == "/";
+assert !(builtins.hasContext (toString ./read-dir));
+assert builtins.isPath (./read-dir + "/file.txt");
+
+# Interpolating into a path keeps the local path too
+assert ./read-dir/${"file.txt"} == ./read-dir/file.txt;
+assert ./read-dir/${{ outPath = "file.txt"; }} == ./read-dir/file.txt;
+
+# Sets are coerced through `__toString` or `outPath`
+assert "${{ outPath = "/x"; }}" == "/x";
+assert "${{ __toString = self: "y"; }}" == "y";
+
+# `+` keeps the type of its left side, a path plus a path is still a path
+assert ./read-dir + "/file.txt" == ./read-dir/file.txt;
+assert ./read-dir + ./file.txt == ./read-dir + (toString ./file.txt);
+assert builtins.isPath (./read-dir + ./file.txt);
+assert builtins.isString ("" + ./read-dir);
+
+# If everything is ok, then return true
+true
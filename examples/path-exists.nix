@@ -0,0 +1,19 @@
+# Test `builtins.pathExists`
+#@@@
+# true
+
+assert builtins.pathExists ./read-dir/file.txt;
+assert !(builtins.pathExists ./read-dir/missing.txt);
+
+# Dangling symlinks exist
+assert builtins.pathExists ./read-dir/dangling;
+
+# A trailing slash means a directory
+assert builtins.pathExists "${toString ./read-dir}/";
+assert builtins.pathExists "${toString ./read-dir}/.";
+assert !(builtins.pathExists "${toString ./read-dir/file.txt}/");
+
+assert import ./path-exists/relative.nix;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,2 @@
+# Relative strings are relative to this file
+builtins.pathExists "./relative.nix" && !(builtins.pathExists "./path-exists.nix")
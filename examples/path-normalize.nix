@@ -0,0 +1,35 @@
+# Test that path literals are normalized without touching the filesystem
+#@@@
+# true
+
+let
+  here = toString ./.;
+  parent = builtins.dirOf here;
+  sub = "a/../b";
+in
+
+# Any number of leading `..`
+assert toString ../x == "${parent}/x";
+assert toString ../../x == "${builtins.dirOf parent}/x";
+assert toString ../. == parent;
+assert toString ./.. == parent;
+
+# Interior `.` and `..`, even of directories that don't exist
+assert toString ./a/../b == "${here}/b";
+assert toString ./a/./b == "${here}/a/b";
+assert toString ./a/b/../../c == "${here}/c";
+assert toString ./read-dir/../read-dir/file.txt == "${here}/read-dir/file.txt";
+assert builtins.readFile ./read-dir/../read-dir/file.txt == builtins.readFile ./read-dir/file.txt;
+
+# Interpolated segments are normalized too, with the slashes they bring
+assert toString ./x/${sub} == "${here}/x/b";
+assert toString ./x/${"/y"} == "${here}/x/y";
+assert toString ./x/${"y/"}z == "${here}/x/y/z";
+
+# `..` doesn't go above the root
+assert toString /../.. == "/";
+assert toString /a/../../b == "/b";
+assert /a + "/../b" == /b;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,18 @@
+# Test `builtins.readDir`
+#@@@
+# true
+
+let
+  entries = builtins.readDir ./read-dir;
+in
+
+assert entries == {
+  dangling = "symlink";
+  "file.txt" = "regular";
+  subdir = "directory";
+};
+
+assert builtins.readDir ./read-dir/subdir == { ".keep" = "regular"; };
+
+# If everything is ok, then return true
+true
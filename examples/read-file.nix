@@ -0,0 +1,16 @@
+# Test `builtins.readFile` and `builtins.readFileType`
+#@@@
+# true
+
+# Symlinks aren't followed
+assert builtins.readFileType ./read-file/link == "symlink";
+assert builtins.readFileType ./read-dir/dangling == "symlink";
+assert builtins.readFileType ./read-dir/file.txt == "regular";
+assert builtins.readFileType ./read-dir == "directory";
+
+# readFile does follow them
+assert builtins.readFile ./read-file/link == builtins.readFile ./read-dir/file.txt;
+assert builtins.hashFile "sha256" ./read-file/link == builtins.hashString "sha256" (builtins.readFile ./read-dir/file.txt);
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,120 @@
+# Test recursive attribute sets, bindings see every sibling whatever the order
+#@@@
+# true
+
+let
+  # Each key depends on the next one, defined after it
+  chain = rec {
+    k0 = k1 + 1;
+    k1 = k2 + 1;
+    k2 = k3 + 1;
+    k3 = k4 + 1;
+    k4 = k5 + 1;
+    k5 = k6 + 1;
+    k6 = k7 + 1;
+    k7 = k8 + 1;
+    k8 = k9 + 1;
+    k9 = k10 + 1;
+    k10 = k11 + 1;
+    k11 = k12 + 1;
+    k12 = k13 + 1;
+    k13 = k14 + 1;
+    k14 = k15 + 1;
+    k15 = k16 + 1;
+    k16 = k17 + 1;
+    k17 = k18 + 1;
+    k18 = k19 + 1;
+    k19 = k20 + 1;
+    k20 = k21 + 1;
+    k21 = k22 + 1;
+    k22 = k23 + 1;
+    k23 = k24 + 1;
+    k24 = k25 + 1;
+    k25 = k26 + 1;
+    k26 = k27 + 1;
+    k27 = k28 + 1;
+    k28 = k29 + 1;
+    k29 = k30 + 1;
+    k30 = k31 + 1;
+    k31 = k32 + 1;
+    k32 = k33 + 1;
+    k33 = k34 + 1;
+    k34 = k35 + 1;
+    k35 = k36 + 1;
+    k36 = k37 + 1;
+    k37 = k38 + 1;
+    k38 = k39 + 1;
+    k39 = k40 + 1;
+    k40 = k41 + 1;
+    k41 = k42 + 1;
+    k42 = k43 + 1;
+    k43 = k44 + 1;
+    k44 = k45 + 1;
+    k45 = k46 + 1;
+    k46 = k47 + 1;
+    k47 = k48 + 1;
+    k48 = k49 + 1;
+    k49 = k50 + 1;
+    k50 = k51 + 1;
+    k51 = k52 + 1;
+    k52 = k53 + 1;
+    k53 = k54 + 1;
+    k54 = k55 + 1;
+    k55 = k56 + 1;
+    k56 = k57 + 1;
+    k57 = k58 + 1;
+    k58 = k59 + 1;
+    k59 = k60 + 1;
+    k60 = k61 + 1;
+    k61 = k62 + 1;
+    k62 = k63 + 1;
+    k63 = k64 + 1;
+    k64 = k65 + 1;
+    k65 = k66 + 1;
+    k66 = k67 + 1;
+    k67 = k68 + 1;
+    k68 = k69 + 1;
+    k69 = k70 + 1;
+    k70 = k71 + 1;
+    k71 = k72 + 1;
+    k72 = k73 + 1;
+    k73 = k74 + 1;
+    k74 = k75 + 1;
+    k75 = k76 + 1;
+    k76 = k77 + 1;
+    k77 = k78 + 1;
+    k78 = k79 + 1;
+    k79 = k80 + 1;
+    k80 = k81 + 1;
+    k81 = k82 + 1;
+    k82 = k83 + 1;
+    k83 = k84 + 1;
+    k84 = k85 + 1;
+    k85 = k86 + 1;
+    k86 = k87 + 1;
+    k87 = k88 + 1;
+    k88 = k89 + 1;
+    k89 = k90 + 1;
+    k90 = k91 + 1;
+    k91 = k92 + 1;
+    k92 = k93 + 1;
+    k93 = k94 + 1;
+    k94 = k95 + 1;
+    k95 = k96 + 1;
+    k96 = k97 + 1;
+    k97 = k98 + 1;
+    k98 = k99 + 1;
+    k99 = 0;
+  };
+in
+
+assert (rec { b = a + 1; a = 1; }).b == 2;
+assert chain.k0 == 99 && chain.k50 == 49;
+
+# `inherit (expr)` and dynamic names can use siblings
+assert (rec { inherit (b) c; b = { c = a; }; a = 1; }).c == 1;
+assert (rec { ${name} = 1; name = "x"; }).x == 1;
+assert (rec { a.${name} = 1; name = "x"; }).a.x == 1;
+
+# If everything is ok, then return true
+true
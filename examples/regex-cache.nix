@@ -0,0 +1,22 @@
+# Test using the same regular expressions many times, they are compiled once
+# and then taken from the cache. With `--trace-verbose` every compilation is
+# traced, this prints only two of them.
+#@@@
+# true
+
+let
+  lines = builtins.genList (i: "key${toString i} = ${toString (i * 2)}") 200;
+
+  matched = map (builtins.match "key([0-9]+) = ([0-9]+)") lines;
+  split = map (builtins.split " = ") lines;
+in
+
+assert builtins.length matched == 200;
+assert builtins.elemAt matched 0 == [ "0" "0" ];
+assert builtins.elemAt matched 199 == [ "199" "398" ];
+assert builtins.all (m: m != null) matched;
+
+assert builtins.all (parts: builtins.length parts == 3) split;
+assert builtins.elemAt split 42 == [ "key42" [ ] "84" ];
+
+true
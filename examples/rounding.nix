@@ -0,0 +1,21 @@
+# Test ceil and floor, integers are returned unchanged
+#@@@
+# true
+
+assert builtins.floor 1.9 == 1;
+assert builtins.floor (-1.1) == -2;
+assert builtins.floor 2 == 2;
+assert builtins.floor (-2) == -2;
+
+assert builtins.ceil 1.1 == 2;
+assert builtins.ceil (-1.1) == -1;
+assert builtins.ceil 2 == 2;
+assert builtins.ceil 2.0 == 2;
+
+assert builtins.isInt (builtins.floor 1.5);
+assert builtins.isInt (builtins.ceil 1.5);
+
+# The lowest integer is still in range
+assert builtins.floor (-9223372036854775808.0) == -9223372036854775807 - 1;
+
+true
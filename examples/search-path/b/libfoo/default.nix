@@ -0,0 +1 @@
+"libfoo"
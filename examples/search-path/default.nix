@@ -0,0 +1,15 @@
+# NIX_PATH=lib=examples/search-path/b/lib:examples/search-path/b \
+#   nix-compiler -I lib=examples/search-path/a/lib examples/search-path/default.nix
+let
+  prefixes = map (entry: entry.prefix) builtins.nixPath;
+in
+# -I entries come before NIX_PATH
+assert import <lib/file.nix> == "a";
+# the first entry that has the file wins
+assert import <lib/extra.nix> == "b-only";
+# `lib` is a prefix of components, not of characters
+assert import <libfoo> == "libfoo";
+assert builtins.elemAt prefixes 0 == "lib";
+assert builtins.elemAt prefixes 1 == "lib";
+assert builtins.elemAt prefixes 2 == "";
+true
@@ -0,0 +1,18 @@
+# Test reading store paths of a store directory deeper than /nix/store
+#@@@
+# true
+
+let
+  source = builtins.path { path = ./unsafe-get-attr-pos; name = "source"; };
+in
+
+assert builtins.storeDir == "/tmp/nix-compiler/deep/store";
+assert builtins.substring 0 29 source == "/tmp/nix-compiler/deep/store/";
+
+# Files added to the store can be read back
+assert builtins.readFile (builtins.toFile "hello" "hello world") == "hello world";
+
+# And so can the files inside a directory
+assert builtins.readFile "${source}/set.nix" == builtins.readFile ./unsafe-get-attr-pos/set.nix;
+
+true
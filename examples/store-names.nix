@@ -0,0 +1,17 @@
+# Test the names allowed in the store
+#@@@
+# true
+
+let
+  # The longest name allowed
+  long = builtins.concatStringsSep "" (builtins.genList (_: "a") 211);
+
+  nameOf = path: builtins.substring 44 (builtins.stringLength path) path;
+in
+
+assert nameOf (builtins.toFile long "") == long;
+assert nameOf (builtins.toFile "a+-._?=Z9" "") == "a+-._?=Z9";
+assert nameOf (builtins.path { path = ./read-dir; name = "dir.d"; }) == "dir.d";
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,23 @@
+# Test builtins that produce store paths
+#@@@
+# true
+
+# hasContext
+assert !(builtins.hasContext "plain");
+
+# toFile
+assert builtins.toFile "hello" "hello world" == "/nix/store/ivlnvab4q9c7wbsvbfsvgaa15j9p6206-hello";
+assert builtins.toFile "hello" "hello world" == builtins.toFile "hello" "hello world";
+assert builtins.toFile "hello" "" != builtins.toFile "hello" "hello world";
+assert builtins.readFile (builtins.toFile "hello" "hello world") == "hello world";
+
+# placeholder
+assert builtins.placeholder "out" == "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9";
+assert builtins.placeholder "dev" != builtins.placeholder "out";
+
+# storePath
+assert builtins.storePath "/nix/store/ivlnvab4q9c7wbsvbfsvgaa15j9p6206-hello" == /nix/store/ivlnvab4q9c7wbsvbfsvgaa15j9p6206-hello;
+assert builtins.storePath "/nix/store/ivlnvab4q9c7wbsvbfsvgaa15j9p6206-hello/bin/hello" == /nix/store/ivlnvab4q9c7wbsvbfsvgaa15j9p6206-hello/bin/hello;
+
+# If everything is ok, then return true
+true
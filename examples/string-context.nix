@@ -0,0 +1,58 @@
+# Test that strings carry their context through the string builtins
+#@@@
+# true
+
+let
+  drv = "/nix/store/ffffffffffffffffffffffffffffffff-hello.drv";
+  src = "/nix/store/gggggggggggggggggggggggggggggggg-source";
+
+  out = builtins.appendContext "hello" { ${drv} = { outputs = [ "out" ]; }; };
+  both = builtins.appendContext "${out}-${src}" { ${src} = { path = true; }; };
+
+  # Structural list equality, `==` on lists only compares the references
+  listEq = a: b:
+    builtins.length a == builtins.length b
+    && builtins.all (i: builtins.elemAt a i == builtins.elemAt b i)
+      (builtins.genList (i: i) (builtins.length a));
+
+  context = builtins.getContext out;
+  bothContext = builtins.getContext both;
+in
+
+# getContext and appendContext
+assert builtins.getContext "plain" == { };
+assert listEq (builtins.attrNames context) [ drv ];
+assert listEq context.${drv}.outputs [ "out" ];
+assert listEq (builtins.attrNames bothContext) [ drv src ];
+assert bothContext.${src}.path;
+assert (builtins.getContext (builtins.appendContext "" { ${drv} = { allOutputs = true; }; })).${drv}.allOutputs;
+
+# Context doesn't affect the content
+assert out == "hello";
+assert builtins.stringLength out == 5;
+
+# Interpolation and concatenation keep it
+assert builtins.hasContext "${out}";
+assert builtins.hasContext ("prefix-" + out);
+assert builtins.hasContext (builtins.concatStringsSep ", " [ "a" out ]);
+assert builtins.hasContext (builtins.toString out);
+
+# Derived strings keep it
+assert builtins.hasContext (builtins.substring 0 1 out);
+assert builtins.hasContext (builtins.replaceStrings [ "h" ] [ "j" ] out);
+assert builtins.hasContext (builtins.replaceStrings [ "h" ] [ out ] "hi");
+assert !(builtins.hasContext (builtins.replaceStrings [ "x" ] [ out ] "hi"));
+assert builtins.hasContext (builtins.elemAt (builtins.split "l+" out) 0);
+assert builtins.hasContext (builtins.elemAt (builtins.match "(h).*" out) 0);
+
+# And discarding it removes everything
+assert !(builtins.hasContext (builtins.unsafeDiscardStringContext both));
+assert builtins.unsafeDiscardStringContext both == "hello-${src}";
+
+# toFile references the paths in the content and has its own path as context
+let
+  file = builtins.toFile "a" "${builtins.toFile "b" "c"}";
+in
+assert listEq (builtins.attrNames (builtins.getContext file)) [ file ];
+
+true
@@ -0,0 +1,68 @@
+# Test escapes and indentation of string literals, the expected values are from Nix 2.24
+#@@@
+# true
+
+let
+  # The bytes of the strings, without going through other escapes
+  sha256 = builtins.hashString "sha256";
+
+  x = "value";
+in
+
+# Escapes of double quoted strings
+assert sha256 "a\nb" == "7e18f737311b2dc3b2f269dd78396b0351f14fb66efa879f768cb23181883c78";
+assert sha256 "\t\r" == "e37c8326d81bd33bc8410e541d9d73a198501c37311886aea5954935212b70a6";
+assert sha256 "q\"q" == "3d7839cccbf1519e8a9376b2666aa31a3dd121a3afe915a54c2dbd2979432c62";
+assert sha256 "a\\b" == "c62016d0f8ee333350283fd879b50b692932e932794e5d686f7d37d67484e199";
+assert sha256 "\${x}" == "4364f747efb7ae7ce7414b878a884cace03be50583d72a01f9abf7e67d1955ee";
+assert "$${x}" == "$" + "$" + "{x}";
+assert "\q" == "q";
+assert "a$" == "a" + "$";
+assert builtins.stringLength "\n\t\r\\\"" == 5;
+
+# Escapes of indented strings
+assert ''''${x}'' == "\${x}";
+assert ''a'''b'' == "a''b";
+assert ''a''\nb''\tc''\r'' == "a\nb\tc\r";
+assert ''''\x'' == "x";
+assert ''\n'' == "\\n";
+assert ''"'' == "\"";
+assert ''$${x}'' == "$\${x}";
+assert ''${x}'' == "value";
+
+# The common indentation is removed
+assert ''
+    foo
+      bar
+  '' == "foo\n  bar\n";
+assert ''  foo
+  bar'' == "foo\nbar";
+assert ''
+    a ${x}
+    ${x} b
+  '' == "a value\nvalue b\n";
+assert ''
+
+    first
+
+  '' == "\nfirst\n\n";
+assert ''
+    a
+  	b
+  '' == "  a\n\tb\n";
+
+# Whitespace only lines are ignored, as the last one
+assert ''a  
+   '' == "a  \n";
+assert ''  '' == "";
+assert ''
+  '' == "";
+
+# Attribute names are strings too
+assert { "a\nb" = 1; } ? ${"a" + "\n" + "b"};
+assert { ''
+  c
+  '' = 1; } ? "c\n";
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,101 @@
+# Test string builtins against upstream behavior
+#@@@
+# true
+
+let
+  # Structural list equality, `==` on lists only compares the references
+  listEq = a: b:
+    builtins.length a == builtins.length b
+    && builtins.all (i:
+      let
+        x = builtins.elemAt a i;
+        y = builtins.elemAt b i;
+      in
+      if builtins.isList x && builtins.isList y then listEq x y else x == y)
+      (builtins.genList (i: i) (builtins.length a));
+
+  check = cases: builtins.all (case: if builtins.isList case.expected
+    then listEq case.result case.expected
+    else case.result == case.expected) cases;
+in
+
+# substring
+assert check [
+  { result = builtins.substring 0 3 "nixos"; expected = "nix"; }
+  { result = builtins.substring 3 10 "nixos"; expected = "os"; }
+  { result = builtins.substring 3 (-1) "nixos"; expected = "os"; }
+  { result = builtins.substring 10 2 "nixos"; expected = ""; }
+  { result = builtins.substring 10 (-1) "nixos"; expected = ""; }
+  { result = builtins.substring 1 0 "nixos"; expected = ""; }
+  { result = builtins.substring 0 3 ""; expected = ""; }
+  { result = builtins.substring 1 (-1) "héllo"; expected = "éllo"; }
+  { result = builtins.substring 0 3 "héllo"; expected = "hé"; }
+  { result = builtins.substring 3 2 "héllo"; expected = "ll"; }
+  { result = builtins.substring 6 1 "héllo"; expected = ""; }
+];
+
+# Cutting a character in half doesn't fail
+assert builtins.substring 0 2 "héllo" != "h";
+
+# stringLength counts bytes
+assert check [
+  { result = builtins.stringLength ""; expected = 0; }
+  { result = builtins.stringLength "nixos"; expected = 5; }
+  { result = builtins.stringLength "ñ"; expected = 2; }
+  { result = builtins.stringLength "héllo"; expected = 6; }
+  { result = builtins.stringLength "日本"; expected = 6; }
+  { result = builtins.stringLength { outPath = "out"; }; expected = 3; }
+];
+
+# toString, the strings are the ones Nix gives
+assert check [
+  { result = toString 42; expected = "42"; }
+  { result = toString (-7); expected = "-7"; }
+  { result = toString 1.5; expected = "1.500000"; }
+  { result = toString 1.0; expected = "1.000000"; }
+  { result = toString true; expected = "1"; }
+  { result = toString false; expected = ""; }
+  { result = toString null; expected = ""; }
+  { result = toString /etc/hosts; expected = "/etc/hosts"; }
+  { result = toString [ 1 "a" [ 2 3 ] ]; expected = "1 a 2 3"; }
+  { result = toString [ 1 [ ] 2 null 3 ]; expected = "1 2  3"; }
+  { result = toString { __toString = self: "set ${self.name}"; name = "x"; }; expected = "set x"; }
+  { result = toString { outPath = "/nix/store/out"; }; expected = "/nix/store/out"; }
+];
+
+# Derivations coerce to their output path
+assert
+  let
+    drv = derivation { name = "hello"; system = "x86_64-linux"; builder = "/bin/sh"; };
+  in
+  "${drv}" == drv.outPath && toString drv == drv.outPath && builtins.hasContext "${drv}";
+
+# match has to match the whole string
+assert check [
+  { result = builtins.match "ab" "abc"; expected = null; }
+  { result = builtins.match "a" "xax"; expected = null; }
+  { result = builtins.match "a|b" "ab"; expected = null; }
+  { result = builtins.match ".*(a).*" "xax"; expected = [ "a" ]; }
+  { result = builtins.match "abc" "abc"; expected = [ ]; }
+  { result = builtins.match "a(b)(c)" "abc"; expected = [ "b" "c" ]; }
+  { result = builtins.match "a(x)?b" "ab"; expected = [ null ]; }
+  { result = builtins.match "[[:space:]]+([[:upper:]]+)[[:space:]]+" "  FOO   "; expected = [ "FOO" ]; }
+];
+
+# split keeps the text between matches and the capture groups
+assert check [
+  { result = builtins.split "(a)b" "abc"; expected = [ "" [ "a" ] "c" ]; }
+  { result = builtins.split "([ac])" "abc"; expected = [ "" [ "a" ] "b" [ "c" ] "" ]; }
+  { result = builtins.split "(a)|(c)" "abc"; expected = [ "" [ "a" null ] "b" [ null "c" ] "" ]; }
+  { result = builtins.split "([[:upper:]]+)" " FOO "; expected = [ " " [ "FOO" ] " " ]; }
+  { result = builtins.split "x" "abc"; expected = [ "abc" ]; }
+  { result = builtins.split "a" "xax"; expected = [ "x" [ ] "x" ]; }
+  { result = builtins.split "^a" "aa"; expected = [ "" [ ] "a" ]; }
+];
+
+# The same patterns over and over, like nixpkgs does in loops, hit the regex cache
+assert builtins.all (i: builtins.match "([0-9]+)-(.*)" "${toString i}-x" != null
+  && builtins.length (builtins.split "-" "${toString i}-x") == 3) (builtins.genList (i: i) 200);
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,33 @@
+# Test `__structuredAttrs`, the attributes go to the builder as one JSON
+# object in `__json` instead of one variable each
+#@@@
+# true
+
+let
+  structured = derivation {
+    name = "structured";
+    system = "x86_64-linux";
+    builder = "/bin/sh";
+    __structuredAttrs = true;
+    outputs = [ "out" "dev" ];
+    nested = {
+      a = 1;
+      b = [ true null "x\n" ];
+      c.d = 1.5;
+    };
+    path = ./read-dir/file.txt;
+  };
+
+  # The environment is `__json`, `out` and `dev`, with `__json` being
+  # {"builder":"/bin/sh","name":"structured","nested":{"a":1,"b":[true,null,"x\n"],"c":{"d":1.5}},
+  #  "outputs":["out","dev"],"path":"/nix/store/qjqcrhqkjmdgv3yqcgcd9fa7qi234hga-file.txt",
+  #  "system":"x86_64-linux"}
+  drvPath = "/nix/store/1rprs2gnly28h5v5b54mbxdq57hmsbp3-structured.drv";
+in
+
+assert structured.drvPath == drvPath;
+assert structured.out.outPath == "/nix/store/hda625clr8hxjhir9r5a9lqq31gkv2wl-structured";
+assert structured.dev.outPath == "/nix/store/69fqvnhcz62l5skrlz7vly6avqg79l03-structured-dev";
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,21 @@
+# Values that need care to print back as Nix with `--to-nix`
+#@@@
+# `--to-nix` output evaluates back to an equal value and prints identically
+{
+  simple = 1;
+  negative = -1;
+  float = 0.1;
+  negativeFloat = -2.5;
+  bigFloat = 1.0e100;
+  bool = true;
+  null = null;
+  string = "plain string with a lone dollar $ sign";
+  path = ./to-nix.nix;
+  "needs quotes" = "key with spaces";
+  "1starts-with-digit" = 1;
+  "if" = "keyword";
+  kebab-case' = "valid identifier";
+  "" = "empty key";
+  list = [ 1 (-1) "two" [ ] { } [ { a = [ null ]; } ] ];
+  nested.attr.set = { };
+}
@@ -0,0 +1,55 @@
+# Test builtins.fromTOML
+#@@@
+# true
+
+let
+  cargo = builtins.fromTOML ''
+    [package]
+    name = "nix-compiler"
+    version = "0.1.0"
+    edition = "2021"
+    published = 1979-05-27T07:32:00Z
+
+    [dependencies]
+    rnix = "0.11.0"
+    nix-macros = { path = "./crates/macros/" }
+
+    [profile.release]
+    lto = true
+    opt-level = 3
+    ratio = 0.5
+
+    [[bin]]
+    name = "first"
+
+    [[bin]]
+    name = "second"
+    features = [ "a", "b" ]
+  '';
+in
+
+# Tables
+assert cargo.package.name == "nix-compiler";
+assert cargo.package.edition == "2021";
+assert cargo.dependencies.rnix == "0.11.0";
+assert cargo.dependencies.nix-macros.path == "./crates/macros/";
+
+# Nested tables and scalars
+assert cargo.profile.release.lto;
+assert cargo.profile.release.opt-level == 3;
+assert cargo.profile.release.ratio == 0.5;
+
+# Datetimes are kept as strings
+assert cargo.package.published == "1979-05-27T07:32:00Z";
+
+# Array of tables
+assert builtins.length cargo.bin == 2;
+assert (builtins.elemAt cargo.bin 0).name == "first";
+assert (builtins.elemAt cargo.bin 1).name == "second";
+assert builtins.elemAt (builtins.elemAt cargo.bin 1).features 1 == "b";
+
+# Empty document
+assert builtins.fromTOML "" == { };
+
+# If everything is ok, then return true
+true
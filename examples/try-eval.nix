@@ -0,0 +1,21 @@
+# Test `builtins.tryEval`, it only catches `throw` and failed asserts
+#@@@
+# true
+
+assert builtins.tryEval (throw "x") == { success = false; value = false; };
+assert builtins.tryEval (assert 1 == 2; 1) == { success = false; value = false; };
+assert builtins.tryEval 1 == { success = true; value = 1; };
+
+# Only the argument itself is forced
+assert (builtins.tryEval [ (throw "x") ]).success;
+
+# A failed thunk fails again instead of looking like it's still resolving
+assert
+  let x = throw "nope"; in
+  !(builtins.tryEval x).success && !(builtins.tryEval x).success;
+assert
+  let y = { a = 1; } // throw "nope"; in
+  !(builtins.tryEval y).success && !(builtins.tryEval y).success;
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,19 @@
+let
+  set = import ./unsafe-get-attr-pos/set.nix;
+  file = toString ./unsafe-get-attr-pos/set.nix;
+
+  namePos = builtins.unsafeGetAttrPos "name" set;
+  descriptionPos = builtins.unsafeGetAttrPos "description" set.meta;
+in
+assert namePos == { inherit file; line = 2; column = 3; };
+assert descriptionPos == { inherit file; line = 3; column = 8; };
+assert (builtins.unsafeGetAttrPos "true" set).column == 22;
+
+# Overriding with `//` keeps the position of the definition that wins
+assert (builtins.unsafeGetAttrPos "name" (set // { name = "other"; })).line == 13;
+assert (builtins.unsafeGetAttrPos "meta" ({ meta = { }; } // set)).line == 3;
+
+assert builtins.unsafeGetAttrPos "missing" set == null;
+assert builtins.unsafeGetAttrPos "a" (builtins.listToAttrs [ { name = "a"; value = 1; } ]) == null;
+
+true
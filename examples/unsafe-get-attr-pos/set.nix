@@ -0,0 +1,5 @@
+{
+  name = "fixture";
+  meta.description = "Attributes at known positions";
+  inherit (builtins) true;
+}
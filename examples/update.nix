@@ -0,0 +1,22 @@
+# Test the `//` operator
+#@@@
+# true
+
+let
+  base = { a = 1; b = 1; };
+  x = base // { a = 2; };
+  chain = base // { a = 2; } // { b = 3; };
+in
+
+assert x.a == 2;
+assert x == { a = 2; b = 1; };
+assert chain.a == 2 && chain.b == 3;
+
+# Nested values must not see only the left hand side
+assert { inherit x; } == { x = { a = 2; b = 1; }; };
+assert { inherit chain; } == { chain = { a = 2; b = 3; }; };
+assert { y = base // { a = 2; } // { c = 3; }; }.y ? c;
+assert builtins.toXML { y = base // { a = 2; } // { b = 3; }; } == builtins.toXML { y = { a = 2; b = 3; }; };
+
+# If everything is ok, then return true
+true
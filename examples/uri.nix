@@ -0,0 +1,9 @@
+# URL literals are strings, the deprecation warning is printed once
+let
+  url = https://example.org/x.tar.gz;
+  mirror = mirror://gnu/hello/hello-2.12.tar.gz;
+in
+assert url == "https://example.org/x.tar.gz";
+assert builtins.isString mirror && !(builtins.hasContext mirror);
+
+true
@@ -0,0 +1,72 @@
+# Test version builtins
+#@@@
+# true
+
+let
+  # Structural list equality, `==` on lists only compares the references
+  listEq = a: b:
+    builtins.length a == builtins.length b
+    && builtins.all (i: builtins.elemAt a i == builtins.elemAt b i)
+      (builtins.genList (i: i) (builtins.length a));
+
+  splitVersionCases = [
+    { version = "1.2.3a-rc1"; components = [ "1" "2" "3" "a" "rc" "1" ]; }
+    { version = "1.2.3"; components = [ "1" "2" "3" ]; }
+    { version = "2.3pre1"; components = [ "2" "3" "pre" "1" ]; }
+    { version = "2.3.0pre1234"; components = [ "2" "3" "0" "pre" "1234" ]; }
+    { version = "2.3a"; components = [ "2" "3" "a" ]; }
+    { version = "1.2_3-4"; components = [ "1" "2" "3" "4" ]; }
+    { version = "..1--"; components = [ "1" ]; }
+    { version = ""; components = [ ]; }
+  ];
+
+  # The table of the Nix test suite, plus versions that don't fit in an integer
+  compareVersionsCases = [
+    { a = "1.0"; b = "2.3"; result = -1; }
+    { a = "2.1"; b = "2.3"; result = -1; }
+    { a = "2.3"; b = "2.3"; result = 0; }
+    { a = "2.5"; b = "2.3"; result = 1; }
+    { a = "3.1"; b = "2.3"; result = 1; }
+    { a = "2.3.1"; b = "2.3"; result = 1; }
+    { a = "2.3.1"; b = "2.3a"; result = 1; }
+    { a = "2.3pre1"; b = "2.3"; result = -1; }
+    { a = "2.3pre3"; b = "2.3pre12"; result = -1; }
+    { a = "2.3a"; b = "2.3c"; result = -1; }
+    { a = "2.3pre1"; b = "2.3c"; result = -1; }
+    { a = "2.3pre1"; b = "2.3q"; result = -1; }
+    { a = "2024-01-01"; b = "2023-12-31"; result = 1; }
+    { a = "1.2a"; b = "1.2"; result = 1; }
+    { a = "1.02"; b = "1.2"; result = 0; }
+    { a = "1.99999999999999999999"; b = "1.100000000000000000000"; result = -1; }
+  ];
+in
+
+# splitVersion
+assert builtins.all (case: listEq (builtins.splitVersion case.version) case.components)
+  splitVersionCases;
+
+# compareVersions
+assert builtins.compareVersions "1.0" "2.3" == -1;
+assert builtins.compareVersions "2.1" "2.3" == -1;
+assert builtins.compareVersions "2.3" "2.3" == 0;
+assert builtins.compareVersions "2.5" "2.3" == 1;
+assert builtins.compareVersions "3.1" "2.3" == 1;
+assert builtins.compareVersions "2.10" "2.9" == 1;
+assert builtins.all (case: builtins.compareVersions case.a case.b == case.result && builtins.compareVersions case.b case.a == -case.result)
+  compareVersionsCases;
+
+# parseDrvName
+assert builtins.parseDrvName "nix-2.24.9" == { name = "nix"; version = "2.24.9"; };
+assert builtins.parseDrvName "hello" == { name = "hello"; version = ""; };
+assert builtins.parseDrvName "hello-2.12.1" == { name = "hello"; version = "2.12.1"; };
+assert builtins.parseDrvName "python3.12-requests-2.32.3" == { name = "python3.12-requests"; version = "2.32.3"; };
+assert builtins.parseDrvName "gtk+3-3.24.43" == { name = "gtk+3"; version = "3.24.43"; };
+assert builtins.parseDrvName "linux-firmware-20241017" == { name = "linux-firmware"; version = "20241017"; };
+assert builtins.parseDrvName "nixos-system-nixos-24.05.20241017" == { name = "nixos-system-nixos"; version = "24.05.20241017"; };
+assert builtins.parseDrvName "perl5.38.2-Test-Simple-1.302195-devdoc" == { name = "perl5.38.2-Test-Simple"; version = "1.302195-devdoc"; };
+assert builtins.parseDrvName "trailing-" == { name = "trailing-"; version = ""; };
+assert builtins.parseDrvName "a-1-2" == { name = "a"; version = "1-2"; };
+assert builtins.parseDrvName "" == { name = ""; version = ""; };
+
+# If everything is ok, then return true
+true
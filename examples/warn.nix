@@ -0,0 +1,10 @@
+# Prints a warning and a trace to stderr, `traceVerbose` only with --trace-verbose
+let
+  deprecated = builtins.warn "'deprecated' is deprecated, use 'value'" value;
+  value = { answer = 42; };
+in
+assert deprecated.answer == 42;
+assert builtins.traceVerbose "verbose" 1 == 1;
+assert builtins.trace "traced" 2 == 2;
+
+true
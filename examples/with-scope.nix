@@ -0,0 +1,19 @@
+# Test the scoping of `with`
+#@@@
+# true
+
+# Lexical bindings beat every `with`
+assert (let x = 1; in with { x = 2; }; x) == 1;
+assert (with { x = 2; }; (x: x) 1) == 1;
+assert (with { x = 2; }; let x = 1; in x) == 1;
+assert (with { map = 1; }; builtins.isFunction map);
+
+# The innermost `with` wins
+assert (with { x = 1; }; with { x = 2; }; x) == 2;
+assert (with { x = 1; y = 3; }; with { x = 2; }; y) == 3;
+
+# `inherit` looks up like a variable
+assert (with { x = 1; }; { inherit x; }) == { x = 1; };
+
+# If everything is ok, then return true
+true
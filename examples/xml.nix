@@ -0,0 +1,55 @@
+# Test builtins.toXML
+#@@@
+# true
+
+let
+  value = {
+    int = 1;
+    list = [ "a < b & c" null true 0.5 ];
+    nested.set = { };
+    pattern = { x, y ? 1, ... }@args: x;
+    ident = x: x;
+  };
+in
+
+assert builtins.toXML value == ''<?xml version='1.0' encoding='utf-8'?>
+<expr>
+  <attrs>
+    <attr name="ident">
+      <function>
+        <varpat name="x" />
+      </function>
+    </attr>
+    <attr name="int">
+      <int value="1" />
+    </attr>
+    <attr name="list">
+      <list>
+        <string value="a &lt; b &amp; c" />
+        <null />
+        <bool value="true" />
+        <float value="0.5" />
+      </list>
+    </attr>
+    <attr name="nested">
+      <attrs>
+        <attr name="set">
+          <attrs>
+          </attrs>
+        </attr>
+      </attrs>
+    </attr>
+    <attr name="pattern">
+      <function>
+        <attrspat ellipsis="1" name="args">
+          <attr name="x" />
+          <attr name="y" />
+        </attrspat>
+      </function>
+    </attr>
+  </attrs>
+</expr>
+'';
+
+# If everything is ok, then return true
+true
@@ -0,0 +1,167 @@
+//! Dotted attribute paths given as strings, like `-A` and `flake.nix#attr`
+//!
+//! Unlike `Scope::resolve_attr_path` these don't come from the AST, so there
+//! is no node to point at and errors name the whole requested path instead.
+
+use std::fmt;
+
+use crate::{NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixVar};
+
+/// Keys listed when an attribute is missing, big sets (nixpkgs) are cut
+const MAX_SUGGESTIONS: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttrPathComponent(pub String);
+
+impl AttrPathComponent {
+    /// Parse `a."b.c".d`, quoted components may contain dots and `\"`
+    pub fn parse_path(path: &str) -> Result<Vec<Self>, String> {
+        let mut components = vec![];
+
+        if path.is_empty() {
+            return Ok(components);
+        }
+
+        let mut chars = path.chars().peekable();
+
+        loop {
+            let mut name = String::new();
+
+            if chars.next_if_eq(&'"').is_some() {
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c) => name.push(c),
+                            None => return Err(format!("unterminated quote in '{path}'")),
+                        },
+                        Some(c) => name.push(c),
+                        None => return Err(format!("unterminated quote in '{path}'")),
+                    }
+                }
+
+                if chars.peek().is_some_and(|c| *c != '.') {
+                    return Err(format!("expected '.' after a quoted attribute in '{path}'"));
+                }
+            } else {
+                while let Some(c) = chars.next_if(|c| *c != '.') {
+                    name.push(c);
+                }
+
+                if name.is_empty() {
+                    return Err(format!("empty attribute name in '{path}'"));
+                }
+            }
+
+            components.push(AttrPathComponent(name));
+
+            if chars.next().is_none() {
+                return Ok(components);
+            }
+        }
+    }
+
+    fn is_identifier(&self) -> bool {
+        let mut chars = self.0.chars();
+
+        chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '\'' | '-'))
+    }
+}
+
+impl fmt::Display for AttrPathComponent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_identifier() {
+            f.write_str(&self.0)
+        } else {
+            write!(
+                f,
+                "\"{}\"",
+                self.0.replace('\\', "\\\\").replace('"', "\\\"")
+            )
+        }
+    }
+}
+
+pub fn display_path(path: &[AttrPathComponent]) -> String {
+    path.iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+fn available_keys<'a>(keys: impl ExactSizeIterator<Item = &'a String>) -> String {
+    let count = keys.len();
+
+    if count == 0 {
+        return String::from("it has no attributes");
+    }
+
+    let mut out = keys
+        .take(MAX_SUGGESTIONS)
+        .map(|key| AttrPathComponent(key.clone()).to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if count > MAX_SUGGESTIONS {
+        out += &format!(" and {} more", count - MAX_SUGGESTIONS);
+    }
+
+    format!("available attributes: {out}")
+}
+
+/// Walk `path` through `value`, only the sets along the way are forced
+pub fn select_path(
+    value: NixVar,
+    path: &[AttrPathComponent],
+    backtrace: &NixBacktrace,
+) -> NixResult<NixVar> {
+    let mut current = value;
+
+    for (idx, component) in path.iter().enumerate() {
+        let resolved = current.resolve(backtrace)?;
+        let resolved = resolved.borrow();
+
+        let Some(set) = resolved.as_attr_set() else {
+            let parent = if idx == 0 {
+                String::from("the value")
+            } else {
+                format!("'{}'", display_path(&path[..idx]))
+            };
+
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "Cannot select '\x1b[1;95m{}\x1b[0m', {parent} is a {} while a set was expected",
+                    display_path(path),
+                    resolved.as_type()
+                ),
+            ));
+        };
+
+        let Some(next) = set.get(&component.0) else {
+            let selecting = if idx + 1 < path.len() {
+                format!(" while selecting '{}'", display_path(path))
+            } else {
+                String::new()
+            };
+
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::AttributeMissing,
+                format!(
+                    "Attribute '\x1b[1;95m{}\x1b[0m' missing{selecting}, {}",
+                    display_path(&path[..=idx]),
+                    available_keys(set.keys())
+                ),
+            ));
+        };
+
+        current = next.clone();
+    }
+
+    Ok(current)
+}
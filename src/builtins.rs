@@ -1,13 +1,21 @@
-mod hash;
+pub mod hash;
 mod r#impl;
+pub mod json;
+mod version;
+mod xml;
 
 use std::fmt::{self, Write};
 use std::path::PathBuf;
 
-use crate::value::{NixLambda, NixList};
-use crate::{NixBacktrace, NixResult, NixValue, NixValueWrapped, NixVar};
+use crate::value::{NixLambda, NixList, NixNumber, NixString};
+use crate::{
+    NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue, NixValueWrapped, NixVar,
+};
 
-pub use r#impl::{get_builtins, Abort, BaseNameOf, Import, Map, RemoveAttrs, Throw, ToString};
+pub use r#impl::{
+    current_time, get_builtins, Abort, BaseNameOf, Derivation, Import, Map, RemoveAttrs, Throw,
+    ToString,
+};
 
 pub trait FromNixExpr: Sized {
     fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self>;
@@ -50,6 +58,20 @@ macro_rules! int_from_nix_expr {
 int_from_nix_expr! {isize, i64, i32, i16, i8}
 int_from_nix_expr! {usize, u64, u32, u16, u8}
 
+impl FromNixExpr for NixNumber {
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        match *var.resolve(backtrace)?.borrow() {
+            NixValue::Int(i) => Ok(NixNumber::Int(i)),
+            NixValue::Float(f) => Ok(NixNumber::Float(f)),
+            ref value => Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!("expected a number but found a {}", value.as_type()),
+            )),
+        }
+    }
+}
+
 impl FromNixExpr for NixLambda {
     fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
         var.resolve(backtrace)?
@@ -87,6 +109,14 @@ impl FromNixExpr for String {
     }
 }
 
+impl FromNixExpr for NixString {
+    fn from_nix_expr(backtrace: &NixBacktrace, var: NixVar) -> NixResult<Self> {
+        var.resolve(backtrace)?
+            .borrow()
+            .coerce_to_string(backtrace, false, true)
+    }
+}
+
 // TODO:
 // impl FromNixExpr for NixAttrSet {
 //     fn from_nix_expr(
@@ -108,6 +138,9 @@ pub trait NixBuiltinInfo {
 pub trait NixBuiltin {
     fn get_name(&self) -> &'static str;
 
+    /// Whether some of the arguments were already applied
+    fn is_partial(&self) -> bool;
+
     fn run(&self, backtrace: &NixBacktrace, argument: NixVar) -> NixResult;
 }
 
@@ -127,9 +160,11 @@ impl fmt::Display for dyn NixBuiltin {
     }
 }
 
+/// Only the builtins themselves are equal by name, partial applications
+/// carry different arguments
 impl PartialEq for dyn NixBuiltin {
     fn eq(&self, other: &Self) -> bool {
-        self.get_name() == other.get_name()
+        self.get_name() == other.get_name() && !self.is_partial() && !other.is_partial()
     }
 }
 
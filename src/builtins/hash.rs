@@ -2,7 +2,9 @@
 
 use std::io::{self, Write};
 
-use openssl::hash;
+use openssl::{base64, hash};
+
+use crate::store;
 
 /// Available cryptographic hash functions.
 #[derive(Clone, Copy, Eq, PartialEq)]
@@ -17,13 +19,149 @@ pub enum Algorithm {
     SHA512,
 }
 
+impl Algorithm {
+    pub const ALL: [Algorithm; 4] = [
+        Algorithm::MD5,
+        Algorithm::SHA1,
+        Algorithm::SHA256,
+        Algorithm::SHA512,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        Algorithm::ALL
+            .into_iter()
+            .find(|algorithm| algorithm.name() == name)
+    }
+
+    /// Name in Nix, like `sha256`
+    pub fn name(self) -> &'static str {
+        match self {
+            Algorithm::MD5 => "md5",
+            Algorithm::SHA1 => "sha1",
+            Algorithm::SHA256 => "sha256",
+            Algorithm::SHA512 => "sha512",
+        }
+    }
+
+    /// Size of the digest in bytes
+    pub fn size(self) -> usize {
+        match self {
+            Algorithm::MD5 => 16,
+            Algorithm::SHA1 => 20,
+            Algorithm::SHA256 => 32,
+            Algorithm::SHA512 => 64,
+        }
+    }
+}
+
+/// How a digest is written, `toHashFormat` of `builtins.convertHash`
+#[derive(Clone, Copy, Eq, PartialEq)]
+pub enum HashFormat {
+    Base16,
+    Nix32,
+    Base64,
+    /// `<algorithm>-<base64>`
+    Sri,
+}
+
+impl HashFormat {
+    pub const NAMES: &[&str] = &["base16", "nix32", "base32", "base64", "sri"];
+
+    /// `base32` is the old name of `nix32`
+    pub fn from_name(name: &str) -> Option<HashFormat> {
+        match name {
+            "base16" => Some(HashFormat::Base16),
+            "nix32" | "base32" => Some(HashFormat::Nix32),
+            "base64" => Some(HashFormat::Base64),
+            "sri" => Some(HashFormat::Sri),
+            _ => None,
+        }
+    }
+}
+
+/// Write `digest` in `format`
+pub fn format(algorithm: Algorithm, digest: &[u8], format: HashFormat) -> String {
+    match format {
+        HashFormat::Base16 => hex::encode(digest),
+        HashFormat::Nix32 => store::to_base32(digest),
+        HashFormat::Base64 => base64::encode_block(digest),
+        HashFormat::Sri => format!("{}-{}", algorithm.name(), base64::encode_block(digest)),
+    }
+}
+
+/// Read a hash in any of the forms Nix accepts: SRI (`sha256-<base64>`),
+/// `sha256:<hash>` or a bare hash of `algorithm`. The encoding of the last
+/// two is told by the length.
+pub fn parse(hash: &str, algorithm: Option<Algorithm>) -> Result<(Algorithm, Vec<u8>), String> {
+    let prefixed = hash
+        .split_once('-')
+        .map(|(name, rest)| (name, rest, true))
+        .or_else(|| hash.split_once(':').map(|(name, rest)| (name, rest, false)));
+
+    let (algorithm, encoded, is_sri) = match prefixed {
+        Some((name, rest, is_sri)) => {
+            let Some(found) = Algorithm::from_name(name) else {
+                return Err(format!("unknown hash algorithm '{name}' in '{hash}'"));
+            };
+
+            if let Some(expected) = algorithm.filter(|expected| *expected != found) {
+                return Err(format!(
+                    "hash '{hash}' should have type '{}' but got '{}'",
+                    expected.name(),
+                    found.name()
+                ));
+            }
+
+            (found, rest, is_sri)
+        }
+        None => {
+            let Some(algorithm) = algorithm else {
+                return Err(format!(
+                    "hash '{hash}' does not include a type, nor is the type otherwise known from context"
+                ));
+            };
+
+            (algorithm, hash, false)
+        }
+    };
+
+    let size = algorithm.size();
+    let base64_len = size.div_ceil(3) * 4;
+
+    let digest = if is_sri {
+        base64::decode_block(encoded).ok()
+    } else if encoded.len() == size * 2 {
+        hex::decode(encoded).ok()
+    } else if encoded.len() == (size * 8 - 1) / 5 + 1 {
+        store::from_base32(encoded)
+    } else if encoded.len() == base64_len {
+        base64::decode_block(encoded).ok()
+    } else {
+        return Err(format!(
+            "hash '{hash}' has wrong length for hash type '{}'",
+            algorithm.name()
+        ));
+    };
+
+    match digest {
+        Some(digest) if digest.len() == size => Ok((algorithm, digest)),
+        _ if is_sri => Err(format!("invalid SRI hash '{hash}'")),
+        _ => Err(format!("invalid {} hash '{hash}'", algorithm.name())),
+    }
+}
+
+/// Function for `Hasher` which generates a cryptographic digest from the given
+/// data and algorithm.
+pub fn digest(algorithm: Algorithm, data: &[u8]) -> Vec<u8> {
+    let mut hasher = Hasher::new(algorithm);
+    hasher.write_all(data).expect("Could not write hash data");
+    hasher.finish()
+}
+
 /// Function for `Hasher` which generates a cryptographic digest serialized in
 /// hexadecimal from the given data and algorithm.
 pub fn hex_digest(algorithm: Algorithm, data: &[u8]) -> String {
-    let mut hasher = Hasher::new(algorithm);
-    hasher.write_all(data).expect("Could not write hash data");
-    let hash = hasher.finish();
-    hex::encode(hash)
+    hex::encode(digest(algorithm, data))
 }
 
 /// Generator of digests using a cryptographic hash function.
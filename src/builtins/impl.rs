@@ -1,21 +1,55 @@
-use std::collections::{HashMap, HashSet, VecDeque};
-use std::hash::{Hash, Hasher};
+use std::cell::{OnceCell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
 use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use nix_macros::{builtin, gen_builtins};
 
-use crate::value::{NixAttrSet, NixLambda, NixList};
+use crate::derivation;
+use crate::fetchers;
+use crate::flake;
+use crate::impurities::{self, Impurity};
+use crate::log;
+use crate::search_path;
+use crate::value::{
+    NixArithOp, NixAttrSet, NixContextElem, NixLambda, NixList, NixNumber, NixString,
+    NixStringContext,
+};
 use crate::{
-    LazyNixValue, NixBacktrace, NixLabelKind, NixLabelMessage, NixLambdaParam, NixResult, NixValue,
-    NixValueWrapped, NixVar, Scope,
+    store, LazyNixValue, NixBacktrace, NixErrorKind, NixLabelKind, NixLabelMessage, NixLambdaParam,
+    NixResult, NixValue, NixValueWrapped, NixVar, Scope,
 };
 
-use super::hash;
+use super::xml::XmlWriter;
+use super::{hash, version};
+
+#[builtin]
+pub fn abort(backtrace: &NixBacktrace, message: String) {
+    Err(backtrace
+        .to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("evaluation aborted with the following error message: '{message}'"),
+        )
+        .with_kind(NixErrorKind::Abort))
+}
+
+fn arith(
+    backtrace: &NixBacktrace,
+    op: NixArithOp,
+    lhs: NixValueWrapped,
+    rhs: NixValueWrapped,
+) -> NixResult {
+    NixValue::arith(op, &lhs.borrow(), &rhs.borrow())
+        .map(NixValue::wrap)
+        .map_err(|message| backtrace.to_error(NixLabelKind::Error, NixLabelMessage::Empty, message))
+}
 
 #[builtin]
-pub fn abort(message: String) {
-    panic!("Aborting: {message}")
+pub fn add(backtrace: &NixBacktrace, lhs: NixValueWrapped, rhs: NixValueWrapped) {
+    arith(backtrace, NixArithOp::Add, lhs, rhs)
 }
 
 #[builtin]
@@ -54,6 +88,91 @@ pub fn any(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     Ok(NixValue::Bool(false).wrap())
 }
 
+#[builtin]
+pub fn append_context(backtrace: &NixBacktrace, s: NixString, context: NixValueWrapped) {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("appendContext: {message}"),
+        )
+    };
+
+    let Some(context) = context.borrow().as_attr_set().cloned() else {
+        return Err(error(format!(
+            "expected a set but found {}",
+            context.borrow().as_type_with_article()
+        )));
+    };
+
+    let mut out = s;
+
+    for (path, info) in context {
+        store::check_store_path(&path).map_err(error)?;
+
+        let info = info.resolve(backtrace)?;
+        let Some(info) = info.borrow().as_attr_set().cloned() else {
+            return Err(error(format!(
+                "expected the context of '{path}' to be a set but found {}",
+                info.borrow().as_type_with_article()
+            )));
+        };
+
+        let get_bool =
+            |attr: &str| -> NixResult<bool> {
+                match info.get(attr) {
+                    Some(value) => value.resolve(backtrace)?.borrow().as_bool().ok_or_else(|| {
+                        error(format!("expected '{attr}' of '{path}' to be a boolean"))
+                    }),
+                    None => Ok(false),
+                }
+            };
+
+        if get_bool("path")? {
+            out.extend_context([NixContextElem::Opaque(path.clone())]);
+        }
+
+        if get_bool("allOutputs")? {
+            if !path.ends_with(".drv") {
+                return Err(error(format!(
+                    "tried to add all-outputs context of '{path}', which is not a derivation"
+                )));
+            }
+
+            out.extend_context([NixContextElem::DrvDeep(path.clone())]);
+        }
+
+        if let Some(outputs) = info.get("outputs") {
+            let Some(outputs) = outputs.resolve(backtrace)?.borrow().as_list() else {
+                return Err(error(format!(
+                    "expected 'outputs' of '{path}' to be a list"
+                )));
+            };
+
+            if !outputs.0.is_empty() && !path.ends_with(".drv") {
+                return Err(error(format!(
+                    "tried to add derivation output context of '{path}', which is not a derivation"
+                )));
+            }
+
+            for output in outputs.0.iter() {
+                let Some(output) = output.resolve(backtrace)?.borrow().as_string().cloned() else {
+                    return Err(error(format!(
+                        "expected the outputs of '{path}' to be strings"
+                    )));
+                };
+
+                out.extend_context([NixContextElem::Built {
+                    drv_path: path.clone(),
+                    output,
+                }]);
+            }
+        }
+    }
+
+    Ok(NixValue::String(out).wrap())
+}
+
 #[builtin]
 pub fn attr_names(set: NixValueWrapped) {
     let set = set.borrow();
@@ -64,6 +183,7 @@ pub fn attr_names(set: NixValueWrapped) {
     let names = set
         .keys()
         .cloned()
+        .map(NixString::from)
         .map(NixValue::String)
         .map(NixValue::wrap_var)
         .collect::<Vec<NixVar>>();
@@ -74,6 +194,7 @@ pub fn attr_names(set: NixValueWrapped) {
 #[builtin]
 pub fn base_name_of(s: NixValueWrapped) {
     let s = s.borrow();
+    let context = s.as_nix_string().cloned().unwrap_or_default();
 
     let s = if let Some(s) = s.as_string() {
         if s.ends_with("/") {
@@ -96,7 +217,7 @@ pub fn base_name_of(s: NixValueWrapped) {
         todo!("Error Handling: baseNameOf cannot get str from path");
     };
 
-    Ok(NixValue::String(s.to_owned()).wrap())
+    Ok(NixValue::String(context.with_value(s.to_owned())).wrap())
 }
 
 #[builtin]
@@ -111,23 +232,81 @@ pub fn attr_values(set: NixValueWrapped) {
     Ok(NixValue::List(NixList(Rc::new(values))).wrap())
 }
 
+/// Integer operands of the bitwise builtins, floats aren't truncated
+fn int_operands(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    operands: [NixValueWrapped; 2],
+) -> NixResult<[i64; 2]> {
+    let [a, b] = operands.map(|operand| {
+        let operand = operand.borrow();
+
+        operand.as_int().ok_or_else(|| {
+            backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "{builtin}: expected an integer but found a {}",
+                    operand.as_type()
+                ),
+            )
+        })
+    });
+
+    Ok([a?, b?])
+}
+
 #[builtin]
-pub fn compare_versions(first_arg: String, second_arg: String) {
-    let first_arg = first_arg.split(".");
-    let second_arg = second_arg.split(".");
+pub fn bit_and(backtrace: &NixBacktrace, a: NixValueWrapped, b: NixValueWrapped) {
+    let [a, b] = int_operands(backtrace, "bitAnd", [a, b])?;
+    Ok(NixValue::Int(a & b).wrap())
+}
 
-    for (first, second) in first_arg.zip(second_arg) {
-        let first = first.parse::<u8>().unwrap();
-        let second = second.parse::<u8>().unwrap();
+#[builtin]
+pub fn bit_or(backtrace: &NixBacktrace, a: NixValueWrapped, b: NixValueWrapped) {
+    let [a, b] = int_operands(backtrace, "bitOr", [a, b])?;
+    Ok(NixValue::Int(a | b).wrap())
+}
 
-        match first.cmp(&second) {
-            std::cmp::Ordering::Less => return Ok(NixValue::Int(-1).wrap()),
-            std::cmp::Ordering::Equal => {}
-            std::cmp::Ordering::Greater => return Ok(NixValue::Int(1).wrap()),
-        }
+#[builtin]
+pub fn bit_xor(backtrace: &NixBacktrace, a: NixValueWrapped, b: NixValueWrapped) {
+    let [a, b] = int_operands(backtrace, "bitXor", [a, b])?;
+    Ok(NixValue::Int(a ^ b).wrap())
+}
+
+/// Rounded float to int, out of range values are errors instead of saturating
+fn float_to_int(backtrace: &NixBacktrace, builtin: &str, value: f64) -> NixResult<i64> {
+    // i64::MAX isn't representable as a float, 2^63 is the first value out of range
+    if !value.is_finite() || value < i64::MIN as f64 || value >= -(i64::MIN as f64) {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("{builtin}: {value} cannot be converted to an integer"),
+        ));
     }
 
-    Ok(NixValue::Int(0).wrap())
+    Ok(value as i64)
+}
+
+#[builtin]
+pub fn ceil(backtrace: &NixBacktrace, number: NixNumber) {
+    let value = match number {
+        NixNumber::Int(int) => int,
+        NixNumber::Float(float) => float_to_int(backtrace, "ceil", float.ceil())?,
+    };
+
+    Ok(NixValue::Int(value).wrap())
+}
+
+#[builtin]
+pub fn compare_versions(first_arg: String, second_arg: String) {
+    let ordering = match version::compare_versions(&first_arg, &second_arg) {
+        Ordering::Less => -1,
+        Ordering::Equal => 0,
+        Ordering::Greater => 1,
+    };
+
+    Ok(NixValue::Int(ordering).wrap())
 }
 
 #[builtin]
@@ -148,25 +327,149 @@ pub fn concat_map(backtrace: &NixBacktrace, callback: NixLambda, list: NixList)
 }
 
 #[builtin]
-pub fn concat_string_sep(backtrace: &NixBacktrace, sep: String, list: NixList) {
-    let list = list
-        .0
-        .iter()
-        .map(|i| i.resolve(backtrace))
-        .collect::<NixResult<Vec<_>>>()?
-        .iter()
-        .map(|i| {
-            i.borrow()
-                .cast_to_string()
-                .ok_or_else(|| todo!("Error Handling"))
+pub fn concat_strings_sep(backtrace: &NixBacktrace, sep: NixString, list: NixList) {
+    let mut out = NixString::default();
+
+    for (idx, item) in list.0.iter().enumerate() {
+        if idx > 0 {
+            out.push(&sep);
+        }
+
+        let item = item
+            .resolve(backtrace)?
+            .borrow()
+            .coerce_to_string(backtrace, false, true)?;
+
+        out.push(&item);
+    }
+
+    Ok(NixValue::String(out).wrap())
+}
+
+#[builtin]
+pub fn convert_hash(backtrace: &NixBacktrace, args: NixValueWrapped) {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("convertHash: {message}"),
+        )
+    };
+
+    let Some(args) = args.borrow().as_attr_set().cloned() else {
+        return Err(error(format!(
+            "expected a set but found a {}",
+            args.borrow().as_type()
+        )));
+    };
+
+    let get_string = |attr: &str| -> NixResult<Option<String>> {
+        let Some(value) = args.get(attr) else {
+            return Ok(None);
+        };
+
+        let value = value.resolve(backtrace)?;
+        let value = value.borrow();
+
+        match &*value {
+            NixValue::Null => Ok(None),
+            NixValue::String(string) => Ok(Some(string.to_string())),
+            value => Err(error(format!(
+                "expected '{attr}' to be a string, got {}",
+                value.as_type()
+            ))),
+        }
+    };
+
+    let Some(hash) = get_string("hash")? else {
+        return Err(error(String::from("the 'hash' attribute is required")));
+    };
+
+    let Some(to_format) = get_string("toHashFormat")? else {
+        return Err(error(String::from(
+            "the 'toHashFormat' attribute is required",
+        )));
+    };
+
+    let Some(to_format) = hash::HashFormat::from_name(&to_format) else {
+        return Err(error(format!(
+            "unknown hash format '{to_format}', expected one of: {}",
+            hash::HashFormat::NAMES.join(", ")
+        )));
+    };
+
+    let algorithm = get_string("hashAlgo")?
+        .map(|name| hash_algorithm(backtrace, &name))
+        .transpose()?;
+
+    let (algorithm, digest) = hash::parse(&hash, algorithm).map_err(error)?;
+
+    Ok(NixValue::String(hash::format(algorithm, &digest, to_format).into()).wrap())
+}
+
+thread_local! {
+    static CURRENT_TIME: OnceCell<i64> = const { OnceCell::new() };
+}
+
+/// `builtins.currentTime`, read on first use and the same for the rest of
+/// the evaluation. It's installed as a thunk by `Scope::new_with_builtins`
+pub fn current_time(backtrace: &NixBacktrace) -> NixResult {
+    impurities::record(backtrace, Impurity::CurrentTime);
+
+    let time = CURRENT_TIME.with(|time| {
+        *time.get_or_init(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |time| time.as_secs() as i64)
         })
-        .collect::<NixResult<Vec<_>>>()?;
-    Ok(NixValue::String(list.join(&sep)).wrap())
+    });
+
+    Ok(NixValue::Int(time).wrap())
+}
+
+#[builtin]
+pub fn derivation(backtrace: &NixBacktrace, attrs: NixValueWrapped) {
+    let Some(attrs) = attrs.borrow().as_attr_set().cloned() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "derivation: expected a set but found a {}",
+                attrs.borrow().as_type()
+            ),
+        ));
+    };
+
+    derivation::derivation(backtrace, &attrs)
+}
+
+#[builtin]
+pub fn derivation_strict(backtrace: &NixBacktrace, attrs: NixValueWrapped) {
+    let Some(attrs) = attrs.borrow().as_attr_set().cloned() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "derivationStrict: expected a set but found a {}",
+                attrs.borrow().as_type()
+            ),
+        ));
+    };
+
+    Ok(NixValue::AttrSet(derivation::derivation_strict(backtrace, &attrs)?).wrap())
+}
+
+#[builtin]
+pub fn deep_seq(backtrace: &NixBacktrace, first: NixVar, second: NixValueWrapped) {
+    first.resolve_set(true, backtrace)?;
+
+    Ok(second)
 }
 
 #[builtin]
 pub fn dir_of(s: NixValueWrapped) {
     let s = s.borrow();
+    let context = s.as_nix_string().cloned().unwrap_or_default();
     let Some(s) = s.as_path() else {
         todo!("Error Handling: dirOf cannot convert into path");
     };
@@ -177,7 +480,12 @@ pub fn dir_of(s: NixValueWrapped) {
         todo!("Error Handling: dirOf cannot get str from path");
     };
 
-    Ok(NixValue::String(s.to_owned()).wrap())
+    Ok(NixValue::String(context.with_value(s.to_owned())).wrap())
+}
+
+#[builtin]
+pub fn div(backtrace: &NixBacktrace, lhs: NixValueWrapped, rhs: NixValueWrapped) {
+    arith(backtrace, NixArithOp::Div, lhs, rhs)
 }
 
 #[builtin]
@@ -200,6 +508,88 @@ pub fn elemAt(backtrace: &NixBacktrace, xs: NixList, x: usize) {
         .resolve(backtrace)
 }
 
+/// A URL or `{ url; name ? ...; sha256 ? null; }`
+fn url_input(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    input: &NixValue,
+) -> NixResult<(String, Option<String>, Option<String>)> {
+    match input {
+        NixValue::AttrSet(input) => fetchers::url_input(backtrace, builtin, input),
+        NixValue::String(url) => Ok((url.to_string(), None, None)),
+        input => Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "{builtin}: expected a string or a set but found a {}",
+                input.as_type()
+            ),
+        )),
+    }
+}
+
+#[builtin("fetchGit")]
+pub fn fetch_git(backtrace: &NixBacktrace, input: NixValueWrapped) {
+    let input = match &*input.borrow() {
+        NixValue::AttrSet(input) => input.clone(),
+        NixValue::String(url) => {
+            NixAttrSet::from([("url".to_owned(), NixValue::String(url.clone()).wrap_var())])
+        }
+        NixValue::Path(path) => NixAttrSet::from([(
+            "url".to_owned(),
+            NixValue::String(path.display().to_string().into()).wrap_var(),
+        )]),
+        input => {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "fetchGit: expected a string or a set but found a {}",
+                    input.as_type()
+                ),
+            ))
+        }
+    };
+
+    Ok(NixValue::AttrSet(fetchers::fetch_git(backtrace, &input)?).wrap())
+}
+
+#[builtin("fetchTarball")]
+pub fn fetch_tarball(backtrace: &NixBacktrace, input: NixValueWrapped) {
+    let (url, name, sha256) = url_input(backtrace, "fetchTarball", &input.borrow())?;
+
+    let store_path = fetchers::fetch_tarball(backtrace, &url, name, sha256)?;
+    let context = NixStringContext::from([NixContextElem::Opaque(store_path.clone())]);
+
+    Ok(NixValue::String(NixString::new(store_path, context)).wrap())
+}
+
+#[builtin]
+pub fn fetch_tree(backtrace: &NixBacktrace, input: NixValueWrapped) {
+    let Some(input) = input.borrow().as_attr_set().cloned() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "fetchTree: expected a set but found {}",
+                input.borrow().as_type_with_article()
+            ),
+        ));
+    };
+
+    Ok(NixValue::AttrSet(fetchers::fetch_tree(backtrace, &input)?).wrap())
+}
+
+#[builtin("fetchurl")]
+pub fn fetch_url(backtrace: &NixBacktrace, input: NixValueWrapped) {
+    let (url, name, sha256) = url_input(backtrace, "fetchurl", &input.borrow())?;
+
+    let store_path = fetchers::fetch_url(backtrace, &url, name, sha256)?;
+    let context = NixStringContext::from([NixContextElem::Opaque(store_path.clone())]);
+
+    Ok(NixValue::String(NixString::new(store_path, context)).wrap())
+}
+
 #[builtin]
 pub fn filter(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     let mut out = Vec::with_capacity(list.0.len());
@@ -221,6 +611,56 @@ pub fn filter(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
+#[builtin]
+pub fn filter_source(backtrace: &NixBacktrace, filter: NixLambda, path: PathBuf) {
+    add_path(backtrace, "filterSource", &path, None, Some(&filter), None)
+}
+
+fn toml_to_nix(value: toml::Value) -> NixValue {
+    match value {
+        toml::Value::String(s) => NixValue::String(s.into()),
+        toml::Value::Integer(i) => NixValue::Int(i),
+        toml::Value::Float(f) => NixValue::Float(f),
+        toml::Value::Boolean(b) => NixValue::Bool(b),
+        toml::Value::Datetime(datetime) => NixValue::String(datetime.to_string().into()),
+        toml::Value::Array(array) => NixValue::List(NixList(Rc::new(
+            array
+                .into_iter()
+                .map(|value| toml_to_nix(value).wrap_var())
+                .collect(),
+        ))),
+        toml::Value::Table(table) => NixValue::AttrSet(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, toml_to_nix(value).wrap_var()))
+                .collect(),
+        ),
+    }
+}
+
+#[builtin("fromTOML")]
+pub fn from_toml(backtrace: &NixBacktrace, content: String) {
+    let table = content.parse::<toml::Table>().map_err(|err| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("while parsing TOML: {}", err.to_string().trim_end()),
+        )
+    })?;
+
+    Ok(toml_to_nix(toml::Value::Table(table)).wrap())
+}
+
+#[builtin]
+pub fn floor(backtrace: &NixBacktrace, number: NixNumber) {
+    let value = match number {
+        NixNumber::Int(int) => int,
+        NixNumber::Float(float) => float_to_int(backtrace, "floor", float.floor())?,
+    };
+
+    Ok(NixValue::Int(value).wrap())
+}
+
 #[builtin]
 pub fn function_args(callback: NixLambda) {
     match callback {
@@ -253,56 +693,144 @@ pub fn gen_list(backtrace: &NixBacktrace, callback: NixLambda, size: i64) {
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
-fn hash_var(backtrace: &NixBacktrace, var: &NixVar, hasher: &mut impl Hasher) -> NixResult<u64> {
-    match &*var.resolve(backtrace)?.borrow() {
-        NixValue::AttrSet(_) => todo!("Error handling: Cannot hash AttrSet"),
-        NixValue::Lambda(_) => todo!("Error handling: Cannot hash Lambda"),
-        NixValue::Path(_) => todo!("Error handling: Cannot hash Path"),
-        NixValue::String(_) => todo!("Error handling: Cannot hash String"),
+/// `key` of a `genericClosure` element, ints and floats of the same value are
+/// the same key (`1` and `1.0`)
+enum ClosureKey {
+    Null,
+    Bool(bool),
+    Number(NixNumber),
+    String(String),
+    Path(PathBuf),
+    List(Vec<ClosureKey>),
+}
+
+impl ClosureKey {
+    fn from_var(backtrace: &NixBacktrace, var: &NixVar) -> NixResult<Self> {
+        let value = var.resolve(backtrace)?;
+        let value = value.borrow();
+
+        Ok(match &*value {
+            NixValue::Null => ClosureKey::Null,
+            NixValue::Bool(bool) => ClosureKey::Bool(*bool),
+            NixValue::Int(int) => ClosureKey::Number(NixNumber::Int(*int)),
+            NixValue::Float(float) => ClosureKey::Number(NixNumber::Float(*float)),
+            NixValue::String(string) => ClosureKey::String(string.to_string()),
+            NixValue::Path(path) => ClosureKey::Path(path.clone()),
+            NixValue::List(list) => ClosureKey::List(
+                list.0
+                    .iter()
+                    .map(|item| ClosureKey::from_var(backtrace, item))
+                    .collect::<NixResult<_>>()?,
+            ),
+            NixValue::AttrSet(_) | NixValue::Lambda(_) => {
+                return Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!(
+                        "genericClosure: a {} cannot be used as a key",
+                        value.as_type()
+                    ),
+                ))
+            }
+        })
+    }
 
-        NixValue::Bool(e) => e.hash(hasher),
-        NixValue::Float(e) => (*e as u64).hash(hasher),
-        NixValue::Int(e) => e.hash(hasher),
-        NixValue::List(e) => {
-            e.0.iter()
-                .map(|i| hash_var(backtrace, i, hasher).map(|_| {}))
-                .collect::<NixResult<()>>()?
+    fn kind(&self) -> u8 {
+        match self {
+            ClosureKey::Null => 0,
+            ClosureKey::Bool(_) => 1,
+            ClosureKey::Number(_) => 2,
+            ClosureKey::String(_) => 3,
+            ClosureKey::Path(_) => 4,
+            ClosureKey::List(_) => 5,
         }
-        NixValue::Null => hasher.write(&[0]),
-    };
+    }
+}
+
+impl Ord for ClosureKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (ClosureKey::Bool(lhs), ClosureKey::Bool(rhs)) => lhs.cmp(rhs),
+            (ClosureKey::Number(NixNumber::Int(lhs)), ClosureKey::Number(NixNumber::Int(rhs))) => {
+                lhs.cmp(rhs)
+            }
+            (ClosureKey::Number(lhs), ClosureKey::Number(rhs)) => {
+                let as_float = |number: &NixNumber| match *number {
+                    NixNumber::Int(int) => int as f64,
+                    NixNumber::Float(float) => float,
+                };
+
+                as_float(lhs).total_cmp(&as_float(rhs))
+            }
+            (ClosureKey::String(lhs), ClosureKey::String(rhs)) => lhs.cmp(rhs),
+            (ClosureKey::Path(lhs), ClosureKey::Path(rhs)) => lhs.cmp(rhs),
+            (ClosureKey::List(lhs), ClosureKey::List(rhs)) => lhs.cmp(rhs),
+            (lhs, rhs) => lhs.kind().cmp(&rhs.kind()),
+        }
+    }
+}
+
+impl PartialOrd for ClosureKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-    Ok(hasher.finish())
+impl PartialEq for ClosureKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
 }
 
+impl Eq for ClosureKey {}
+
 #[builtin]
 pub fn generic_closure(backtrace: &NixBacktrace, argument: NixValueWrapped) {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("genericClosure: {message}"),
+        )
+    };
+
     let argument = argument.borrow();
-    let argument = argument
-        .as_attr_set()
-        .ok_or_else(|| todo!("Error handling"))?;
+    let Some(argument) = argument.as_attr_set() else {
+        return Err(error(format!(
+            "expected a set but found {}",
+            argument.as_type_with_article()
+        )));
+    };
+
+    let get = |attr: &str| {
+        argument
+            .get(attr)
+            .ok_or_else(|| error(format!("missing the attribute '{attr}'")))?
+            .resolve(backtrace)
+    };
 
-    let start_set = argument
-        .get("startSet")
-        .ok_or_else(|| todo!("Error handling: Getting startSet"))?
-        .resolve(backtrace)?
-        .borrow()
-        .as_list()
-        .ok_or_else(|| todo!("Error handling"))?
-        .0;
+    let start_set = get("startSet")?;
+    let Some(start_set) = start_set.borrow().as_list() else {
+        return Err(error(format!(
+            "expected 'startSet' to be a list but found {}",
+            start_set.borrow().as_type_with_article()
+        )));
+    };
 
-    if start_set.is_empty() {
-        return Ok(NixValue::List(NixList(start_set)).wrap());
+    if start_set.0.is_empty() {
+        return Ok(NixValue::List(start_set).wrap());
     }
 
     let mut work_set = VecDeque::new();
-    work_set.extend(start_set.iter().cloned());
-
-    let op = argument
-        .get("operator")
-        .ok_or_else(|| todo!("Error handling: Getting startSet"))?
-        .resolve(backtrace)?;
-    let op = op.borrow();
-    let op = op.as_lambda().ok_or_else(|| todo!("Error handling"))?;
+    work_set.extend(start_set.0.iter().cloned());
+
+    let op = get("operator")?;
+    let Some(op) = op.borrow().as_lambda().cloned() else {
+        return Err(error(format!(
+            "expected 'operator' to be a function but found {}",
+            op.borrow().as_type_with_article()
+        )));
+    };
 
     /* Construct the closure by applying the operator to elements of
     `workSet', adding the result to `workSet', continuing until
@@ -312,18 +840,24 @@ pub fn generic_closure(backtrace: &NixBacktrace, argument: NixValueWrapped) {
     // `doneKeys' doesn't need to be a GC root, because its values are
     // reachable from res.
 
-    let mut done_keys = HashSet::new();
+    let mut done_keys = BTreeSet::new();
     while let Some(item) = work_set.pop_front() {
         let e = item.resolve(backtrace)?;
         let e = e.borrow();
-        let e = e.as_attr_set().ok_or_else(|| todo!("Error handling"))?;
+        let Some(e) = e.as_attr_set() else {
+            return Err(error(format!(
+                "expected the elements to be sets but found {}",
+                e.as_type_with_article()
+            )));
+        };
 
-        let key = e
-            .get("key")
-            .ok_or_else(|| todo!("Error handling: Getting key"))?;
+        let Some(key) = e.get("key") else {
+            return Err(error(String::from(
+                "an element is missing the attribute 'key'",
+            )));
+        };
 
-        let mut hasher = std::hash::DefaultHasher::new();
-        let key = hash_var(backtrace, key, &mut hasher)?;
+        let key = ClosureKey::from_var(backtrace, key)?;
 
         if !done_keys.insert(key) {
             continue;
@@ -332,12 +866,13 @@ pub fn generic_closure(backtrace: &NixBacktrace, argument: NixValueWrapped) {
         res.push(item.clone());
 
         /* Call the `operator' function with `e' as argument. */
-        let list = op
-            .call(backtrace, item.clone())?
-            .resolve(backtrace)?
-            .borrow()
-            .as_list()
-            .ok_or_else(|| todo!("Error handling: Cast as list"))?;
+        let list = op.call(backtrace, item.clone())?.resolve(backtrace)?;
+        let Some(list) = list.borrow().as_list() else {
+            return Err(error(format!(
+                "expected 'operator' to return a list but it returned {}",
+                list.borrow().as_type_with_article()
+            )));
+        };
 
         work_set.extend(list.0.iter().cloned());
     }
@@ -345,87 +880,255 @@ pub fn generic_closure(backtrace: &NixBacktrace, argument: NixValueWrapped) {
     Ok(NixValue::List(NixList(res.into())).wrap())
 }
 
-#[builtin()]
-pub fn get_env(env: String) {
-    let value = std::env::var(env).unwrap_or_default();
+#[builtin]
+pub fn get_attr(backtrace: &NixBacktrace, name: String, set: NixValueWrapped) {
+    let value = set.borrow().get(backtrace, &name)?;
+
+    let Some(value) = value else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::AttributeMissing,
+            format!("Attribute '\x1b[1;95m{name}\x1b[0m' missing"),
+        ));
+    };
 
-    Ok(NixValue::String(value).wrap())
+    value.resolve(backtrace)
 }
 
-fn intern_hash(ty: &str, bytes: &[u8]) -> String {
-    let algorithm = match ty {
-        "md5" => hash::Algorithm::MD5,
-        "sha1" => hash::Algorithm::SHA1,
-        "sha256" => hash::Algorithm::SHA256,
-        "sha512" => hash::Algorithm::SHA512,
-        _ => todo!("Error Handling: hashFile incompatible hash type"),
-    };
+#[builtin]
+pub fn get_context(s: NixString) {
+    // Elements are sorted by path, each group becomes one attribute
+    let mut out = NixAttrSet::new();
+    let mut info = NixAttrSet::new();
+    let mut outputs = vec![];
 
-    hash::hex_digest(algorithm, bytes)
-}
+    let mut elems = s.context().iter().peekable();
 
-#[builtin()]
-pub fn hash_file(t: String, p: NixValueWrapped) {
-    let Some(path) = p.borrow().as_path() else {
-        todo!("Error Handling: hashFile cannot convert into path");
-    };
-    let Ok(content) = std::fs::read(path) else {
-        todo!("Error Handling: hashFile cannot read file");
-    };
+    while let Some(elem) = elems.next() {
+        match elem {
+            NixContextElem::Opaque(_) => {
+                info.insert("path".to_owned(), NixValue::Bool(true).wrap_var());
+            }
+            NixContextElem::DrvDeep(_) => {
+                info.insert("allOutputs".to_owned(), NixValue::Bool(true).wrap_var());
+            }
+            NixContextElem::Built { output, .. } => {
+                outputs.push(NixValue::String(output.as_str().into()).wrap_var());
+            }
+        }
 
-    let value = intern_hash(&t, &content);
-    Ok(NixValue::String(value).wrap())
-}
+        if elems.peek().is_some_and(|next| next.path() == elem.path()) {
+            continue;
+        }
 
-#[builtin]
-pub fn import(backtrace: &NixBacktrace, argument: NixValueWrapped) {
-    let argument = argument.borrow();
+        if !outputs.is_empty() {
+            let outputs = NixList(Rc::new(std::mem::take(&mut outputs)));
+            info.insert("outputs".to_owned(), NixValue::List(outputs).wrap_var());
+        }
 
-    let path = match *argument {
-        NixValue::AttrSet(ref set) => {
-            let is_flake = if let Some(ty) = set.get("_type") {
-                ty.resolve(backtrace)?
-                    .borrow()
-                    .cast_to_string()
-                    .eq(&Some("flake".to_owned()))
-            } else {
-                false
-            };
+        let info = NixValue::AttrSet(std::mem::take(&mut info)).wrap_var();
+        out.insert(elem.path().to_owned(), info);
+    }
 
-            if !is_flake {
-                todo!("Cannot import attr set");
-            }
+    Ok(NixValue::AttrSet(out).wrap())
+}
 
-            let out_path = set.get("outPath").expect("Flake should have outPath");
-            let out_path = out_path.resolve(backtrace)?;
-            let out_path = out_path.borrow();
+#[builtin()]
+pub fn get_env(backtrace: &NixBacktrace, env: String) {
+    if impurities::options().pure {
+        log::warn(&format!(
+            "getEnv: '{env}' is not available in pure evaluation mode, using \"\""
+        ));
 
-            let NixValue::Path(ref path) = *out_path else {
-                todo!("Error handling");
-            };
+        return Ok(NixValue::String("".into()).wrap());
+    }
 
-            path.join("default.nix")
+    impurities::record(backtrace, Impurity::EnvVar(env.clone()));
+
+    // Unset variables are empty, like in Nix
+    let value = match std::env::var(&env) {
+        Ok(value) => value,
+        Err(std::env::VarError::NotPresent) => String::new(),
+        Err(std::env::VarError::NotUnicode(_)) => {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!("getEnv: the environment variable '{env}' is not valid UTF-8"),
+            ))
         }
-        NixValue::Path(ref path) => path.clone(),
-        NixValue::String(ref path) => path.into(),
-        _ => todo!("Error handling"),
     };
 
-    Scope::import_path(backtrace, path)
+    Ok(NixValue::String(value.into()).wrap())
 }
 
-#[builtin]
-/// Log a variable and return it
-pub fn inspect(backtrace: &NixBacktrace, argument: NixVar) {
-    let argument = argument.resolve_set(true, backtrace)?;
-    println!("{argument:#?}");
-    Ok(argument)
+#[builtin()]
+pub fn get_flake(backtrace: &NixBacktrace, flake_ref: String) {
+    Ok(NixValue::AttrSet(flake::get_flake(backtrace, &flake_ref)?).wrap())
 }
 
 #[builtin]
-pub fn is_attrs(argument: NixValueWrapped) {
-    Ok(NixValue::Bool(argument.borrow().is_attr_set()).wrap())
-}
+pub fn group_by(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
+    let mut groups: HashMap<String, Vec<NixVar>> = HashMap::new();
+
+    for item in list.0.iter() {
+        let key = callback.call(backtrace, item.clone())?.resolve(backtrace)?;
+
+        let Some(key) = key.borrow().as_string().cloned() else {
+            let ty = key.borrow().as_type();
+
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!("groupBy: expected the function to return a string, got {ty}"),
+            ));
+        };
+
+        groups.entry(key).or_default().push(item.clone());
+    }
+
+    let set = groups
+        .into_iter()
+        .map(|(key, items)| (key, NixValue::List(NixList(Rc::new(items))).wrap_var()))
+        .collect::<NixAttrSet>();
+
+    Ok(NixValue::AttrSet(set).wrap())
+}
+
+#[builtin]
+pub fn has_attr(backtrace: &NixBacktrace, name: String, set: NixValueWrapped) {
+    let has_attr = set.borrow().get(backtrace, &name)?.is_some();
+
+    Ok(NixValue::Bool(has_attr).wrap())
+}
+
+#[builtin]
+pub fn has_context(s: NixString) {
+    Ok(NixValue::Bool(s.has_context()).wrap())
+}
+
+fn hash_algorithm(backtrace: &NixBacktrace, ty: &str) -> NixResult<hash::Algorithm> {
+    hash::Algorithm::from_name(ty).ok_or_else(|| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "unknown hash algorithm '{ty}', expected one of: {}",
+                hash::Algorithm::ALL.map(hash::Algorithm::name).join(", ")
+            ),
+        )
+    })
+}
+
+fn intern_hash(backtrace: &NixBacktrace, ty: &str, bytes: &[u8]) -> NixResult<String> {
+    Ok(hash::hex_digest(hash_algorithm(backtrace, ty)?, bytes))
+}
+
+#[builtin()]
+pub fn hash_file(backtrace: &NixBacktrace, t: String, p: NixValueWrapped) {
+    let path = path_argument(backtrace, "hashFile", &p.borrow())?;
+
+    impurities::record_path(backtrace, &path);
+
+    let content =
+        std::fs::read(&path).map_err(|err| read_error(backtrace, "hashFile", &path, err))?;
+
+    let value = intern_hash(backtrace, &t, &content)?;
+    Ok(NixValue::String(value.into()).wrap())
+}
+
+#[builtin]
+pub fn hash_string(backtrace: &NixBacktrace, t: String, s: String) {
+    let value = intern_hash(backtrace, &t, s.as_bytes())?;
+    Ok(NixValue::String(value.into()).wrap())
+}
+
+#[builtin]
+pub fn import(backtrace: &NixBacktrace, argument: NixValueWrapped) {
+    let argument = argument.borrow();
+
+    let path = match *argument {
+        NixValue::AttrSet(ref set) => {
+            let is_flake = if let Some(ty) = set.get("_type") {
+                ty.resolve(backtrace)?
+                    .borrow()
+                    .cast_to_string()
+                    .eq(&Some("flake".to_owned()))
+            } else {
+                false
+            };
+
+            if !is_flake {
+                todo!("Cannot import attr set");
+            }
+
+            let out_path = set.get("outPath").expect("Flake should have outPath");
+            let out_path = out_path.resolve(backtrace)?;
+            let out_path = out_path.borrow();
+
+            let NixValue::Path(ref path) = *out_path else {
+                todo!("Error handling");
+            };
+
+            path.join("default.nix")
+        }
+        NixValue::Path(ref path) => path.clone(),
+        NixValue::String(ref path) => store::to_local_path(path),
+        _ => todo!("Error handling"),
+    };
+
+    Scope::import_path(backtrace, path)
+}
+
+#[builtin]
+/// Log a variable and return it
+pub fn inspect(backtrace: &NixBacktrace, argument: NixVar) {
+    let argument = argument.resolve_set(true, backtrace)?;
+    println!("{argument:#?}");
+    Ok(argument)
+}
+
+#[builtin]
+pub fn intersect_attrs(backtrace: &NixBacktrace, e1: NixValueWrapped, e2: NixValueWrapped) {
+    let expected_set = |argument: &str, value: &NixValue| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "intersectAttrs: expected the {argument} argument to be a set but found {}",
+                value.as_type_with_article()
+            ),
+        )
+    };
+
+    let e1 = e1.borrow();
+    let Some(e1) = e1.as_attr_set() else {
+        return Err(expected_set("first", &e1));
+    };
+
+    let e2 = e2.borrow();
+    let Some(e2) = e2.as_attr_set() else {
+        return Err(expected_set("second", &e2));
+    };
+
+    // Iterate the smaller set and probe the other, values are always taken from `e2`
+    let set = if e1.len() < e2.len() {
+        e1.keys()
+            .filter_map(|key| Some((key.clone(), e2.get(key)?.clone())))
+            .collect::<NixAttrSet>()
+    } else {
+        e2.iter()
+            .filter(|(key, _)| e1.contains_key(*key))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<NixAttrSet>()
+    };
+
+    Ok(NixValue::AttrSet(set).wrap())
+}
+
+#[builtin]
+pub fn is_attrs(argument: NixValueWrapped) {
+    Ok(NixValue::Bool(argument.borrow().is_attr_set()).wrap())
+}
 
 #[builtin]
 pub fn is_bool(argument: NixValueWrapped) {
@@ -472,41 +1175,62 @@ pub fn length(list: NixList) {
     Ok(NixValue::Int(list.0.len() as i64).wrap())
 }
 
+#[builtin]
+pub fn less_than(backtrace: &NixBacktrace, lhs: NixValueWrapped, rhs: NixValueWrapped) {
+    lhs.borrow()
+        .less_than(&rhs.borrow(), backtrace)?
+        .map(|less| NixValue::Bool(less).wrap())
+        .map_err(|message| backtrace.to_error(NixLabelKind::Error, NixLabelMessage::Empty, message))
+}
+
 #[builtin]
 pub fn list_to_attrs(backtrace: &NixBacktrace, list: NixList) {
-    let out = list
-        .0
-        .iter()
-        .map(|item| {
-            let (name, value) = {
-                let item = item.resolve(backtrace)?;
-                let item = item.borrow();
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("listToAttrs: {message}"),
+        )
+    };
 
-                let Some(set) = item.as_attr_set() else {
-                    todo!("Error handling!");
-                };
+    let mut out = NixAttrSet::new();
 
-                (set.get("name").cloned(), set.get("value").cloned())
-            };
+    // The first binding of a name wins and values are never forced, like in Nix
+    for (idx, item) in list.0.iter().enumerate() {
+        let item = item.resolve(backtrace)?;
+        let item = item.borrow();
 
-            let Some(name) = name else {
-                todo!("Error handling!");
-            };
+        let Some(set) = item.as_attr_set() else {
+            return Err(error(format!(
+                "expected the element {idx} to be a set, got {}",
+                item.as_type()
+            )));
+        };
 
-            let name = name.resolve(backtrace)?;
+        let Some(name) = set.get("name") else {
+            return Err(error(format!("the element {idx} has no 'name' attribute")));
+        };
 
-            let name = match &*name.borrow() {
-                NixValue::String(ref s) => s.clone(),
-                _ => todo!("Error handling!"),
-            };
+        let name = name.resolve(backtrace)?;
+        let name = name.borrow();
 
-            let Some(value) = value else {
-                todo!("Error handling!");
-            };
+        let Some(name) = name.as_string() else {
+            return Err(error(format!(
+                "expected the 'name' of the element {idx} to be a string, got {}",
+                name.as_type()
+            )));
+        };
 
-            Ok((name, value))
-        })
-        .collect::<NixResult<NixAttrSet>>()?;
+        if out.contains_key(name) {
+            continue;
+        }
+
+        let Some(value) = set.get("value") else {
+            return Err(error(format!("the element {idx} has no 'value' attribute")));
+        };
+
+        out.insert(name.clone(), value.clone());
+    }
 
     Ok(NixValue::AttrSet(out).wrap())
 }
@@ -527,93 +1251,552 @@ pub fn map(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
 #[builtin]
 pub fn map_attrs(backtrace: &NixBacktrace, callback: NixLambda, set: NixValueWrapped) {
     let set = set.borrow();
+
     let Some(set) = set.as_attr_set() else {
-        todo!("Error handling");
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("mapAttrs: expected a set, got {}", set.as_type()),
+        ));
     };
 
     let mut out = NixAttrSet::new();
 
+    // Each value is `callback name value`, applied only when it is forced
     for (key, value) in set.iter() {
-        let callback = callback
-            .call(backtrace, NixValue::String(key.clone()).wrap_var())?
-            .resolve(backtrace)?;
-        let callback = callback.borrow();
-        let Some(callback) = callback.as_lambda() else {
-            todo!("Error handling")
-        };
+        let callback = callback.clone();
+        let name = key.clone();
+        let value = value.clone();
+
+        let value = LazyNixValue::new_eval(
+            backtrace.clone(),
+            Box::new(move |backtrace| {
+                let callback = callback
+                    .call(backtrace, NixValue::String(name.into()).wrap_var())?
+                    .resolve(backtrace)?;
+
+                let Some(callback) = callback.borrow().as_lambda().cloned() else {
+                    return Err(backtrace.to_error(
+                        NixLabelKind::Error,
+                        NixLabelMessage::Empty,
+                        format!(
+                            "mapAttrs: expected the callback to take two arguments, it returned {}",
+                            callback.borrow().as_type()
+                        ),
+                    ));
+                };
 
-        let value = callback.call(backtrace, value.clone())?;
+                callback.call(backtrace, value)?.resolve(backtrace)
+            }),
+        )
+        .wrap_var();
 
-        out.insert(key.clone(), value);
+        match set.position(key) {
+            Some(position) => out.insert_with_position(key.clone(), value, position.clone()),
+            None => out.insert(key.clone(), value),
+        };
     }
 
     Ok(NixValue::AttrSet(out).wrap())
 }
 
+/// Most regexes compiled at once, the least recently used is dropped
+const REGEX_CACHE_SIZE: usize = 512;
+
+/// `match` and `split` are called in loops with the same patterns
+#[derive(Default)]
+struct RegexCache {
+    tick: u64,
+    /// Each regex with when it was last used
+    regexes: HashMap<String, (regex::Regex, u64)>,
+}
+
+impl RegexCache {
+    fn get(&mut self, pattern: &str) -> Option<regex::Regex> {
+        self.tick += 1;
+
+        let (regex, last_used) = self.regexes.get_mut(pattern)?;
+        *last_used = self.tick;
+
+        Some(regex.clone())
+    }
+
+    fn insert(&mut self, pattern: String, regex: regex::Regex) {
+        if self.regexes.len() >= REGEX_CACHE_SIZE {
+            let oldest = self
+                .regexes
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(pattern, _)| pattern.clone());
+
+            if let Some(oldest) = oldest {
+                self.regexes.remove(&oldest);
+            }
+        }
+
+        self.regexes.insert(pattern, (regex, self.tick));
+    }
+}
+
+thread_local! {
+    static REGEX_CACHE: RefCell<RegexCache> = RefCell::new(RegexCache::default());
+}
+
+/// `anchored` regexes have to match the whole string, like the ones of `match`
+fn compile_regex(backtrace: &NixBacktrace, regex: &str, anchored: bool) -> NixResult<regex::Regex> {
+    let pattern = if anchored {
+        format!("^(?:{regex})$")
+    } else {
+        regex.to_owned()
+    };
+
+    if let Some(compiled) = REGEX_CACHE.with_borrow_mut(|cache| cache.get(&pattern)) {
+        return Ok(compiled);
+    }
+
+    let compiled = regex::Regex::new(&pattern).map_err(|_| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("invalid regular expression '{regex}'"),
+        )
+    })?;
+
+    // Only the misses are traced, so it shows that each regex is compiled once
+    if log::is_verbose() {
+        log::trace(&format!("compiled the regular expression '{regex}'"));
+    }
+
+    REGEX_CACHE.with_borrow_mut(|cache| cache.insert(pattern, compiled.clone()));
+
+    Ok(compiled)
+}
+
+/// Capture groups as a list, unmatched groups are `null`
+fn captures_to_list(content: &NixString, captures: regex::Captures) -> NixValue {
+    NixValue::List(NixList(Rc::new(
+        captures
+            .iter()
+            .skip(1)
+            .map(|c| {
+                c.map(|c| NixValue::String(content.with_value(c.as_str().to_owned())))
+                    .unwrap_or_default()
+                    .wrap_var()
+            })
+            .collect::<Vec<_>>(),
+    )))
+}
+
+#[builtin]
+pub fn mul(backtrace: &NixBacktrace, lhs: NixValueWrapped, rhs: NixValueWrapped) {
+    arith(backtrace, NixArithOp::Mul, lhs, rhs)
+}
+
 #[builtin]
-pub fn r#match(regex: String, content: String) {
-    // TODO: Should do a regex caching, specially for loop optimisation
-    let regex = regex::Regex::new(&regex).unwrap();
+pub fn r#match(backtrace: &NixBacktrace, regex: String, content: NixString) {
+    // The whole string has to match
+    let regex = compile_regex(backtrace, &regex, true)?;
 
     Ok(regex
         .captures(content.as_str())
-        .map(|c| {
-            NixValue::List(NixList(Rc::new(
-                c.iter()
-                    .skip(1)
-                    .map(|c| {
-                        c.map(|c| c.as_str())
-                            .map(String::from)
-                            .map(NixValue::String)
-                            .unwrap_or_default()
-                            .wrap_var()
-                    })
-                    .collect::<Vec<_>>(),
-            )))
-        })
+        .map(|captures| captures_to_list(&content, captures))
         .unwrap_or_default()
         .wrap())
 }
 
-#[builtin()]
-pub fn path_exists(path: PathBuf) {
-    let exists = path.try_exists().is_ok_and(|x| x);
+#[builtin]
+pub fn parse_drv_name(s: String) {
+    let (name, version) = version::parse_drv_name(&s);
 
-    Ok(NixValue::Bool(exists).wrap())
+    let mut out = NixAttrSet::new();
+    out.insert("name".to_owned(), NixValue::String(name.into()).wrap_var());
+    out.insert(
+        "version".to_owned(),
+        NixValue::String(version.into()).wrap_var(),
+    );
+
+    Ok(NixValue::AttrSet(out).wrap())
 }
 
 #[builtin]
-pub fn read_file(path: NixValueWrapped) {
-    let path = path.borrow();
-    let Some(path) = path.as_path() else {
-        todo!("Error Handling");
+pub fn partition(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
+    let mut right = vec![];
+    let mut wrong = vec![];
+
+    for value in list.0.iter() {
+        let item = callback
+            .call(backtrace, value.clone())?
+            .resolve(backtrace)?;
+
+        let Some(item) = item.borrow().as_bool() else {
+            let ty = item.borrow().as_type();
+
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!("partition: expected the function to return a bool, got {ty}"),
+            ));
+        };
+
+        if item {
+            right.push(value.clone());
+        } else {
+            wrong.push(value.clone());
+        }
+    }
+
+    let mut result = NixAttrSet::new();
+    result.insert(
+        "right".to_owned(),
+        NixValue::List(NixList(Rc::new(right))).wrap_var(),
+    );
+    result.insert(
+        "wrong".to_owned(),
+        NixValue::List(NixList(Rc::new(wrong))).wrap_var(),
+    );
+
+    Ok(NixValue::AttrSet(result).wrap())
+}
+
+/// The path a file builtin reads, strings are fine too
+fn path_argument(backtrace: &NixBacktrace, builtin: &str, value: &NixValue) -> NixResult<PathBuf> {
+    value.as_path().ok_or_else(|| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("{builtin}: expected a path but found a {}", value.as_type()),
+        )
+    })
+}
+
+fn read_error(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    path: &std::path::Path,
+    err: std::io::Error,
+) -> crate::NixError {
+    backtrace.to_error(
+        NixLabelKind::Error,
+        NixLabelMessage::Empty,
+        format!("{builtin}: cannot read '{}': {err}", path.display()),
+    )
+}
+
+/// The type of `path` as `readDir` and source filters name it, symlinks are
+/// reported as such, not followed
+fn file_type_name(path: &std::path::Path) -> std::io::Result<&'static str> {
+    let file_type = std::fs::symlink_metadata(path)?.file_type();
+
+    Ok(if file_type.is_symlink() {
+        "symlink"
+    } else if file_type.is_dir() {
+        "directory"
+    } else if file_type.is_file() {
+        "regular"
+    } else {
+        "unknown"
+    })
+}
+
+/// Ask `filter` about everything under `path`, the rejected entries end up in
+/// `excluded` and rejected directories aren't entered
+fn filter_tree(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    filter: &NixLambda,
+    path: &std::path::Path,
+    excluded: &mut HashSet<PathBuf>,
+) -> NixResult<()> {
+    let io_error = |err: std::io::Error| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("{builtin}: cannot read '{}': {err}", path.display()),
+        )
     };
-    let Ok(content) = std::fs::read_to_string(path) else {
-        todo!("Error Handling");
+
+    for entry in std::fs::read_dir(path).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?.path();
+        let ty = file_type_name(&entry).map_err(io_error)?;
+
+        let partial = filter
+            .call(
+                backtrace,
+                NixValue::String(entry.display().to_string().into()).wrap_var(),
+            )?
+            .resolve(backtrace)?;
+
+        let keep = match partial.borrow().as_lambda() {
+            Some(partial) => partial
+                .call(backtrace, NixValue::String(ty.into()).wrap_var())?
+                .resolve(backtrace)?,
+            None => partial.clone(),
+        };
+
+        let Some(keep) = keep.borrow().as_bool() else {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "{builtin}: the filter must return a bool, but it returned a {}",
+                    keep.borrow().as_type()
+                ),
+            ));
+        };
+
+        if !keep {
+            excluded.insert(entry);
+        } else if ty == "directory" {
+            filter_tree(backtrace, builtin, filter, &entry, excluded)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Add `path` to the store like `builtins.path` does, the result is its store
+/// path with itself as context
+fn add_path(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    path: &std::path::Path,
+    name: Option<String>,
+    filter: Option<&NixLambda>,
+    expected_hash: Option<String>,
+) -> NixResult {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("{builtin}: {message}"),
+        )
     };
 
-    Ok(NixValue::String(content).wrap())
+    if !path.is_absolute() {
+        return Err(error(format!(
+            "string '{}' doesn't represent an absolute path",
+            path.display()
+        )));
+    }
+
+    let name = name.unwrap_or_else(|| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+
+    if let Err(err) = store::check_name(&name) {
+        return Err(error(err));
+    }
+
+    impurities::record_path(backtrace, path);
+
+    let mut excluded = HashSet::new();
+
+    if let Some(filter) = filter {
+        if file_type_name(path).ok() == Some("directory") {
+            filter_tree(backtrace, builtin, filter, path, &mut excluded)?;
+        }
+    }
+
+    let nar_hash = store::nar::hash_path_filtered(path, &|path| !excluded.contains(path))
+        .map_err(|err| error(format!("cannot read '{}': {err}", path.display())))?;
+
+    if let Some(expected) = expected_hash {
+        let Some(expected) = store::parse_sha256(&expected) else {
+            return Err(error(format!("invalid SHA-256 hash '{expected}'")));
+        };
+
+        if expected != nar_hash {
+            return Err(error(format!(
+                "hash mismatch for '{}', expected '{}' but got '{}'",
+                path.display(),
+                fetchers::to_sri(&expected),
+                fetchers::to_sri(&nar_hash)
+            )));
+        }
+    }
+
+    let store_path = store::add_source(path, &name, &nar_hash);
+    let context = NixStringContext::from([NixContextElem::Opaque(store_path.clone())]);
+
+    Ok(NixValue::String(NixString::new(store_path, context)).wrap())
 }
 
+/// `builtins.path { path; name ? baseNameOf path; filter ? null; sha256 ? null; }`
 #[builtin]
-pub fn read_file_type(path: NixValueWrapped) {
-    let path = path.borrow();
-    let Some(path) = path.as_path() else {
-        todo!("Error Handling");
+pub fn path(backtrace: &NixBacktrace, argument: NixValueWrapped) {
+    let argument = argument.borrow();
+
+    let Some(argument) = argument.as_attr_set() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("path: expected a set but found a {}", argument.as_type()),
+        ));
     };
-    let Ok(metadata) = std::fs::metadata(path) else {
-        todo!("Error Handling");
+
+    let mut path = None;
+    let mut name = None;
+    let mut filter = None;
+    let mut sha256 = None;
+
+    for (key, value) in argument {
+        let value = value.resolve(backtrace)?;
+        let value = value.borrow();
+
+        let expected = match (key.as_str(), &*value) {
+            ("path", value) => {
+                path = value.as_path();
+                path.is_none().then_some("a path")
+            }
+            ("name", value) => {
+                name = value.cast_to_string();
+                name.is_none().then_some("a string")
+            }
+            ("filter", value) => {
+                filter = value.as_lambda().cloned();
+                filter.is_none().then_some("a function")
+            }
+            ("sha256", NixValue::Null) => None,
+            ("sha256", value) => {
+                sha256 = value.cast_to_string();
+                sha256.is_none().then_some("a string")
+            }
+            ("recursive", NixValue::Bool(true)) => None,
+            ("recursive", _) => Some("true (flat paths aren't supported)"),
+            (key, _) => {
+                return Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!("path: unsupported argument '{key}'"),
+                ))
+            }
+        };
+
+        if let Some(expected) = expected {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "path: expected '{key}' to be {expected} but found a {}",
+                    value.as_type()
+                ),
+            ));
+        }
+    }
+
+    let Some(path) = path else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            "path: the 'path' attribute is required",
+        ));
     };
-    let res = if metadata.is_dir() {
-        "directory"
-    } else if metadata.is_symlink() {
-        "symlink"
-    } else if metadata.is_file() {
-        "regular"
+
+    add_path(backtrace, "path", &path, name, filter.as_ref(), sha256)
+}
+
+#[builtin()]
+pub fn path_exists(backtrace: &NixBacktrace, path: NixValueWrapped) {
+    let path = path.borrow();
+
+    // A string ending in a slash has to be a directory
+    let must_be_dir = path
+        .as_string()
+        .is_some_and(|path| path.ends_with('/') || path.ends_with("/."));
+
+    let mut path = path_argument(backtrace, "pathExists", &path)?;
+
+    // Relative strings are relative to the file calling `pathExists`
+    if path.is_relative() {
+        if let Some(dir) = backtrace.0.file.path.parent() {
+            path = dir.join(path);
+        }
+    }
+
+    impurities::record_path(backtrace, &path);
+
+    // Symlinks exist even if they point nowhere, unless they have to be a
+    // directory
+    let exists = if must_be_dir {
+        std::fs::metadata(&path).is_ok_and(|metadata| metadata.is_dir())
     } else {
-        "unknown"
+        std::fs::symlink_metadata(&path).is_ok()
     };
-    Ok(NixValue::String(res.to_owned()).wrap())
+
+    Ok(NixValue::Bool(exists).wrap())
+}
+
+#[builtin]
+pub fn placeholder(output: String) {
+    Ok(NixValue::String(store::hash_placeholder(&output).into()).wrap())
+}
+
+#[builtin]
+pub fn read_dir(backtrace: &NixBacktrace, path: PathBuf) {
+    let io_error = |err| read_error(backtrace, "readDir", &path, err);
+
+    impurities::record_path(backtrace, &path);
+
+    let mut out = NixAttrSet::new();
+
+    for entry in std::fs::read_dir(&path).map_err(io_error)? {
+        let entry = entry.map_err(io_error)?;
+        let ty = file_type_name(&entry.path()).map_err(io_error)?;
+
+        out.insert(
+            entry.file_name().to_string_lossy().into_owned(),
+            NixValue::String(ty.into()).wrap_var(),
+        );
+    }
+
+    Ok(NixValue::AttrSet(out).wrap())
+}
+
+#[builtin]
+pub fn read_file(backtrace: &NixBacktrace, path: NixValueWrapped) {
+    let path = path_argument(backtrace, "readFile", &path.borrow())?;
+
+    impurities::record_path(backtrace, &path);
+
+    let content =
+        std::fs::read(&path).map_err(|err| read_error(backtrace, "readFile", &path, err))?;
+
+    // Nix strings end at the first NUL
+    if content.contains(&0) {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "readFile: the contents of the file '{}' cannot be represented as a Nix string",
+                path.display()
+            ),
+        ));
+    }
+
+    let content = String::from_utf8(content).map_err(|_| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("readFile: the file '{}' is not valid UTF-8", path.display()),
+        )
+    })?;
+
+    Ok(NixValue::String(content.into()).wrap())
+}
+
+#[builtin]
+pub fn read_file_type(backtrace: &NixBacktrace, path: NixValueWrapped) {
+    let path = path_argument(backtrace, "readFileType", &path.borrow())?;
+
+    impurities::record_path(backtrace, &path);
+
+    // Symlinks aren't followed
+    let ty =
+        file_type_name(&path).map_err(|err| read_error(backtrace, "readFileType", &path, err))?;
+
+    Ok(NixValue::String(ty.into()).wrap())
 }
 
 #[builtin]
@@ -621,7 +1804,7 @@ pub fn replace_strings(
     backtrace: &NixBacktrace,
     from: NixList,
     to: NixList,
-    s: String,
+    s: NixString,
 ) -> Result<NixValueWrapped, NixError> {
     if from.0.len() != to.0.len() {
         todo!(
@@ -642,7 +1825,7 @@ pub fn replace_strings(
         from_vec.push(search.clone());
     }
 
-    let mut res = String::new();
+    let mut res = s.with_value(String::new());
     let s_chars: Vec<_> = s.chars().collect();
     let mut p = 0;
 
@@ -653,17 +1836,18 @@ pub fn replace_strings(
             if s_chars[p..].iter().collect::<String>().starts_with(search) {
                 let replace = to.0.get(i).unwrap();
                 let resolved_replace = replace.resolve(backtrace)?;
-                let Some(replace_str) = resolved_replace.borrow().cast_to_string() else {
+                let Some(replace_str) = resolved_replace.borrow().cast_to_nix_string() else {
                     todo!("Expected string in `to`");
                 };
 
                 let cached_replace = to_cache.entry(i).or_insert_with(|| replace_str.clone());
 
-                res.push_str(cached_replace);
+                // Only replacements that get used bring their context
+                res.push(cached_replace);
 
                 if search.is_empty() {
                     if p < s_chars.len() {
-                        res.push(s_chars[p]);
+                        res.push(&s_chars[p].to_string().into());
                     }
                     p += 1;
                 } else {
@@ -676,7 +1860,7 @@ pub fn replace_strings(
 
         if !found {
             if p < s_chars.len() {
-                res.push(s_chars[p]);
+                res.push(&s_chars[p].to_string().into());
             }
             p += 1;
         }
@@ -687,26 +1871,53 @@ pub fn replace_strings(
 
 #[builtin()]
 pub fn remove_attrs(backtrace: &NixBacktrace, attrset: NixValueWrapped, attrs: NixList) {
-    if !attrset.borrow().is_attr_set() {
-        todo!("Error handling")
-    }
-
-    let mut attrset = attrset.borrow().as_attr_set().unwrap().clone();
+    let attrset = attrset.borrow();
+
+    let Some(attrset) = attrset.as_attr_set() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("removeAttrs: expected a set, got {}", attrset.as_type()),
+        ));
+    };
 
-    let attrs = attrs
+    let removed = attrs
         .0
         .iter()
         .map(|attr| {
-            attr.resolve(backtrace)
-                .map(|attr| attr.borrow().cast_to_string().unwrap())
+            let attr = attr.resolve(backtrace)?;
+            let attr = attr.borrow();
+
+            attr.as_string().cloned().ok_or_else(|| {
+                backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!(
+                        "removeAttrs: expected the names to be strings, got {}",
+                        attr.as_type()
+                    ),
+                )
+            })
         })
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect::<NixResult<HashSet<_>>>()?;
 
-    for attr in attrs {
-        attrset.remove(&attr);
+    // Kept values are shared as they are, without forcing them
+    let mut out = NixAttrSet::new();
+
+    for (name, value) in attrset.iter() {
+        if removed.contains(name) {
+            continue;
+        }
+
+        match attrset.position(name) {
+            Some(position) => {
+                out.insert_with_position(name.clone(), value.clone(), position.clone())
+            }
+            None => out.insert(name.clone(), value.clone()),
+        };
     }
 
-    Ok(NixValue::AttrSet(attrset).wrap())
+    Ok(NixValue::AttrSet(out).wrap())
 }
 
 #[builtin]
@@ -715,60 +1926,157 @@ pub fn seq(_: NixValueWrapped, argument: NixValueWrapped) {
 }
 
 #[builtin]
-pub fn substring(start: usize, len: isize, s: String) {
-    if len < 0 || start + len as usize > s.len() {
-        Ok(NixValue::String(s[start..].to_owned()).wrap())
-    } else if len == 0 || start > s.len() {
-        Ok(NixValue::String(String::new()).wrap())
-    } else {
-        Ok(NixValue::String(s[start..start + len as usize].to_owned()).wrap())
+pub fn sub(backtrace: &NixBacktrace, lhs: NixValueWrapped, rhs: NixValueWrapped) {
+    arith(backtrace, NixArithOp::Sub, lhs, rhs)
+}
+
+#[builtin]
+pub fn substring(backtrace: &NixBacktrace, start: i64, len: i64, s: NixString) {
+    if start < 0 {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("substring: negative start position ({start}) is not allowed"),
+        ));
     }
+
+    // Positions are in bytes and a negative length takes the rest of the string,
+    // half of a character becomes U+FFFD where Nix would keep the byte
+    let bytes = s.as_bytes();
+    let start = (start as usize).min(bytes.len());
+    let end = if len < 0 {
+        bytes.len()
+    } else {
+        start.saturating_add(len as usize).min(bytes.len())
+    };
+
+    Ok(
+        NixValue::String(s.with_value(String::from_utf8_lossy(&bytes[start..end]).into_owned()))
+            .wrap(),
+    )
 }
 
 #[builtin]
-pub fn split(regex: String, content: String) {
-    // TODO: Should do a regex caching, specially for loop optimisation
-    let regex = regex::Regex::new(&regex).unwrap();
+pub fn split(backtrace: &NixBacktrace, regex: String, content: NixString) {
+    let regex = compile_regex(backtrace, &regex, false)?;
 
     let mut out = vec![];
+    let mut last_idx = 0;
 
-    let last_idx = regex.find_iter(&content).fold(0, |last_idx, matches| {
-        out.push(NixValue::String(String::from(&content[last_idx..matches.start()])).wrap_var());
+    for captures in regex.captures_iter(&content) {
+        let matches = captures.get(0).unwrap();
 
         out.push(
-            NixValue::List(NixList(Rc::new(
-                regex
-                    .captures(matches.as_str())
-                    .expect("Capture a string that already match")
-                    .iter()
-                    .skip(1)
-                    .map(|c| {
-                        c.map(|c| c.as_str())
-                            .map(String::from)
-                            .map(NixValue::String)
-                            .unwrap_or_default()
-                            .wrap_var()
-                    })
-                    .collect::<Vec<_>>(),
-            )))
-            .wrap_var(),
+            NixValue::String(content.with_value(content[last_idx..matches.start()].to_owned()))
+                .wrap_var(),
         );
+        out.push(captures_to_list(&content, captures).wrap_var());
 
-        matches.end()
-    });
+        last_idx = matches.end();
+    }
 
-    out.push(NixValue::String(String::from(&content[last_idx..])).wrap_var());
+    out.push(NixValue::String(content.with_value(content[last_idx..].to_owned())).wrap_var());
 
     Ok(NixValue::List(NixList(Rc::new(out))).wrap())
 }
 
 #[builtin]
-pub fn string_length(argument: NixValueWrapped) {
-    Ok(NixValue::Int(argument.borrow().cast_to_string().unwrap().len() as i64).wrap())
+pub fn split_version(s: String) {
+    let components = version::split_version(&s)
+        .into_iter()
+        .map(|component| NixValue::String(component.into()).wrap_var())
+        .collect();
+
+    Ok(NixValue::List(NixList(Rc::new(components))).wrap())
+}
+
+#[builtin]
+pub fn store_path(backtrace: &NixBacktrace, path: String) {
+    if let Err(message) = store::check_store_path(&path) {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("storePath: {message}"),
+        ));
+    }
+
+    impurities::record(backtrace, Impurity::StorePath(path.clone()));
+
+    let path = PathBuf::from(path);
+
+    // Without a local store there is nothing to check against
+    if PathBuf::from(store::store_dir()).is_dir() && !path.exists() {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("storePath: path '{}' does not exist", path.display()),
+        ));
+    }
+
+    Ok(NixValue::Path(path).wrap())
+}
+
+#[builtin]
+pub fn string_length(s: NixString) {
+    Ok(NixValue::Int(s.len() as i64).wrap())
+}
+
+#[builtin]
+pub fn to_file(backtrace: &NixBacktrace, name: String, content: NixString) {
+    if let Err(err) = store::check_name(&name) {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("toFile: {err}"),
+        ));
+    }
+
+    let mut references = vec![];
+
+    for elem in content.context() {
+        match elem {
+            NixContextElem::Opaque(path) => references.push(path.clone()),
+            _ => {
+                return Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!(
+                        "toFile: files '{name}' cannot refer to derivation outputs ('{}')",
+                        elem.path()
+                    ),
+                ))
+            }
+        }
+    }
+
+    let path = store::add_text(&name, &content, &references).map_err(|err| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("toFile: {err}"),
+        )
+    })?;
+    let context = NixStringContext::from([NixContextElem::Opaque(path.clone())]);
+
+    Ok(NixValue::String(NixString::new(path, context)).wrap())
+}
+
+#[builtin("toXML")]
+pub fn to_xml(backtrace: &NixBacktrace, argument: NixVar) {
+    let argument = argument.resolve_set(true, backtrace)?;
+
+    let mut xml = XmlWriter::new();
+    xml.open("expr", &[]);
+    xml.write_value(&argument);
+    xml.close("expr");
+
+    Ok(NixValue::String(xml.finish().into()).wrap())
 }
 
 #[builtin()]
-pub fn to_string(argument: String) {
+pub fn to_string(backtrace: &NixBacktrace, argument: NixValueWrapped) {
+    let argument = argument.borrow().coerce_to_string(backtrace, true, false)?;
+
     Ok(NixValue::String(argument).wrap())
 }
 
@@ -778,26 +2086,35 @@ pub fn throw(backtrace: &NixBacktrace, message: String) {
     // to evaluate a derivation that throws an error is
     // silently skipped (which is not the case for abort).
 
-    let error = backtrace.to_error(
-        NixLabelKind::Error,
-        NixLabelMessage::Empty,
-        format!("Throwing: {message}"),
-    );
-
-    print!("{error}");
+    Err(backtrace
+        .to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("Throwing: {message}"),
+        )
+        .with_kind(NixErrorKind::Throw))
+}
 
-    std::process::exit(1)
+fn trace_message(message: &NixValue) -> String {
+    if message.is_string() || message.is_path() {
+        message.cast_to_string().unwrap()
+    } else {
+        format!("{message:?}")
+    }
 }
 
 #[builtin]
 pub fn trace(backtrace: &NixBacktrace, message: NixValueWrapped, argument: NixVar) {
-    let message = message.borrow();
+    log::trace(&trace_message(&message.borrow()));
 
-    if message.is_string() || message.is_path() {
-        let message = message.cast_to_string().unwrap();
-        println!("trace: {message}");
-    } else {
-        println!("trace: {message:?}");
+    argument.resolve(backtrace)
+}
+
+/// `trace` only with `--trace-verbose` or `NIX_COMPILER_VERBOSE`
+#[builtin]
+pub fn trace_verbose(backtrace: &NixBacktrace, message: NixValueWrapped, argument: NixVar) {
+    if log::is_verbose() {
+        log::trace(&trace_message(&message.borrow()));
     }
 
     argument.resolve(backtrace)
@@ -805,7 +2122,12 @@ pub fn trace(backtrace: &NixBacktrace, message: NixValueWrapped, argument: NixVa
 
 #[builtin()]
 pub fn try_eval(backtrace: &NixBacktrace, argument: NixVar) {
-    if let Err(_) = argument.resolve(backtrace) {
+    // Only `throw` and failed asserts are caught, like in Nix
+    if let Err(err) = argument.resolve(backtrace) {
+        if !err.is_catchable() {
+            return Err(err);
+        }
+
         let mut result = NixAttrSet::new();
         result.insert("success".to_string(), NixValue::Bool(false).wrap_var());
         // `value = false;` is unfortunate but removing it is a breaking change.
@@ -824,19 +2146,151 @@ pub fn try_eval(backtrace: &NixBacktrace, argument: NixVar) {
 
 #[builtin]
 pub fn type_of(argument: NixValueWrapped) {
-    Ok(NixValue::String(argument.borrow().as_type().to_owned()).wrap())
+    Ok(NixValue::String(argument.borrow().as_type().into()).wrap())
+}
+
+#[builtin]
+pub fn zip_attrs_with(backtrace: &NixBacktrace, callback: NixLambda, list: NixList) {
+    // Every value of each name, in the order of the list
+    let mut values = BTreeMap::<String, Vec<NixVar>>::new();
+
+    for item in list.0.iter() {
+        let item = item.resolve(backtrace)?;
+        let item = item.borrow();
+
+        let Some(set) = item.as_attr_set() else {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "zipAttrsWith: expected a list of sets but found {}",
+                    item.as_type_with_article()
+                ),
+            ));
+        };
+
+        for (name, value) in set.iter() {
+            values.entry(name.clone()).or_default().push(value.clone());
+        }
+    }
+
+    // `f` only runs for the attributes that are forced
+    let out = values
+        .into_iter()
+        .map(|(name, values)| {
+            let callback = callback.clone();
+            let key = name.clone();
+
+            let value = LazyNixValue::new_eval(
+                backtrace.clone(),
+                Box::new(move |backtrace| {
+                    let partial = callback
+                        .call(backtrace, NixValue::String(name.into()).wrap_var())?
+                        .resolve(backtrace)?;
+                    let partial = partial.borrow();
+
+                    let Some(partial) = partial.as_lambda() else {
+                        return Err(backtrace.to_error(
+                            NixLabelKind::Error,
+                            NixLabelMessage::Empty,
+                            format!(
+                                "zipAttrsWith: expected a function of two arguments but it returned {}",
+                                partial.as_type_with_article()
+                            ),
+                        ));
+                    };
+
+                    partial
+                        .call(
+                            backtrace,
+                            NixValue::List(NixList(Rc::new(values))).wrap_var(),
+                        )?
+                        .resolve(backtrace)
+                }),
+            )
+            .wrap_var();
+
+            (key, value)
+        })
+        .collect::<NixAttrSet>();
+
+    Ok(NixValue::AttrSet(out).wrap())
 }
 
 // TODO: Add message to backtrace
+#[builtin]
+pub fn unsafe_discard_string_context(s: NixString) {
+    Ok(NixValue::String(s.into_string().into()).wrap())
+}
+
+#[builtin]
+pub fn warn(backtrace: &NixBacktrace, message: NixValueWrapped, argument: NixVar) {
+    let message = message.borrow();
+
+    let Some(message) = message.as_string() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "warn: expected the message to be a string, got {}",
+                message.as_type()
+            ),
+        ));
+    };
+
+    log::warn(message);
+
+    argument.resolve(backtrace)
+}
+
+#[builtin]
+pub fn unsafe_get_attr_pos(backtrace: &NixBacktrace, name: String, set: NixValueWrapped) {
+    let set = set.borrow();
+
+    let Some(set) = set.as_attr_set() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "unsafeGetAttrPos: expected a set but found a {}",
+                set.as_type()
+            ),
+        ));
+    };
+
+    let Some(position) = set.position(&name) else {
+        return Ok(NixValue::Null.wrap());
+    };
+
+    let (line, column, _) = position.start;
+
+    Ok(NixValue::AttrSet(NixAttrSet::from([
+        // Columns start at 1 in Nix
+        (
+            "column".to_owned(),
+            NixValue::Int(column as i64 + 1).wrap_var(),
+        ),
+        (
+            "file".to_owned(),
+            NixValue::String(position.file.path.display().to_string().into()).wrap_var(),
+        ),
+        ("line".to_owned(), NixValue::Int(line as i64).wrap_var()),
+    ]))
+    .wrap())
+}
+
 #[builtin]
 pub fn add_error_context(_: NixValueWrapped, argument: NixValueWrapped) {
     Ok(argument)
 }
 
 gen_builtins! {
-    currentSystem = NixValue::String("x86_64-linux".to_owned());
+    currentSystem = NixValue::String("x86_64-linux".into());
     false = NixValue::Bool(false);
-    nixVersion = NixValue::String("2.24.9".to_owned());
+    langVersion = NixValue::Int(6);
+    nixPath = search_path::to_value();
+    nixVersion = NixValue::String("2.24.9".into());
     null = NixValue::Null;
+    storeDir = NixValue::String(store::store_dir().into());
     true = NixValue::Bool(true);
 }
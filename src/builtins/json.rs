@@ -0,0 +1,186 @@
+//! JSON printer, follows `printValueAsJSON` from upstream and how
+//! `nlohmann::json` dumps the result
+//!
+//! https://github.com/NixOS/nix/blob/2.24.9/src/libexpr/value-to-json.cc
+
+use std::fmt::Write;
+
+use crate::value::NixStringContext;
+use crate::{NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue};
+
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\u{8}' => out.push_str("\\b"),
+            '\u{c}' => out.push_str("\\f"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+/// The shortest digits that read back as `float`, always with a `.` or an
+/// exponent so it's still a float, like `1.0` or `1e+20`
+pub fn format_float(float: f64) -> String {
+    if !float.is_finite() {
+        return String::from("null");
+    }
+
+    if float == 0.0 {
+        return if float.is_sign_negative() {
+            "-0.0"
+        } else {
+            "0.0"
+        }
+        .to_owned();
+    }
+
+    let sign = if float < 0.0 { "-" } else { "" };
+
+    // `{:e}` has the shortest digits, like `1.5e20`
+    let scientific = format!("{:e}", float.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let len = digits.len() as i32;
+    // Where the decimal point goes in `digits`
+    let point = exponent.parse::<i32>().unwrap() + 1;
+
+    let out = if len <= point && point <= 15 {
+        format!("{digits}{}.0", "0".repeat((point - len) as usize))
+    } else if 0 < point && point <= 15 {
+        let (int, frac) = digits.split_at(point as usize);
+        format!("{int}.{frac}")
+    } else if -4 < point && point <= 0 {
+        format!("0.{}{digits}", "0".repeat(-point as usize))
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let exponent = point - 1;
+        let exponent_sign = if exponent < 0 { '-' } else { '+' };
+        let point = if rest.is_empty() { "" } else { "." };
+
+        format!("{first}{point}{rest}e{exponent_sign}{:02}", exponent.abs())
+    };
+
+    format!("{sign}{out}")
+}
+
+pub struct JsonWriter<'a> {
+    backtrace: &'a NixBacktrace,
+    out: String,
+    copy_to_store: bool,
+    /// Of every string written
+    context: NixStringContext,
+}
+
+impl<'a> JsonWriter<'a> {
+    /// Paths are written as they are, or copied to the store if
+    /// `copy_to_store`
+    pub fn new(backtrace: &'a NixBacktrace, copy_to_store: bool) -> Self {
+        Self {
+            backtrace,
+            out: String::new(),
+            copy_to_store,
+            context: NixStringContext::new(),
+        }
+    }
+
+    pub fn finish(self) -> (String, NixStringContext) {
+        (self.out, self.context)
+    }
+
+    /// `{`, then call `write_entry` for each key and `end_object`
+    pub fn start_object(&mut self) {
+        self.out.push('{');
+    }
+
+    pub fn write_entry(&mut self, key: &str, value: &NixValue) -> NixResult<()> {
+        if !self.out.ends_with('{') {
+            self.out.push(',');
+        }
+
+        write_string(&mut self.out, key);
+        self.out.push(':');
+        self.write_value(value)
+    }
+
+    pub fn end_object(&mut self) {
+        self.out.push('}');
+    }
+
+    pub fn write_value(&mut self, value: &NixValue) -> NixResult<()> {
+        match value {
+            NixValue::AttrSet(set) => {
+                // `__toString` and `outPath` make it a string
+                if set.contains_key("__toString") || set.contains_key("outPath") {
+                    let string =
+                        value.coerce_to_string(self.backtrace, false, self.copy_to_store)?;
+
+                    self.context.extend(string.context().iter().cloned());
+                    write_string(&mut self.out, string.as_str());
+
+                    return Ok(());
+                }
+
+                self.start_object();
+
+                for (key, value) in set {
+                    let value = value.resolve(self.backtrace)?;
+
+                    self.write_entry(key, &value.borrow())?;
+                }
+
+                self.end_object();
+            }
+            NixValue::Bool(bool) => self.out.push_str(if *bool { "true" } else { "false" }),
+            NixValue::Float(float) => self.out.push_str(&format_float(*float)),
+            NixValue::Int(int) => {
+                let _ = write!(self.out, "{int}");
+            }
+            NixValue::Lambda(_) => {
+                return Err(self.backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    "cannot convert a function to JSON",
+                ));
+            }
+            NixValue::List(list) => {
+                self.out.push('[');
+
+                for (idx, item) in list.0.iter().enumerate() {
+                    if idx > 0 {
+                        self.out.push(',');
+                    }
+
+                    let item = item.resolve(self.backtrace)?;
+
+                    self.write_value(&item.borrow())?;
+                }
+
+                self.out.push(']');
+            }
+            NixValue::Null => self.out.push_str("null"),
+            NixValue::Path(path) if !self.copy_to_store => {
+                write_string(&mut self.out, &path.display().to_string());
+            }
+            NixValue::Path(_) | NixValue::String(_) => {
+                let string = value.coerce_to_string(self.backtrace, false, self.copy_to_store)?;
+
+                self.context.extend(string.context().iter().cloned());
+                write_string(&mut self.out, string.as_str());
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Version strings, shared by `splitVersion`, `compareVersions` and `parseDrvName`
+//!
+//! https://github.com/NixOS/nix/blob/2.24.9/src/libstore/names.cc
+
+use std::cmp::Ordering;
+
+fn is_separator(c: char) -> bool {
+    matches!(c, '.' | '-' | '_')
+}
+
+/// Split into maximal runs of digits and maximal runs of other non-separator characters
+pub fn split_version(version: &str) -> Vec<&str> {
+    let mut components = vec![];
+    let mut rest = version;
+
+    loop {
+        rest = rest.trim_start_matches(is_separator);
+
+        let Some(first) = rest.chars().next() else {
+            return components;
+        };
+
+        let end = if first.is_ascii_digit() {
+            rest.find(|c: char| !c.is_ascii_digit())
+        } else {
+            rest.find(|c: char| c.is_ascii_digit() || is_separator(c))
+        }
+        .unwrap_or(rest.len());
+
+        components.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+}
+
+/// Digits compared as numbers of any length
+fn cmp_numbers(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+fn is_number(component: &str) -> bool {
+    !component.is_empty() && component.bytes().all(|c| c.is_ascii_digit())
+}
+
+/// Nix's `componentsLT`, a missing component is empty and is older than a
+/// number, `pre` is older than anything else and letters are older than numbers
+fn component_lt(c1: &str, c2: &str) -> bool {
+    let n1 = is_number(c1);
+    let n2 = is_number(c2);
+
+    if n1 && n2 {
+        cmp_numbers(c1, c2) == Ordering::Less
+    } else if (c1.is_empty() && n2) || (c1 == "pre" && c2 != "pre") {
+        true
+    } else if c2 == "pre" {
+        false
+    } else if n2 {
+        // `2.3a` is older than `2.3.1`
+        true
+    } else if n1 {
+        false
+    } else {
+        c1 < c2
+    }
+}
+
+/// Compare the components one by one, the shorter version is padded with empty ones
+pub fn compare_versions(v1: &str, v2: &str) -> Ordering {
+    let c1 = split_version(v1);
+    let c2 = split_version(v2);
+
+    for idx in 0..c1.len().max(c2.len()) {
+        let c1 = c1.get(idx).copied().unwrap_or("");
+        let c2 = c2.get(idx).copied().unwrap_or("");
+
+        if component_lt(c1, c2) {
+            return Ordering::Less;
+        } else if component_lt(c2, c1) {
+            return Ordering::Greater;
+        }
+    }
+
+    Ordering::Equal
+}
+
+/// Split `<name>-<version>` at the first dash not followed by a letter
+pub fn parse_drv_name(drv_name: &str) -> (&str, &str) {
+    let split = drv_name.char_indices().find(|&(idx, c)| {
+        c == '-'
+            && drv_name[idx + 1..]
+                .chars()
+                .next()
+                .is_some_and(|next| !next.is_alphabetic())
+    });
+
+    match split {
+        Some((idx, _)) => (&drv_name[..idx], &drv_name[idx + 1..]),
+        None => (drv_name, ""),
+    }
+}
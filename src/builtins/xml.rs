@@ -0,0 +1,232 @@
+//! `builtins.toXML` printer, follows `printValueAsXML` from upstream
+//!
+//! https://github.com/NixOS/nix/blob/2.24.9/src/libexpr/value-to-xml.cc
+
+use std::collections::HashSet;
+use std::fmt::Write;
+
+use crate::value::{NixLambda, NixLambdaParam};
+use crate::{NixAttrSet, NixValue, NixValueWrapped, NixVar};
+
+pub fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("&quot;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '&' => out.push_str("&amp;"),
+            // Escape new line to prevent stripping
+            '\n' => out.push_str("&#xA;"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// C++ streams print doubles like `%g`
+pub fn format_float(float: f64) -> String {
+    if float == 0.0 || !float.is_finite() {
+        return float.to_string();
+    }
+
+    let exponent = float.abs().log10().floor() as i32;
+
+    if !(-5..6).contains(&exponent) {
+        let out = format!("{float:.5e}");
+        let (mantissa, exponent) = out.split_once('e').unwrap();
+        let mantissa = trim_zeros(mantissa);
+        let exponent: i32 = exponent.parse().unwrap();
+        let sign = if exponent < 0 { '-' } else { '+' };
+
+        format!("{mantissa}e{sign}{:02}", exponent.abs())
+    } else {
+        let precision = (5 - exponent).max(0) as usize;
+        trim_zeros(&format!("{float:.precision$}")).to_owned()
+    }
+}
+
+fn trim_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+pub struct XmlWriter {
+    out: String,
+    depth: usize,
+    drvs_seen: HashSet<String>,
+}
+
+impl XmlWriter {
+    pub fn new() -> Self {
+        Self {
+            out: String::from("<?xml version='1.0' encoding='utf-8'?>\n"),
+            depth: 0,
+            drvs_seen: HashSet::new(),
+        }
+    }
+
+    fn indent(&mut self) {
+        self.out.push_str(&"  ".repeat(self.depth));
+    }
+
+    fn write_attrs(&mut self, attrs: &[(&str, &str)]) {
+        let mut attrs = attrs.to_vec();
+        attrs.sort();
+
+        for (name, value) in attrs {
+            let _ = write!(self.out, " {name}=\"{}\"", escape(value));
+        }
+    }
+
+    pub fn open(&mut self, name: &str, attrs: &[(&str, &str)]) {
+        self.indent();
+        let _ = write!(self.out, "<{name}");
+        self.write_attrs(attrs);
+        self.out.push_str(">\n");
+        self.depth += 1;
+    }
+
+    pub fn close(&mut self, name: &str) {
+        self.depth -= 1;
+        self.indent();
+        let _ = writeln!(self.out, "</{name}>");
+    }
+
+    pub fn empty(&mut self, name: &str, attrs: &[(&str, &str)]) {
+        self.indent();
+        let _ = write!(self.out, "<{name}");
+        self.write_attrs(attrs);
+        self.out.push_str(" />\n");
+    }
+
+    pub fn finish(self) -> String {
+        self.out
+    }
+
+    fn write_attrs_content(&mut self, set: &NixAttrSet) {
+        for (name, value) in set {
+            self.open("attr", &[("name", name)]);
+            self.write_var(value);
+            self.close("attr");
+        }
+    }
+
+    fn write_var(&mut self, var: &NixVar) {
+        // Values are deeply resolved before printing
+        match var.as_concrete() {
+            Some(value) => self.write_value(&value),
+            None => self.empty("unevaluated", &[]),
+        }
+    }
+
+    pub fn write_value(&mut self, value: &NixValueWrapped) {
+        match &*value.borrow() {
+            NixValue::AttrSet(set) => {
+                let is_derivation = set
+                    .get("type")
+                    .and_then(NixVar::as_concrete)
+                    .is_some_and(|ty| ty.borrow().as_string().is_some_and(|ty| ty == "derivation"));
+
+                let get_string = |attr: &str| {
+                    set.get(attr)
+                        .and_then(NixVar::as_concrete)
+                        .and_then(|value| value.borrow().cast_to_string())
+                };
+
+                if !is_derivation {
+                    self.open("attrs", &[]);
+                    self.write_attrs_content(set);
+                    self.close("attrs");
+                    return;
+                }
+
+                let drv_path = get_string("drvPath");
+                let out_path = get_string("outPath");
+
+                let mut attrs = vec![];
+                if let Some(drv_path) = &drv_path {
+                    attrs.push(("drvPath", drv_path.as_str()));
+                }
+                if let Some(out_path) = &out_path {
+                    attrs.push(("outPath", out_path.as_str()));
+                }
+
+                self.open("derivation", &attrs);
+
+                // Each derivation is only printed once
+                if drv_path.is_some_and(|drv_path| self.drvs_seen.insert(drv_path)) {
+                    self.write_attrs_content(set);
+                } else {
+                    self.empty("repeated", &[]);
+                }
+
+                self.close("derivation");
+            }
+            NixValue::Bool(value) => {
+                self.empty("bool", &[("value", if *value { "true" } else { "false" })])
+            }
+            NixValue::Float(value) => self.empty("float", &[("value", &format_float(*value))]),
+            NixValue::Int(value) => self.empty("int", &[("value", &value.to_string())]),
+            NixValue::Lambda(NixLambda::Apply(_, param, _)) => {
+                self.open("function", &[]);
+
+                match param {
+                    NixLambdaParam::Ident(name) => self.empty("varpat", &[("name", name)]),
+                    NixLambdaParam::Pattern(pattern) => {
+                        let bind = pattern
+                            .pat_bind()
+                            .and_then(|bind| bind.ident())
+                            .and_then(|ident| ident.ident_token())
+                            .map(|ident| ident.text().to_owned());
+
+                        let mut attrs = vec![];
+                        if pattern.ellipsis_token().is_some() {
+                            attrs.push(("ellipsis", "1"));
+                        }
+                        if let Some(bind) = &bind {
+                            attrs.push(("name", bind.as_str()));
+                        }
+
+                        self.open("attrspat", &attrs);
+
+                        let mut formals = pattern
+                            .pat_entries()
+                            .filter_map(|entry| entry.ident())
+                            .filter_map(|ident| ident.ident_token())
+                            .map(|ident| ident.text().to_owned())
+                            .collect::<Vec<_>>();
+
+                        formals.sort();
+
+                        for formal in formals {
+                            self.empty("attr", &[("name", &formal)]);
+                        }
+
+                        self.close("attrspat");
+                    }
+                }
+
+                self.close("function");
+            }
+            NixValue::Lambda(NixLambda::Builtin(_)) => self.empty("unevaluated", &[]),
+            NixValue::List(list) => {
+                self.open("list", &[]);
+
+                for item in list.0.iter() {
+                    self.write_var(item);
+                }
+
+                self.close("list");
+            }
+            NixValue::Null => self.empty("null", &[]),
+            NixValue::Path(path) => self.empty("path", &[("value", &path.display().to_string())]),
+            NixValue::String(value) => self.empty("string", &[("value", value)]),
+        }
+    }
+}
@@ -0,0 +1,559 @@
+//! Syntax and scope checking without evaluation (`nix-compiler check`)
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fs, io};
+
+use rnix::ast::{self, HasEntry};
+use rowan::ast::AstNode;
+
+use crate::scope::Constants;
+use crate::{
+    FileScope, NixError, NixErrorKind, NixLabel, NixLabelKind, NixLabelMessage, NixSpan, Scope,
+};
+
+pub struct Checker {
+    file: Rc<FileScope>,
+    globals: HashSet<String>,
+    scopes: Vec<HashSet<String>>,
+    with_depth: usize,
+    errors: Vec<NixError>,
+}
+
+impl Checker {
+    pub fn check_file(path: impl AsRef<Path>) -> io::Result<Vec<NixError>> {
+        let path = path.as_ref();
+
+        let file = Rc::new(FileScope {
+            content: fs::read_to_string(path)?,
+            path: path.canonicalize()?,
+            constants: Constants::default(),
+            warned_uri: Cell::default(),
+        });
+
+        Ok(Self::check(file))
+    }
+
+    pub fn check(file: Rc<FileScope>) -> Vec<NixError> {
+        let parse = rnix::Root::parse(&file.content);
+
+        let mut errors = parse
+            .errors()
+            .iter()
+            .cloned()
+            .map(|error| NixError::from_parse_error(&file, error))
+            .collect::<Vec<_>>();
+
+        let globals = Scope::new_with_builtins(file.clone())
+            .parent
+            .as_ref()
+            .map(|parent| {
+                parent
+                    .variables
+                    .borrow()
+                    .as_attr_set()
+                    .map(|set| set.keys().cloned().collect())
+                    .unwrap_or_default()
+            })
+            .unwrap_or_default();
+
+        let mut checker = Checker {
+            file,
+            globals,
+            scopes: Vec::new(),
+            with_depth: 0,
+            errors: Vec::new(),
+        };
+
+        if let Some(expr) = parse.tree().expr() {
+            checker.visit_expr(expr);
+        }
+
+        errors.append(&mut checker.errors);
+        errors
+    }
+
+    fn span(&self, node: &impl AstNode) -> Rc<NixSpan> {
+        Rc::new(NixSpan::from_ast_node(&self.file, node))
+    }
+
+    fn is_defined(&self, varname: &str) -> bool {
+        self.scopes.iter().any(|scope| scope.contains(varname)) || self.globals.contains(varname)
+    }
+
+    fn check_variable(&mut self, node: &impl AstNode, varname: &str) {
+        if self.with_depth > 0 || self.is_defined(varname) {
+            return;
+        }
+
+        self.errors.push(NixError::from_message(
+            NixLabel::new(
+                self.span(node),
+                NixLabelMessage::VariableNotFound,
+                NixLabelKind::Error,
+            ),
+            format!("Variable '\x1b[1;95m{varname}\x1b[0m' not found"),
+        ));
+    }
+
+    /// Static name of an attribute, `None` if it is only known at evaluation time
+    fn static_attr(attr: &ast::Attr) -> Option<String> {
+        match attr {
+            ast::Attr::Ident(ident) => Some(ident.syntax().text().to_string()),
+            ast::Attr::Dynamic(_) => None,
+            ast::Attr::Str(str) => {
+                let mut content = String::new();
+
+                for part in str.normalized_parts() {
+                    match part {
+                        ast::InterpolPart::Literal(literal) => content += &literal,
+                        ast::InterpolPart::Interpolation(_) => return None,
+                    }
+                }
+
+                Some(content)
+            }
+        }
+    }
+
+    /// Names introduced by the entries of a `let` or a `rec` attrset
+    fn binding_names(node: &impl HasEntry) -> HashSet<String> {
+        let mut names = HashSet::new();
+
+        for entry in node.entries() {
+            match entry {
+                ast::Entry::Inherit(inherit) => {
+                    names.extend(inherit.attrs().filter_map(|attr| Self::static_attr(&attr)));
+                }
+                ast::Entry::AttrpathValue(entry) => {
+                    let first = entry.attrpath().and_then(|path| path.attrs().next());
+
+                    if let Some(name) = first.as_ref().and_then(Self::static_attr) {
+                        names.insert(name);
+                    }
+                }
+            }
+        }
+
+        names
+    }
+
+    fn visit_attrpath(&mut self, attrpath: ast::Attrpath) {
+        for attr in attrpath.attrs() {
+            match attr {
+                ast::Attr::Ident(_) => {}
+                ast::Attr::Dynamic(dynamic) => {
+                    if let Some(expr) = dynamic.expr() {
+                        self.visit_expr(expr);
+                    }
+                }
+                ast::Attr::Str(str) => self.visit_str(str),
+            }
+        }
+    }
+
+    /// Visit the entries of an attrset-like node. `inherit x;` looks up `x`
+    /// in the scopes *outside* of the bindings, so it must be checked before
+    /// the recursive scope (if any) is pushed.
+    fn visit_entries(&mut self, node: &impl HasEntry, recursive: bool) {
+        let mut defined: HashMap<Vec<String>, ast::Attrpath> = HashMap::new();
+        let mut inherited: HashMap<String, ast::Attr> = HashMap::new();
+
+        for entry in node.entries() {
+            if let ast::Entry::Inherit(inherit) = entry {
+                for attr in inherit.attrs() {
+                    let Some(name) = Self::static_attr(&attr) else {
+                        self.errors.push(NixError::from_message(
+                            NixLabel::new(
+                                self.span(&attr),
+                                NixLabelMessage::Custom("Dynamic attribute".to_owned()),
+                                NixLabelKind::Error,
+                            ),
+                            "Dynamic attributes are not allowed in inherit",
+                        ));
+                        continue;
+                    };
+
+                    if let Some(previous) = inherited.get(&name) {
+                        self.push_duplicated(&name, previous, &attr);
+                    } else {
+                        if inherit.from().is_none() {
+                            self.check_variable(&attr, &name);
+                        }
+
+                        inherited.insert(name, attr);
+                    }
+                }
+            }
+        }
+
+        if recursive {
+            self.scopes.push(Self::binding_names(node));
+        }
+
+        for entry in node.entries() {
+            match entry {
+                ast::Entry::Inherit(inherit) => {
+                    if let Some(expr) = inherit.from().and_then(|from| from.expr()) {
+                        self.visit_expr(expr);
+                    }
+                }
+                ast::Entry::AttrpathValue(entry) => {
+                    let Some(attrpath) = entry.attrpath() else {
+                        continue;
+                    };
+
+                    let path = attrpath
+                        .attrs()
+                        .map(|attr| Self::static_attr(&attr))
+                        .collect::<Option<Vec<_>>>();
+
+                    if let Some(path) = path {
+                        if let Some(previous) = defined.get(&path) {
+                            self.push_duplicated(&path.join("."), previous, &attrpath);
+                        } else if let Some(previous) = path
+                            .first()
+                            .filter(|_| path.len() == 1)
+                            .and_then(|name| inherited.get(name))
+                        {
+                            self.push_duplicated(&path[0], previous, &attrpath);
+                        } else {
+                            defined.insert(path, attrpath.clone());
+                        }
+                    }
+
+                    self.visit_attrpath(attrpath);
+
+                    if let Some(value) = entry.value() {
+                        self.visit_expr(value);
+                    }
+                }
+            }
+        }
+
+        if recursive {
+            self.scopes.pop();
+        }
+    }
+
+    fn push_duplicated(&mut self, name: &str, previous: &impl AstNode, current: &impl AstNode) {
+        self.errors.push(NixError {
+            message: format!("Attribute '\x1b[1;95m{name}\x1b[0m' already defined"),
+            labels: vec![
+                NixLabel::new(
+                    self.span(current),
+                    NixLabelMessage::Custom("Duplicated binding".to_owned()),
+                    NixLabelKind::Error,
+                ),
+                NixLabel::new(
+                    self.span(previous),
+                    NixLabelMessage::Custom("First defined here".to_owned()),
+                    NixLabelKind::Help,
+                ),
+            ],
+            backtrace: None.into(),
+            kind: NixErrorKind::Eval,
+        });
+    }
+
+    fn visit_str(&mut self, node: ast::Str) {
+        for part in node.parts() {
+            if let ast::InterpolPart::Interpolation(interpol) = part {
+                if let Some(expr) = interpol.expr() {
+                    self.visit_expr(expr);
+                }
+            }
+        }
+    }
+
+    fn visit_opt_expr(&mut self, node: Option<ast::Expr>) {
+        if let Some(node) = node {
+            self.visit_expr(node);
+        }
+    }
+
+    fn visit_expr(&mut self, node: ast::Expr) {
+        match node {
+            ast::Expr::Apply(node) => {
+                self.visit_opt_expr(node.lambda());
+                self.visit_opt_expr(node.argument());
+            }
+            ast::Expr::Assert(node) => {
+                self.visit_opt_expr(node.condition());
+                self.visit_opt_expr(node.body());
+            }
+            ast::Expr::AttrSet(node) => {
+                let is_recursive = node.rec_token().is_some();
+                self.visit_entries(&node, is_recursive);
+            }
+            ast::Expr::BinOp(node) => {
+                self.visit_opt_expr(node.lhs());
+                self.visit_opt_expr(node.rhs());
+            }
+            // Already reported as parse errors
+            ast::Expr::Error(_) => {}
+            ast::Expr::HasAttr(node) => {
+                self.visit_opt_expr(node.expr());
+                if let Some(attrpath) = node.attrpath() {
+                    self.visit_attrpath(attrpath);
+                }
+            }
+            ast::Expr::Ident(node) => {
+                let varname = node.syntax().text().to_string();
+                self.check_variable(&node, &varname);
+            }
+            ast::Expr::IfElse(node) => {
+                self.visit_opt_expr(node.condition());
+                self.visit_opt_expr(node.body());
+                self.visit_opt_expr(node.else_body());
+            }
+            ast::Expr::Lambda(node) => {
+                let mut params = HashSet::new();
+
+                match node.param() {
+                    Some(ast::Param::IdentParam(param)) => {
+                        if let Some(ident) = param.ident().and_then(|ident| ident.ident_token()) {
+                            params.insert(ident.text().to_owned());
+                        }
+                    }
+                    Some(ast::Param::Pattern(pattern)) => {
+                        if let Some(ident) = pattern
+                            .pat_bind()
+                            .and_then(|bind| bind.ident())
+                            .and_then(|ident| ident.ident_token())
+                        {
+                            params.insert(ident.text().to_owned());
+                        }
+
+                        params.extend(
+                            pattern
+                                .pat_entries()
+                                .filter_map(|entry| entry.ident())
+                                .filter_map(|ident| ident.ident_token())
+                                .map(|ident| ident.text().to_owned()),
+                        );
+                    }
+                    None => {}
+                }
+
+                self.scopes.push(params);
+
+                // Default values can see every formal
+                if let Some(ast::Param::Pattern(pattern)) = node.param() {
+                    for entry in pattern.pat_entries() {
+                        self.visit_opt_expr(entry.default());
+                    }
+                }
+
+                self.visit_opt_expr(node.body());
+
+                self.scopes.pop();
+            }
+            ast::Expr::LegacyLet(node) => self.visit_entries(&node, true),
+            ast::Expr::LetIn(node) => {
+                self.visit_entries(&node, true);
+
+                self.scopes.push(Self::binding_names(&node));
+                self.visit_opt_expr(node.body());
+                self.scopes.pop();
+            }
+            ast::Expr::List(node) => {
+                for item in node.items() {
+                    self.visit_expr(item);
+                }
+            }
+            ast::Expr::Literal(_) => {}
+            ast::Expr::Paren(node) => {
+                self.visit_opt_expr(node.expr());
+            }
+            ast::Expr::Path(node) => {
+                for part in node.parts() {
+                    if let ast::InterpolPart::Interpolation(interpol) = part {
+                        self.visit_opt_expr(interpol.expr());
+                    }
+                }
+            }
+            ast::Expr::Root(node) => {
+                self.visit_opt_expr(node.expr());
+            }
+            ast::Expr::Select(node) => {
+                self.visit_opt_expr(node.expr());
+                if let Some(attrpath) = node.attrpath() {
+                    self.visit_attrpath(attrpath);
+                }
+                self.visit_opt_expr(node.default_expr());
+            }
+            ast::Expr::Str(node) => self.visit_str(node),
+            ast::Expr::UnaryOp(node) => {
+                self.visit_opt_expr(node.expr());
+            }
+            ast::Expr::With(node) => {
+                self.visit_opt_expr(node.namespace());
+
+                self.with_depth += 1;
+                self.visit_opt_expr(node.body());
+                self.with_depth -= 1;
+            }
+        }
+    }
+}
+
+/// Collect every `.nix` file under `path`, sorted to get a stable report
+pub fn collect_files(path: &Path, out: &mut Vec<PathBuf>) -> io::Result<()> {
+    if !path.is_dir() {
+        out.push(path.to_path_buf());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    entries.sort();
+
+    for entry in entries {
+        if entry.is_dir() {
+            collect_files(&entry, out)?;
+        } else if entry.extension().is_some_and(|ext| ext == "nix") {
+            out.push(entry);
+        }
+    }
+
+    Ok(())
+}
+
+/// Drop the ANSI colors used by the terminal renderer
+pub fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            out.push(c);
+        }
+    }
+
+    out
+}
+
+pub fn write_json_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in strip_ansi(s).chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+pub fn errors_to_json(errors: &[NixError]) -> String {
+    let mut out = String::from("[");
+
+    for (idx, error) in errors.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+
+        out.push_str("{\"message\":");
+        write_json_string(&mut out, &error.message);
+        out.push_str(",\"labels\":[");
+
+        for (idx, label) in error.labels.iter().enumerate() {
+            if idx > 0 {
+                out.push(',');
+            }
+
+            out.push_str("{\"file\":");
+            write_json_string(&mut out, &label.span.file.path.display().to_string());
+            out.push_str(",\"kind\":");
+            write_json_string(&mut out, label.kind.text());
+            out.push_str(",\"message\":");
+            write_json_string(&mut out, &label.label.to_string());
+
+            let _ = write!(
+                out,
+                ",\"start\":{{\"line\":{},\"column\":{}}},\"end\":{{\"line\":{},\"column\":{}}}}}",
+                label.span.start.0,
+                label.span.start.1 + 1,
+                label.span.end.0,
+                label.span.end.1 + 1,
+            );
+        }
+
+        out.push_str("]}");
+    }
+
+    out.push(']');
+    out
+}
+
+/// Entry point of `nix-compiler check [--json] <path>...`
+pub fn run(args: impl Iterator<Item = String>) -> i32 {
+    let mut json = false;
+    let mut paths = Vec::new();
+
+    for arg in args {
+        if arg == "--json" {
+            json = true;
+        } else {
+            paths.push(PathBuf::from(arg));
+        }
+    }
+
+    if paths.is_empty() {
+        eprintln!("Usage: nix-compiler check [--json] <path>...");
+        return 1;
+    }
+
+    let mut files = Vec::new();
+
+    for path in &paths {
+        if let Err(err) = collect_files(path, &mut files) {
+            eprintln!("Cannot read {}: {err}", path.display());
+            return 1;
+        }
+    }
+
+    let mut errors = Vec::new();
+
+    for file in &files {
+        match Checker::check_file(file) {
+            Ok(mut file_errors) => errors.append(&mut file_errors),
+            Err(err) => {
+                eprintln!("Cannot read {}: {err}", file.display());
+                return 1;
+            }
+        }
+    }
+
+    if json {
+        println!("{}", errors_to_json(&errors));
+    } else {
+        for error in &errors {
+            eprintln!("{error}");
+        }
+
+        eprintln!("Checked {} file(s): {} error(s)", files.len(), errors.len());
+    }
+
+    if errors.is_empty() {
+        0
+    } else {
+        1
+    }
+}
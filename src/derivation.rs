@@ -0,0 +1,920 @@
+//! Derivations built by `derivationStrict`, and the store paths of them and
+//! of their outputs
+//!
+//! https://nix.dev/manual/nix/latest/protocols/derivation-aterm
+
+mod parse;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::rc::Rc;
+use std::{fs, io};
+
+use crate::builtins::hash::{self, Algorithm};
+use crate::builtins::json::JsonWriter;
+use crate::store::{self, ContentAddressMethod};
+use crate::value::{NixContextElem, NixString, NixStringContext};
+use crate::{
+    LazyNixValue, NixAttrSet, NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue,
+    NixValueWrapped,
+};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DerivationOutput {
+    /// Addressed by the hash of the derivation that builds it
+    InputAddressed(String),
+    /// Not known yet, outputs are blank while the derivation is hashed
+    Deferred,
+    /// Content addressed once built, `hashAlgo` with the method (`r:sha256`)
+    CAFloating(String),
+    /// Built each time, its path is never known (`__impure`)
+    Impure(String),
+    /// Known beforehand by the hash of what it contains (`outputHash`)
+    CAFixed {
+        path: String,
+        /// With the method, like in `CAFloating`
+        hash_algo: String,
+        /// Hex of the digest
+        hash: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Derivation {
+    pub name: String,
+    pub outputs: BTreeMap<String, DerivationOutput>,
+    /// `.drv` paths, with the outputs used of each
+    pub input_derivations: BTreeMap<String, BTreeSet<String>>,
+    pub input_sources: BTreeSet<String>,
+    pub system: String,
+    pub builder: String,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+thread_local! {
+    /// Every derivation instantiated, by `.drv` path, the ones depending on
+    /// them need them to be hashed
+    static DERIVATIONS: RefCell<HashMap<String, Rc<Derivation>>> = RefCell::new(HashMap::new());
+    /// `Derivation::hash_modulo` of each `.drv` path
+    static HASHES: RefCell<HashMap<String, DrvHash>> = RefCell::new(HashMap::new());
+}
+
+/// Result of `Derivation::hash_modulo`
+#[derive(Clone)]
+struct DrvHash {
+    hash: String,
+    /// The paths of the outputs can't be known until something is built, so
+    /// the ones of the derivations depending on it can't either
+    deferred: bool,
+}
+
+/// The derivation instantiated at `drv_path`, otherwise the `.drv` of the
+/// store. `None` if there's no such file, and an error if it can't be read.
+pub fn get(drv_path: &str) -> Result<Option<Rc<Derivation>>, String> {
+    if let Some(derivation) =
+        DERIVATIONS.with_borrow(|derivations| derivations.get(drv_path).cloned())
+    {
+        return Ok(Some(derivation));
+    }
+
+    let aterm = match fs::read_to_string(store::to_local_path(drv_path)) {
+        Ok(aterm) => aterm,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(format!("cannot read the derivation '{drv_path}': {err}")),
+    };
+
+    let derivation = Rc::new(
+        Derivation::parse(drv_path, &aterm)
+            .map_err(|err| format!("cannot parse the derivation '{drv_path}': {err}"))?,
+    );
+
+    DERIVATIONS
+        .with_borrow_mut(|derivations| derivations.insert(drv_path.to_owned(), derivation.clone()));
+
+    Ok(Some(derivation))
+}
+
+/// `<name>` of `<store>/<hash>-<name>.drv`
+fn drv_name(drv_path: &str) -> Option<&str> {
+    let file_name = drv_path.rsplit('/').next()?;
+    let (_, name) = file_name.split_once('-')?;
+
+    name.strip_suffix(".drv").filter(|name| !name.is_empty())
+}
+
+fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+}
+
+fn write_list<T>(
+    out: &mut String,
+    items: impl IntoIterator<Item = T>,
+    mut f: impl FnMut(&mut String, T),
+) {
+    out.push('[');
+
+    for (idx, item) in items.into_iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+
+        f(out, item);
+    }
+
+    out.push(']');
+}
+
+impl Derivation {
+    /// Name of the store path of `output`
+    pub fn output_path_name(name: &str, output: &str) -> String {
+        if output == "out" {
+            name.to_owned()
+        } else {
+            format!("{name}-{output}")
+        }
+    }
+
+    /// Path of `output`, `None` if it doesn't exist or it isn't known
+    pub fn path(&self, output: &str) -> Option<&str> {
+        match self.outputs.get(output)? {
+            DerivationOutput::InputAddressed(path) | DerivationOutput::CAFixed { path, .. } => {
+                Some(path)
+            }
+            DerivationOutput::Deferred
+            | DerivationOutput::CAFloating(_)
+            | DerivationOutput::Impure(_) => None,
+        }
+    }
+
+    /// The `.drv` file, `Derive(outputs, inputDrvs, inputSrcs, system, builder, args, env)`
+    pub fn to_aterm(&self) -> String {
+        self.write_aterm(&self.input_derivations)
+    }
+
+    fn write_aterm(&self, input_derivations: &BTreeMap<String, BTreeSet<String>>) -> String {
+        let mut out = String::from("Derive(");
+
+        write_list(&mut out, &self.outputs, |out, (name, output)| {
+            let (path, hash_algo, hash) = match output {
+                DerivationOutput::InputAddressed(path) => (path.as_str(), "", ""),
+                DerivationOutput::Deferred => ("", "", ""),
+                DerivationOutput::CAFloating(hash_algo) => ("", hash_algo.as_str(), ""),
+                DerivationOutput::Impure(hash_algo) => ("", hash_algo.as_str(), "impure"),
+                DerivationOutput::CAFixed {
+                    path,
+                    hash_algo,
+                    hash,
+                } => (path.as_str(), hash_algo.as_str(), hash.as_str()),
+            };
+
+            out.push('(');
+            write_string(out, name);
+            out.push(',');
+            write_string(out, path);
+            out.push(',');
+            write_string(out, hash_algo);
+            out.push(',');
+            write_string(out, hash);
+            out.push(')');
+        });
+
+        out.push(',');
+        write_list(&mut out, input_derivations, |out, (path, outputs)| {
+            out.push('(');
+            write_string(out, path);
+            out.push(',');
+            write_list(out, outputs, |out, output| write_string(out, output));
+            out.push(')');
+        });
+
+        out.push(',');
+        write_list(&mut out, &self.input_sources, |out, path| {
+            write_string(out, path)
+        });
+
+        out.push(',');
+        write_string(&mut out, &self.system);
+        out.push(',');
+        write_string(&mut out, &self.builder);
+        out.push(',');
+        write_list(&mut out, &self.args, |out, arg| write_string(out, arg));
+
+        out.push(',');
+        write_list(&mut out, &self.env, |out, (key, value)| {
+            out.push('(');
+            write_string(out, key);
+            out.push(',');
+            write_string(out, value);
+            out.push(')');
+        });
+
+        out.push(')');
+        out
+    }
+
+    /// Hex SHA-256 of the derivation with each input derivation replaced by
+    /// its own hash, so the hash doesn't change when only the `.drv` paths of
+    /// the inputs do (Nix's `hashDerivationModulo`). Fixed outputs only
+    /// depend on what they contain.
+    fn hash_modulo(&self) -> Result<DrvHash, String> {
+        if let Some(DerivationOutput::CAFixed {
+            path,
+            hash_algo,
+            hash,
+        }) = self.outputs.get("out")
+        {
+            return Ok(DrvHash {
+                hash: hash::hex_digest(
+                    Algorithm::SHA256,
+                    format!("fixed:out:{hash_algo}:{hash}:{path}").as_bytes(),
+                ),
+                deferred: false,
+            });
+        }
+
+        if self
+            .outputs
+            .values()
+            .any(|output| matches!(output, DerivationOutput::Impure(_)))
+        {
+            return Ok(DrvHash {
+                hash: hash::hex_digest(Algorithm::SHA256, b"impure"),
+                deferred: true,
+            });
+        }
+
+        let mut deferred = self
+            .outputs
+            .values()
+            .any(|output| matches!(output, DerivationOutput::CAFloating(_)));
+        let mut input_derivations = BTreeMap::new();
+
+        for (drv_path, outputs) in &self.input_derivations {
+            let input = hash_modulo_of(drv_path)?;
+
+            deferred |= input.deferred;
+            input_derivations.insert(input.hash, outputs.clone());
+        }
+
+        Ok(DrvHash {
+            hash: hash::hex_digest(
+                Algorithm::SHA256,
+                self.write_aterm(&input_derivations).as_bytes(),
+            ),
+            deferred,
+        })
+    }
+
+    /// Compute the paths of the outputs and of the `.drv`, and remember the
+    /// derivation so others can depend on it. Outputs that are still
+    /// `Deferred` are addressed by the inputs, unless one of them is deferred.
+    pub fn instantiate(mut self) -> Result<(String, Rc<Derivation>), String> {
+        let input_addressed = self
+            .outputs
+            .values()
+            .all(|output| matches!(output, DerivationOutput::Deferred));
+
+        if input_addressed {
+            for output in self.outputs.keys() {
+                self.env.insert(output.clone(), String::new());
+            }
+
+            let hash = self.hash_modulo()?;
+
+            if !hash.deferred {
+                for (output, value) in self.outputs.iter_mut() {
+                    let path = store::make_store_path(
+                        &format!("output:{output}"),
+                        &hash.hash,
+                        &Self::output_path_name(&self.name, output),
+                    );
+
+                    self.env.insert(output.clone(), path.clone());
+                    *value = DerivationOutput::InputAddressed(path);
+                }
+            }
+        } else {
+            for (output, value) in &self.outputs {
+                let path = match value {
+                    DerivationOutput::CAFixed { path, .. } => path.clone(),
+                    _ => store::hash_placeholder(output),
+                };
+
+                self.env.insert(output.clone(), path);
+            }
+        }
+
+        let references = self
+            .input_sources
+            .iter()
+            .chain(self.input_derivations.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+
+        let aterm = self.to_aterm();
+        let drv_path = store::make_text_path(&format!("{}.drv", self.name), &aterm, &references);
+
+        // Parsing what's written gives the same derivation back
+        debug_assert_eq!(Self::parse(&drv_path, &aterm).as_ref(), Ok(&self));
+
+        let derivation = Rc::new(self);
+
+        DERIVATIONS.with_borrow_mut(|derivations| {
+            derivations.insert(drv_path.clone(), derivation.clone())
+        });
+
+        Ok((drv_path, derivation))
+    }
+}
+
+fn hash_modulo_of(drv_path: &str) -> Result<DrvHash, String> {
+    if let Some(hash) = HASHES.with_borrow(|hashes| hashes.get(drv_path).cloned()) {
+        return Ok(hash);
+    }
+
+    let Some(derivation) = get(drv_path)? else {
+        return Err(format!(
+            "derivation '{drv_path}' is unknown, it wasn't instantiated by this evaluation nor is in the store"
+        ));
+    };
+
+    let hash = derivation.hash_modulo()?;
+
+    HASHES.with_borrow_mut(|hashes| hashes.insert(drv_path.to_owned(), hash.clone()));
+
+    Ok(hash)
+}
+
+/// Stands for `output` of `drv_path` when its path isn't known until it's
+/// built, `/<base32 sha256("nix-upstream-output:<hash>:<name>")>`
+fn downstream_placeholder(drv_path: &str, output: &str) -> String {
+    let file_name = drv_path.rsplit('/').next().unwrap_or_default();
+    let hash = file_name.split_once('-').unwrap_or_default().0;
+    let name = drv_name(drv_path).unwrap_or_default();
+
+    let clear_text = format!(
+        "nix-upstream-output:{hash}:{}",
+        Derivation::output_path_name(name, output)
+    );
+    let digest = hash::digest(Algorithm::SHA256, clear_text.as_bytes());
+
+    format!("/{}", store::to_base32(&digest))
+}
+
+/// Builds a `Derivation` out of the arguments of `derivationStrict`
+struct DerivationBuilder<'a> {
+    backtrace: &'a NixBacktrace,
+    name: String,
+    ignore_nulls: bool,
+    context: NixStringContext,
+}
+
+impl DerivationBuilder<'_> {
+    fn error(&self, message: String) -> crate::NixError {
+        self.backtrace
+            .to_error(NixLabelKind::Error, NixLabelMessage::Empty, message)
+    }
+
+    fn attr_error(&self, attr: &str, message: &str) -> crate::NixError {
+        self.error(format!(
+            "{message}, in the attribute '{attr}' of the derivation '{}'",
+            self.name
+        ))
+    }
+
+    /// The value of an attribute in the environment of the builder, coerced
+    /// like `toString` but with paths copied to the store. `passAsFile` is one
+    /// more list, writing the files is up to the builder.
+    fn coerce(&mut self, attr: &str, value: &NixValue) -> NixResult<String> {
+        let string = value
+            .coerce_to_string(self.backtrace, true, true)
+            .map_err(|err| self.attr_error(attr, &err.message))?;
+
+        self.context.extend(string.context().iter().cloned());
+
+        Ok(string.into_string())
+    }
+
+    /// The value of `attr`, if it's there and not ignored by `__ignoreNulls`
+    fn get(&self, attrs: &NixAttrSet, attr: &str) -> NixResult<Option<NixValueWrapped>> {
+        let Some(value) = attrs.get(attr) else {
+            return Ok(None);
+        };
+
+        let value = value.resolve(self.backtrace)?;
+
+        if self.ignore_nulls && matches!(*value.borrow(), NixValue::Null) {
+            return Ok(None);
+        }
+
+        Ok(Some(value))
+    }
+
+    fn get_bool(&self, attrs: &NixAttrSet, attr: &str) -> NixResult<bool> {
+        let Some(value) = self.get(attrs, attr)? else {
+            return Ok(false);
+        };
+        let value = value.borrow();
+
+        value.as_bool().ok_or_else(|| {
+            self.attr_error(
+                attr,
+                &format!("expected a bool but found {}", value.as_type_with_article()),
+            )
+        })
+    }
+
+    fn get_optional_string(&mut self, attrs: &NixAttrSet, attr: &str) -> NixResult<Option<String>> {
+        let Some(value) = self.get(attrs, attr)? else {
+            return Ok(None);
+        };
+        let value = value.borrow();
+
+        self.coerce(attr, &value).map(Some)
+    }
+
+    fn get_string(&mut self, attrs: &NixAttrSet, attr: &str) -> NixResult<String> {
+        let Some(value) = attrs.get(attr) else {
+            return Err(self.error(format!(
+                "required attribute '{attr}' missing in the derivation '{}'",
+                self.name
+            )));
+        };
+
+        let value = value.resolve(self.backtrace)?;
+        let value = value.borrow();
+
+        self.coerce(attr, &value)
+    }
+
+    fn get_list(&mut self, attrs: &NixAttrSet, attr: &str) -> NixResult<Vec<String>> {
+        let Some(value) = attrs.get(attr) else {
+            return Ok(vec![]);
+        };
+
+        let value = value.resolve(self.backtrace)?;
+        let value = value.borrow();
+
+        let Some(list) = value.as_list() else {
+            return Err(self.attr_error(
+                attr,
+                &format!("expected a list but found a {}", value.as_type()),
+            ));
+        };
+
+        list.0
+            .iter()
+            .map(|item| {
+                let item = item.resolve(self.backtrace)?;
+                let item = item.borrow();
+
+                self.coerce(attr, &item)
+            })
+            .collect()
+    }
+
+    fn outputs(&mut self, attrs: &NixAttrSet) -> NixResult<BTreeMap<String, DerivationOutput>> {
+        if !attrs.contains_key("outputs") {
+            return Ok(BTreeMap::from([(
+                String::from("out"),
+                DerivationOutput::Deferred,
+            )]));
+        }
+
+        let mut outputs = BTreeMap::new();
+
+        for output in self.get_list(attrs, "outputs")? {
+            if output == "drv" || !store::is_valid_name(&output) {
+                return Err(self.attr_error("outputs", &format!("invalid output name '{output}'")));
+            }
+
+            if outputs
+                .insert(output.clone(), DerivationOutput::Deferred)
+                .is_some()
+            {
+                return Err(self.attr_error("outputs", &format!("duplicate output '{output}'")));
+            }
+        }
+
+        if outputs.is_empty() {
+            return Err(self.attr_error("outputs", "a derivation must have at least one output"));
+        }
+
+        Ok(outputs)
+    }
+
+    /// Fix the outputs with `outputHash`, or address them by their contents
+    /// once built with `__contentAddressed`, or make them `__impure`
+    fn content_address(
+        &mut self,
+        attrs: &NixAttrSet,
+        outputs: &mut BTreeMap<String, DerivationOutput>,
+    ) -> NixResult<()> {
+        let content_addressed = self.get_bool(attrs, "__contentAddressed")?;
+        let impure = self.get_bool(attrs, "__impure")?;
+        let output_hash = self.get_optional_string(attrs, "outputHash")?;
+
+        let algorithm = match self.get_optional_string(attrs, "outputHashAlgo")? {
+            Some(name) if !name.is_empty() => {
+                Some(Algorithm::from_name(&name).ok_or_else(|| {
+                    self.attr_error(
+                        "outputHashAlgo",
+                        &format!("unknown hash algorithm '{name}'"),
+                    )
+                })?)
+            }
+            _ => None,
+        };
+
+        let method = match self.get_optional_string(attrs, "outputHashMode")? {
+            Some(mode) => Some(ContentAddressMethod::from_mode(&mode).ok_or_else(|| {
+                self.attr_error(
+                    "outputHashMode",
+                    &format!("invalid value '{mode}', expected 'flat', 'recursive' or 'git'"),
+                )
+            })?),
+            None => None,
+        };
+
+        if let Some(output_hash) = output_hash {
+            if outputs.len() != 1 || !outputs.contains_key("out") {
+                return Err(self.attr_error(
+                    "outputHash",
+                    "multiple outputs are not supported in fixed-output derivations",
+                ));
+            }
+
+            let method = method.unwrap_or(ContentAddressMethod::Flat);
+            let (algorithm, digest) = hash::parse(&output_hash, algorithm)
+                .map_err(|err| self.attr_error("outputHash", &err))?;
+
+            if method == ContentAddressMethod::Git && algorithm != Algorithm::SHA1 {
+                return Err(self.attr_error("outputHashMode", "git hashing must use SHA-1"));
+            }
+
+            outputs.insert(
+                String::from("out"),
+                DerivationOutput::CAFixed {
+                    path: store::make_fixed_output_path(method, algorithm, &digest, &self.name),
+                    hash_algo: format!("{}{}", method.prefix(), algorithm.name()),
+                    hash: hex::encode(digest),
+                },
+            );
+
+            return Ok(());
+        }
+
+        if content_addressed && impure {
+            return Err(self.attr_error(
+                "__impure",
+                "a derivation cannot be both content-addressed and impure",
+            ));
+        }
+
+        if content_addressed || impure {
+            let hash_algo = format!(
+                "{}{}",
+                method.unwrap_or(ContentAddressMethod::Recursive).prefix(),
+                algorithm.unwrap_or(Algorithm::SHA256).name()
+            );
+
+            for output in outputs.values_mut() {
+                *output = if impure {
+                    DerivationOutput::Impure(hash_algo.clone())
+                } else {
+                    DerivationOutput::CAFloating(hash_algo.clone())
+                };
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Add the derivations and sources the strings of the environment refer to
+    fn add_inputs(&self, derivation: &mut Derivation) -> Result<(), String> {
+        for elem in &self.context {
+            match elem {
+                NixContextElem::Opaque(path) => {
+                    derivation.input_sources.insert(path.clone());
+                }
+                NixContextElem::Built { drv_path, output } => {
+                    derivation
+                        .input_derivations
+                        .entry(drv_path.clone())
+                        .or_default()
+                        .insert(output.clone());
+                }
+                NixContextElem::DrvDeep(drv_path) => add_closure(derivation, drv_path)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// `drvPath` strings depend on the `.drv` itself and everything it needs
+fn add_closure(derivation: &mut Derivation, drv_path: &str) -> Result<(), String> {
+    if !derivation.input_sources.insert(drv_path.to_owned()) {
+        return Ok(());
+    }
+
+    let Some(input) = get(drv_path)? else {
+        return Ok(());
+    };
+
+    derivation
+        .input_derivations
+        .entry(drv_path.to_owned())
+        .or_default()
+        .extend(input.outputs.keys().cloned());
+
+    derivation
+        .input_sources
+        .extend(input.input_sources.iter().cloned());
+
+    for input_drv in input.input_derivations.keys() {
+        add_closure(derivation, input_drv)?;
+    }
+
+    Ok(())
+}
+
+/// `builtins.derivationStrict`, instantiate the derivation described by
+/// `attrs` and return `{ drvPath; <output> = <path>; ... }`
+pub fn derivation_strict(backtrace: &NixBacktrace, attrs: &NixAttrSet) -> NixResult<NixAttrSet> {
+    let mut builder = DerivationBuilder {
+        backtrace,
+        name: String::new(),
+        ignore_nulls: false,
+        context: NixStringContext::new(),
+    };
+
+    builder.name = builder.get_string(attrs, "name")?;
+
+    if let Err(err) = store::check_name(&builder.name) {
+        return Err(builder.attr_error("name", &err));
+    }
+
+    builder.ignore_nulls = builder.get_bool(attrs, "__ignoreNulls")?;
+
+    let mut outputs = builder.outputs(attrs)?;
+    builder.content_address(attrs, &mut outputs)?;
+
+    let mut derivation = Derivation {
+        name: builder.name.clone(),
+        outputs,
+        input_derivations: BTreeMap::new(),
+        input_sources: BTreeSet::new(),
+        system: builder.get_string(attrs, "system")?,
+        builder: builder.get_string(attrs, "builder")?,
+        args: builder.get_list(attrs, "args")?,
+        env: BTreeMap::new(),
+    };
+
+    // With `__structuredAttrs` everything goes to the builder as one JSON
+    // object, that doesn't need to be coerced to strings
+    let mut json = builder
+        .get_bool(attrs, "__structuredAttrs")?
+        .then(|| JsonWriter::new(backtrace, true));
+
+    if let Some(json) = &mut json {
+        json.start_object();
+    }
+
+    for (attr, value) in attrs.iter() {
+        // `meta` and `passthru` stay lazy on the derivation, they never reach
+        // the builder
+        if matches!(
+            attr.as_str(),
+            "args" | "__ignoreNulls" | "meta" | "passthru"
+        ) {
+            continue;
+        }
+
+        if json.is_some() && attr == "__structuredAttrs" {
+            continue;
+        }
+
+        let value = value.resolve(backtrace)?;
+        let value = value.borrow();
+
+        if builder.ignore_nulls && matches!(*value, NixValue::Null) {
+            continue;
+        }
+
+        // Like in Nix, the flags only reach the builder when they are false
+        if matches!(attr.as_str(), "__contentAddressed" | "__impure")
+            && value.as_bool() == Some(true)
+        {
+            continue;
+        }
+
+        if let Some(json) = &mut json {
+            json.write_entry(attr, &value)
+                .map_err(|err| builder.attr_error(attr, &err.message))?;
+
+            continue;
+        }
+
+        // Like in the `.drv` of Nix, `${placeholder "out"}` is kept as it is,
+        // the builder replaces it with the output path when it runs
+        let value = builder.coerce(attr, &value)?;
+
+        derivation.env.insert(attr.clone(), value);
+    }
+
+    if let Some(mut json) = json {
+        json.end_object();
+
+        let (json, context) = json.finish();
+
+        builder.context.extend(context);
+        derivation.env.insert("__json".to_owned(), json);
+    }
+
+    builder
+        .add_inputs(&mut derivation)
+        .map_err(|err| builder.error(err))?;
+
+    let (drv_path, derivation) = derivation.instantiate().map_err(|err| builder.error(err))?;
+
+    let mut out = NixAttrSet::new();
+
+    out.insert(
+        "drvPath".to_owned(),
+        NixValue::String(NixString::new(
+            drv_path.clone(),
+            NixStringContext::from([NixContextElem::DrvDeep(drv_path.clone())]),
+        ))
+        .wrap_var(),
+    );
+
+    for output in derivation.outputs.keys() {
+        let context = NixStringContext::from([NixContextElem::Built {
+            drv_path: drv_path.clone(),
+            output: output.clone(),
+        }]);
+
+        let path = match derivation.path(output) {
+            Some(path) => path.to_owned(),
+            None => downstream_placeholder(&drv_path, output),
+        };
+
+        out.insert(
+            output.clone(),
+            NixValue::String(NixString::new(path, context)).wrap_var(),
+        );
+    }
+
+    Ok(out)
+}
+
+/// `derivation`, the attributes of `derivationStrict` plus one set per
+/// output. Like in Nix, nothing is instantiated until a path is needed.
+pub fn derivation(backtrace: &NixBacktrace, attrs: &NixAttrSet) -> NixResult<NixValueWrapped> {
+    let outputs = match attrs.get("outputs") {
+        Some(outputs) => {
+            let outputs = outputs.resolve(backtrace)?;
+            let outputs = outputs.borrow();
+
+            let Some(outputs) = outputs.as_list() else {
+                return Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!(
+                        "derivation: expected 'outputs' to be a list but found a {}",
+                        outputs.as_type()
+                    ),
+                ));
+            };
+
+            outputs
+                .0
+                .iter()
+                .map(|output| {
+                    let output = output.resolve(backtrace)?;
+                    let output = output.borrow();
+
+                    output.as_string().cloned().ok_or_else(|| {
+                        backtrace.to_error(
+                            NixLabelKind::Error,
+                            NixLabelMessage::Empty,
+                            format!(
+                                "derivation: expected the outputs to be strings but found a {}",
+                                output.as_type()
+                            ),
+                        )
+                    })
+                })
+                .collect::<NixResult<Vec<_>>>()?
+        }
+        None => vec![String::from("out")],
+    };
+
+    // Nothing to return, `derivationStrict` has the error
+    if outputs.is_empty() {
+        derivation_strict(backtrace, attrs)?;
+    }
+
+    let strict = {
+        let attrs = attrs.clone();
+
+        LazyNixValue::new_eval(
+            backtrace.clone(),
+            Box::new(move |backtrace| {
+                Ok(NixValue::AttrSet(derivation_strict(backtrace, &attrs)?).wrap())
+            }),
+        )
+        .wrap_var()
+    };
+
+    let from_strict = |attr: String| {
+        let strict = strict.clone();
+
+        LazyNixValue::new_eval(
+            backtrace.clone(),
+            Box::new(move |backtrace| {
+                let strict = strict.resolve(backtrace)?;
+                let value = strict
+                    .borrow()
+                    .as_attr_set()
+                    .and_then(|strict| strict.get(&attr).cloned());
+
+                let Some(value) = value else {
+                    return Err(backtrace.to_error(
+                        NixLabelKind::Error,
+                        NixLabelMessage::Empty,
+                        format!("derivation: derivationStrict didn't return '{attr}'"),
+                    ));
+                };
+
+                value.resolve(backtrace)
+            }),
+        )
+        .wrap_var()
+    };
+
+    // Every output is a copy of the attributes, and all of them refer to each other
+    let sets = outputs
+        .iter()
+        .map(|_| NixValue::AttrSet(NixAttrSet::new()).wrap())
+        .collect::<Vec<_>>();
+
+    let mut common = attrs.clone();
+
+    for (output, set) in outputs.iter().zip(&sets) {
+        common.insert(
+            output.clone(),
+            LazyNixValue::Concrete(set.clone()).wrap_var(),
+        );
+    }
+
+    common.insert(
+        "all".to_owned(),
+        NixValue::List(crate::value::NixList(Rc::new(
+            sets.iter()
+                .map(|set| LazyNixValue::Concrete(set.clone()).wrap_var())
+                .collect(),
+        )))
+        .wrap_var(),
+    );
+    common.insert(
+        "drvAttrs".to_owned(),
+        NixValue::AttrSet(attrs.clone()).wrap_var(),
+    );
+
+    let drv_path = from_strict(String::from("drvPath"));
+
+    for (output, set) in outputs.iter().zip(&sets) {
+        let mut attrs = common.clone();
+
+        attrs.insert("drvPath".to_owned(), drv_path.clone());
+        attrs.insert("outPath".to_owned(), from_strict(output.clone()));
+        attrs.insert(
+            "outputName".to_owned(),
+            NixValue::String(output.as_str().into()).wrap_var(),
+        );
+        attrs.insert(
+            "type".to_owned(),
+            NixValue::String("derivation".into()).wrap_var(),
+        );
+
+        *set.borrow_mut() = NixValue::AttrSet(attrs);
+    }
+
+    Ok(sets[0].clone())
+}
@@ -0,0 +1,270 @@
+//! Reading `.drv` files back, the inverse of `Derivation::to_aterm`
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::iter::Peekable;
+use std::str::Chars;
+
+use thiserror::Error;
+
+use super::{drv_name, Derivation, DerivationOutput};
+use crate::builtins::hash::Algorithm;
+
+#[derive(Clone, Debug, Error, PartialEq, Eq)]
+pub enum DerivationParseError {
+    #[error("expected '{expected}' at byte {offset} of the derivation")]
+    Expected { expected: char, offset: usize },
+
+    #[error("unexpected end of the derivation at byte {0}")]
+    UnexpectedEnd(usize),
+
+    #[error("unknown escape '\\{escape}' at byte {offset} of the derivation")]
+    UnknownEscape { escape: char, offset: usize },
+
+    #[error("output '{0}' has a path, a hash algorithm and a hash that don't go together")]
+    InvalidOutput(String),
+
+    #[error("unknown hash algorithm '{hash_algo}' of the output '{output}'")]
+    UnknownHashAlgo { output: String, hash_algo: String },
+
+    #[error("invalid {hash_algo} hash '{hash}' of the output '{output}'")]
+    InvalidHash {
+        output: String,
+        hash_algo: String,
+        hash: String,
+    },
+
+    /// `DrvWithVersion(<version>, ...)`, written for dynamic derivations
+    #[error("unsupported derivation version '{0}'")]
+    UnsupportedVersion(String),
+
+    #[error("trailing content after the derivation at byte {0}")]
+    TrailingContent(usize),
+
+    #[error("the derivation has no name, neither in its path nor in its environment")]
+    MissingName,
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+    offset: usize,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> Result<char, DerivationParseError> {
+        let c = self
+            .chars
+            .next()
+            .ok_or(DerivationParseError::UnexpectedEnd(self.offset))?;
+        self.offset += c.len_utf8();
+
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), DerivationParseError> {
+        let offset = self.offset;
+
+        if self.next()? == expected {
+            Ok(())
+        } else {
+            Err(DerivationParseError::Expected { expected, offset })
+        }
+    }
+
+    fn expect_str(&mut self, expected: &str) -> Result<(), DerivationParseError> {
+        expected.chars().try_for_each(|c| self.expect(c))
+    }
+
+    fn string(&mut self) -> Result<String, DerivationParseError> {
+        self.expect('"')?;
+
+        let mut out = String::new();
+
+        loop {
+            match self.next()? {
+                '"' => return Ok(out),
+                '\\' => match self.next()? {
+                    '"' => out.push('"'),
+                    '\\' => out.push('\\'),
+                    'n' => out.push('\n'),
+                    'r' => out.push('\r'),
+                    't' => out.push('\t'),
+                    escape => {
+                        return Err(DerivationParseError::UnknownEscape {
+                            escape,
+                            offset: self.offset - escape.len_utf8(),
+                        })
+                    }
+                },
+                c => out.push(c),
+            }
+        }
+    }
+
+    /// `[item,item]`
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, DerivationParseError>,
+    ) -> Result<Vec<T>, DerivationParseError> {
+        self.expect('[')?;
+
+        let mut out = vec![];
+
+        if self.chars.peek() == Some(&']') {
+            self.next()?;
+            return Ok(out);
+        }
+
+        loop {
+            out.push(item(self)?);
+
+            match self.chars.peek() {
+                Some(']') => {
+                    self.next()?;
+                    return Ok(out);
+                }
+                _ => self.expect(',')?,
+            }
+        }
+    }
+
+    /// `("a","b")`
+    fn pair<A, B>(
+        &mut self,
+        first: impl FnOnce(&mut Self) -> Result<A, DerivationParseError>,
+        second: impl FnOnce(&mut Self) -> Result<B, DerivationParseError>,
+    ) -> Result<(A, B), DerivationParseError> {
+        self.expect('(')?;
+        let first = first(self)?;
+        self.expect(',')?;
+        let second = second(self)?;
+        self.expect(')')?;
+
+        Ok((first, second))
+    }
+
+    /// `("out","/nix/store/...","","")`, which fields are blank tells the kind
+    /// of output
+    fn output(&mut self) -> Result<(String, DerivationOutput), DerivationParseError> {
+        self.expect('(')?;
+        let name = self.string()?;
+        self.expect(',')?;
+        let path = self.string()?;
+        self.expect(',')?;
+        let hash_algo = self.string()?;
+        self.expect(',')?;
+        let hash = self.string()?;
+        self.expect(')')?;
+
+        let output = match (path.is_empty(), hash_algo.is_empty(), hash.as_str()) {
+            (false, true, "") => DerivationOutput::InputAddressed(path),
+            (true, true, "") => DerivationOutput::Deferred,
+            (true, false, "") => DerivationOutput::CAFloating(hash_algo),
+            (true, false, "impure") => DerivationOutput::Impure(hash_algo),
+            (false, false, hash) if !hash.is_empty() => DerivationOutput::CAFixed {
+                path,
+                hash_algo,
+                hash: hash.to_owned(),
+            },
+            _ => return Err(DerivationParseError::InvalidOutput(name)),
+        };
+
+        check_hash(&name, &output)?;
+
+        Ok((name, output))
+    }
+}
+
+/// The algorithm of content addressed outputs, and the hash of fixed ones
+fn check_hash(name: &str, output: &DerivationOutput) -> Result<(), DerivationParseError> {
+    let (hash_algo, hash) = match output {
+        DerivationOutput::InputAddressed(_) | DerivationOutput::Deferred => return Ok(()),
+        DerivationOutput::CAFloating(hash_algo) | DerivationOutput::Impure(hash_algo) => {
+            (hash_algo, None)
+        }
+        DerivationOutput::CAFixed {
+            hash_algo, hash, ..
+        } => (hash_algo, Some(hash)),
+    };
+
+    let algorithm = ["r:", "git:"]
+        .into_iter()
+        .find_map(|method| hash_algo.strip_prefix(method))
+        .unwrap_or(hash_algo);
+
+    let Some(algorithm) = Algorithm::from_name(algorithm) else {
+        return Err(DerivationParseError::UnknownHashAlgo {
+            output: name.to_owned(),
+            hash_algo: hash_algo.clone(),
+        });
+    };
+
+    match hash {
+        Some(hash) if hex::decode(hash).map_or(true, |digest| digest.len() != algorithm.size()) => {
+            Err(DerivationParseError::InvalidHash {
+                output: name.to_owned(),
+                hash_algo: algorithm.name().to_owned(),
+                hash: hash.clone(),
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Derivation {
+    /// The derivation written in `aterm`, the `.drv` at `drv_path`. Its name
+    /// is the one of the path, or the `name` of the environment otherwise.
+    pub fn parse(drv_path: &str, aterm: &str) -> Result<Self, DerivationParseError> {
+        let mut parser = Parser {
+            chars: aterm.chars().peekable(),
+            offset: 0,
+        };
+
+        if aterm.starts_with("DrvWithVersion(") {
+            parser.expect_str("DrvWithVersion(")?;
+
+            return Err(DerivationParseError::UnsupportedVersion(parser.string()?));
+        }
+
+        parser.expect_str("Derive(")?;
+        let outputs = parser.list(Parser::output)?;
+        parser.expect(',')?;
+        let input_derivations = parser
+            .list(|parser| parser.pair(Parser::string, |parser| parser.list(Parser::string)))?;
+        parser.expect(',')?;
+        let input_sources = parser.list(Parser::string)?;
+        parser.expect(',')?;
+        let system = parser.string()?;
+        parser.expect(',')?;
+        let builder = parser.string()?;
+        parser.expect(',')?;
+        let args = parser.list(Parser::string)?;
+        parser.expect(',')?;
+        let env = parser.list(|parser| parser.pair(Parser::string, Parser::string))?;
+        parser.expect(')')?;
+
+        if parser.chars.peek().is_some() {
+            return Err(DerivationParseError::TrailingContent(parser.offset));
+        }
+
+        let env = env.into_iter().collect::<BTreeMap<_, _>>();
+
+        let name = drv_name(drv_path)
+            .map(str::to_owned)
+            .or_else(|| env.get("name").cloned())
+            .ok_or(DerivationParseError::MissingName)?;
+
+        Ok(Derivation {
+            name,
+            outputs: outputs.into_iter().collect(),
+            input_derivations: input_derivations
+                .into_iter()
+                .map(|(path, outputs)| (path, outputs.into_iter().collect::<BTreeSet<_>>()))
+                .collect(),
+            input_sources: input_sources.into_iter().collect(),
+            system,
+            builder,
+            args,
+            env,
+        })
+    }
+}
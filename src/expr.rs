@@ -1,14 +1,16 @@
 use std::ops::Deref;
+use std::path::{Component, Path, PathBuf};
 use std::rc::Rc;
 
 use rnix::ast::{self, AstToken, HasEntry};
 use rowan::ast::AstNode;
 
 use crate::result::{NixBacktrace, NixSpan};
-use crate::value::{NixLambda, NixList};
+use crate::value::{NixArithOp, NixLambda, NixList, NixNumber, NixString};
+use crate::{impurities, log, search_path};
 use crate::{
-    LazyNixValue, NixAttrSet, NixBacktraceKind, NixError, NixLabel, NixLabelKind, NixLabelMessage,
-    NixLambdaParam, NixResult, NixValue, NixValueWrapped, NixVar, Scope,
+    LazyNixValue, NixAttrSet, NixBacktraceKind, NixError, NixErrorKind, NixLabel, NixLabelKind,
+    NixLabelMessage, NixLambdaParam, NixResult, NixValue, NixValueWrapped, NixVar, Scope,
 };
 
 impl Scope {
@@ -24,14 +26,45 @@ impl Scope {
             .pop()
             .expect("Attrpath requires at least one attribute");
 
-        let target =
-            self.resolve_attr_set_path(backtrace, out.clone(), attr_path.into_iter())??;
+        let mut path = vec![];
 
-        if !target.borrow().is_attr_set() {
-            todo!("Error handling")
+        let Some(target) =
+            self.resolve_attr_set_path(backtrace, out.clone(), attr_path.into_iter(), &mut path)?
+        else {
+            return Ok(out);
         };
 
-        let attr = self.resolve_attr(backtrace, &last_attr_path)?;
+        let Some(attr) = self.resolve_attr_key(backtrace, &last_attr_path)? else {
+            return Ok(out);
+        };
+
+        let existing = target.borrow().as_attr_set().unwrap().get(&attr).cloned();
+
+        if let Some(existing) = existing {
+            path.push(attr.clone());
+
+            // `{ a.b = 1; a = { c = 2; }; }`, both sets are written in place
+            if let ast::Expr::AttrSet(literal) = &attr_value {
+                if literal.rec_token().is_none() {
+                    if let Some(set) = Self::mergeable_attr_set(backtrace, &existing)? {
+                        for entry in literal.entries() {
+                            self.insert_entry_to_attrset(backtrace, set.clone(), entry)?;
+                        }
+
+                        return Ok(out);
+                    }
+                }
+            }
+
+            let previous = target
+                .borrow()
+                .as_attr_set()
+                .unwrap()
+                .position(&attr)
+                .cloned();
+
+            return Err(self.duplicate_attr_error(backtrace, &path, &last_attr_path, previous));
+        }
 
         let child = LazyNixValue::Pending(
             self.new_backtrace(backtrace, &attr_value),
@@ -40,10 +73,12 @@ impl Scope {
         )
         .wrap_var();
 
+        let position = NixSpan::from_ast_node(&self.file, &last_attr_path).into();
+
         let mut target = target.borrow_mut();
         let set = target.as_attr_set_mut().unwrap();
 
-        set.insert(attr, child);
+        set.insert_with_position(attr, child, position);
 
         Ok(out)
     }
@@ -70,6 +105,18 @@ impl Scope {
 
                 for attr_node in entry.attrs() {
                     let attr = self.resolve_attr(backtrace, &attr_node)?;
+                    let position = NixSpan::from_ast_node(&self.file, &attr_node).into();
+
+                    if out.borrow().as_attr_set().unwrap().contains_key(&attr) {
+                        let previous = out.borrow().as_attr_set().unwrap().position(&attr).cloned();
+
+                        return Err(self.duplicate_attr_error(
+                            backtrace,
+                            &[attr],
+                            &attr_node,
+                            previous,
+                        ));
+                    }
 
                     if let Some((from, from_expr)) = &from {
                         let value = {
@@ -82,12 +129,15 @@ impl Scope {
                             LazyNixValue::new_eval(
                                 self.new_backtrace(backtrace, &from_expr),
                                 Box::new(move |backtrace| {
-                                    from.resolve(backtrace)?
+                                    let value = from
+                                        .resolve(backtrace)?
                                         .borrow()
                                         .as_attr_set()
                                         .unwrap()
                                         .get(&attr)
-                                        .cloned()
+                                        .cloned();
+
+                                    value
                                         .ok_or_else(|| {
                                             backtrace.to_labeled_error(
                                                 vec![
@@ -119,7 +169,7 @@ impl Scope {
                         out.borrow_mut()
                             .as_attr_set_mut()
                             .unwrap()
-                            .insert(attr, value.wrap_var());
+                            .insert_with_position(attr, value.wrap_var(), position);
                     } else {
                         let value = {
                             let scope = self.clone();
@@ -149,7 +199,7 @@ impl Scope {
                         out.borrow_mut()
                             .as_attr_set_mut()
                             .unwrap()
-                            .insert(attr, value.wrap_var());
+                            .insert_with_position(attr, value.wrap_var(), position);
                     }
                 }
 
@@ -164,7 +214,7 @@ impl Scope {
         }
     }
 
-    fn new_backtrace(
+    pub fn new_backtrace(
         self: &Rc<Self>,
         backtrace: &NixBacktrace,
         node: &impl AstNode,
@@ -182,29 +232,32 @@ impl Scope {
         node: ast::Expr,
     ) -> NixResult<NixVar> {
         let backtrace = &backtrace.visit(&self.file, &node);
-
-        match node {
-            ast::Expr::Apply(node) => self.visit_apply(backtrace, node),
-            ast::Expr::Assert(node) => self.visit_assert(backtrace, node),
-            ast::Expr::AttrSet(node) => self.visit_attrset(backtrace, node),
-            ast::Expr::BinOp(node) => self.visit_binop(backtrace, node),
-            ast::Expr::Error(node) => self.visit_error(backtrace, node),
-            ast::Expr::HasAttr(node) => self.visit_hasattr(backtrace, node),
-            ast::Expr::Ident(node) => self.visit_ident(backtrace, node),
-            ast::Expr::IfElse(node) => self.visit_ifelse(backtrace, node),
-            ast::Expr::Lambda(node) => self.visit_lambda(backtrace, node),
-            ast::Expr::LegacyLet(node) => self.visit_legacylet(backtrace, node),
-            ast::Expr::LetIn(node) => self.visit_letin(backtrace, node),
-            ast::Expr::List(node) => self.visit_list(backtrace, node),
-            ast::Expr::Literal(node) => self.visit_literal(backtrace, node),
-            ast::Expr::Paren(node) => self.visit_paren(backtrace, node),
-            ast::Expr::Path(node) => self.visit_path(backtrace, node),
-            ast::Expr::Root(node) => self.visit_root(backtrace, node),
-            ast::Expr::Select(node) => self.visit_select(backtrace, node),
-            ast::Expr::Str(node) => self.visit_str(backtrace, node),
-            ast::Expr::UnaryOp(node) => self.visit_unaryop(backtrace, node),
-            ast::Expr::With(node) => self.visit_with(backtrace, node),
-        }
+        let syntax = node.syntax().clone();
+
+        self.file
+            .constants
+            .memoize(backtrace, &syntax, || match node {
+                ast::Expr::Apply(node) => self.visit_apply(backtrace, node),
+                ast::Expr::Assert(node) => self.visit_assert(backtrace, node),
+                ast::Expr::AttrSet(node) => self.visit_attrset(backtrace, node),
+                ast::Expr::BinOp(node) => self.visit_binop(backtrace, node),
+                ast::Expr::Error(node) => self.visit_error(backtrace, node),
+                ast::Expr::HasAttr(node) => self.visit_hasattr(backtrace, node),
+                ast::Expr::Ident(node) => self.visit_ident(backtrace, node),
+                ast::Expr::IfElse(node) => self.visit_ifelse(backtrace, node),
+                ast::Expr::Lambda(node) => self.visit_lambda(backtrace, node),
+                ast::Expr::LegacyLet(node) => self.visit_legacylet(backtrace, node),
+                ast::Expr::LetIn(node) => self.visit_letin(backtrace, node),
+                ast::Expr::List(node) => self.visit_list(backtrace, node),
+                ast::Expr::Literal(node) => self.visit_literal(backtrace, node),
+                ast::Expr::Paren(node) => self.visit_paren(backtrace, node),
+                ast::Expr::Path(node) => self.visit_path(backtrace, node),
+                ast::Expr::Root(node) => self.visit_root(backtrace, node),
+                ast::Expr::Select(node) => self.visit_select(backtrace, node),
+                ast::Expr::Str(node) => self.visit_str(backtrace, node),
+                ast::Expr::UnaryOp(node) => self.visit_unaryop(backtrace, node),
+                ast::Expr::With(node) => self.visit_with(backtrace, node),
+            })
     }
 
     pub fn visit_apply(
@@ -214,17 +267,67 @@ impl Scope {
     ) -> NixResult<NixVar> {
         let lambda_backtrace = backtrace.change_span((&self.file, &node.lambda().unwrap()));
 
-        self.visit_expr(&lambda_backtrace, node.lambda().unwrap())?
-            .resolve(&lambda_backtrace)?
-            .borrow()
-            .as_lambda()
-            .ok_or_else(|| todo!("Error handling: Lambda cast"))
-            .and_then(|l| {
-                let backtrace = &backtrace.change_span((&self.file, &node.argument().unwrap()));
+        let function = self
+            .visit_expr(&lambda_backtrace, node.lambda().unwrap())?
+            .resolve(&lambda_backtrace)?;
 
-                let argument = self.visit_expr(backtrace, node.argument().unwrap())?;
-                l.call(backtrace, argument)
-            })
+        let argument = node.argument().unwrap();
+        let backtrace = &backtrace.change_span((&self.file, &argument));
+
+        // The argument is a thunk, only what the function uses is evaluated
+        let argument = LazyNixValue::Pending(
+            self.new_backtrace(backtrace, &argument),
+            self.clone(),
+            argument,
+        )
+        .wrap_var();
+
+        self.apply_function(backtrace, &node, function, argument)
+    }
+
+    /// Sets with `__functor` are called as `set.__functor set argument`
+    fn apply_function(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::Apply,
+        function: NixValueWrapped,
+        argument: NixVar,
+    ) -> NixResult<NixVar> {
+        let functor = match &*function.borrow() {
+            NixValue::Lambda(lambda) => return lambda.call(backtrace, argument),
+            NixValue::AttrSet(set) => set.get("__functor").cloned(),
+            _ => None,
+        };
+
+        let Some(functor) = functor else {
+            return Err(backtrace.to_labeled_error(
+                vec![
+                    NixLabel::new(
+                        NixSpan::from_ast_node(&self.file, &node.lambda().unwrap()).into(),
+                        NixLabelMessage::Empty,
+                        NixLabelKind::Error,
+                    ),
+                    NixLabel::new(
+                        NixSpan::from_ast_node(&self.file, &node.argument().unwrap()).into(),
+                        NixLabelMessage::Custom("Called with this argument".to_owned()),
+                        NixLabelKind::Help,
+                    ),
+                ],
+                format!(
+                    "attempt to call something which is not a function but {}",
+                    function.borrow().as_type_with_article()
+                ),
+            ));
+        };
+
+        let functor = functor.resolve(backtrace)?;
+        let this = LazyNixValue::Concrete(function).wrap_var();
+
+        let function = self
+            .apply_function(backtrace, node, functor, this)?
+            .resolve(backtrace)?;
+
+        self.apply_function(backtrace, node, function, argument)
     }
 
     pub fn visit_assert(
@@ -232,29 +335,67 @@ impl Scope {
         backtrace: &NixBacktrace,
         node: ast::Assert,
     ) -> NixResult<NixVar> {
+        let condition_node = node.condition().unwrap();
         let condition = self
-            .visit_expr(backtrace, node.condition().unwrap())?
+            .visit_expr(backtrace, condition_node.clone())?
             .resolve(backtrace)?;
 
-        let Some(condition) = condition.borrow().as_bool() else {
-            todo!("Error handling")
-        };
-
-        if condition {
-            node.body().map_or_else(
+        if self.expect_bool(backtrace, &condition_node, &condition.borrow())? {
+            return node.body().map_or_else(
                 || Ok(NixValue::Null.wrap_var()),
                 |expr| self.visit_expr(backtrace, expr),
-            )
-        } else {
-            Err(backtrace.to_labeled_error(
-                vec![NixLabel::new(
-                    NixSpan::from_ast_node(&self.file, &node.condition().unwrap()).into(),
-                    NixLabelMessage::AssertionFailed,
-                    NixLabelKind::Error,
-                )],
-                "assert failed",
-            ))
+            );
         }
+
+        let mut labels = vec![NixLabel::new(
+            NixSpan::from_ast_node(&self.file, &condition_node).into(),
+            NixLabelMessage::AssertionFailed,
+            NixLabelKind::Error,
+        )];
+
+        // Comparisons show what was compared, operands that fail now are left out
+        let mut comparison = condition_node.clone();
+
+        while let ast::Expr::Paren(paren) = comparison {
+            comparison = paren.expr().unwrap();
+        }
+
+        if let ast::Expr::BinOp(binop) = comparison {
+            use ast::BinOpKind::*;
+
+            if matches!(
+                binop.operator(),
+                Some(Equal | NotEqual | Less | LessOrEq | More | MoreOrEq)
+            ) {
+                let operands = [("left", binop.lhs()), ("right", binop.rhs())];
+
+                for (side, operand) in operands {
+                    let Some(operand) = operand else {
+                        continue;
+                    };
+
+                    let Ok(value) = self
+                        .visit_expr(backtrace, operand.clone())
+                        .and_then(|value| value.resolve(backtrace))
+                    else {
+                        continue;
+                    };
+
+                    labels.push(NixLabel::new(
+                        NixSpan::from_ast_node(&self.file, &operand).into(),
+                        NixLabelMessage::Custom(format!("{side}: {}", value.borrow())),
+                        NixLabelKind::Help,
+                    ));
+                }
+            }
+        }
+
+        Err(backtrace
+            .to_labeled_error(
+                labels,
+                format!("assertion '{}' failed", condition_node.syntax().text()),
+            )
+            .with_kind(NixErrorKind::Assert))
     }
 
     pub fn visit_attrset(
@@ -265,11 +406,7 @@ impl Scope {
         let is_recursive = node.rec_token().is_some();
 
         if is_recursive {
-            let scope = self.clone().new_child();
-
-            for entry in node.entries() {
-                scope.insert_entry_to_attrset(backtrace, scope.variables.clone(), entry)?;
-            }
+            let scope = self.visit_rec_entries(backtrace, node.entries())?;
 
             Ok(LazyNixValue::Concrete(scope.variables.clone()).wrap_var())
         } else {
@@ -283,6 +420,35 @@ impl Scope {
         }
     }
 
+    /// Entries of `rec { }` and `let { }`, in a scope where they see each other
+    fn visit_rec_entries(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        entries: impl Iterator<Item = ast::Entry>,
+    ) -> NixResult<Rc<Scope>> {
+        let scope = self.clone().new_child();
+
+        // Dynamic names may use any sibling, they are inserted once every
+        // static one is in the scope
+        let (dynamic, entries): (Vec<_>, Vec<_>) = entries.partition(|entry| {
+            let ast::Entry::AttrpathValue(entry) = entry else {
+                return false;
+            };
+
+            entry
+                .attrpath()
+                .unwrap()
+                .attrs()
+                .any(|attr| matches!(attr, ast::Attr::Dynamic(_)))
+        });
+
+        for entry in entries.into_iter().chain(dynamic) {
+            scope.insert_entry_to_attrset(backtrace, scope.variables.clone(), entry)?;
+        }
+
+        Ok(scope)
+    }
+
     pub fn visit_binop(
         self: &Rc<Self>,
         backtrace: &NixBacktrace,
@@ -293,31 +459,53 @@ impl Scope {
             .resolve(backtrace)?;
 
         match node.operator().unwrap() {
-            ast::BinOpKind::Concat => lhs
-                .borrow()
-                .as_list()
-                .ok_or_else(|| todo!("Error handling"))
-                .and_then(|ref lhs| {
-                    let rhs = self
-                        .visit_expr(backtrace, node.rhs().unwrap())
-                        .and_then(|rhs| rhs.resolve(backtrace))
-                        .and_then(|rhs| {
-                            rhs.borrow()
-                                .as_list()
-                                .ok_or_else(|| todo!("Error handling"))
-                        })?;
+            ast::BinOpKind::Concat => {
+                let rhs = self
+                    .visit_expr(backtrace, node.rhs().unwrap())?
+                    .resolve(backtrace)?;
+
+                let (lhs, rhs) = (lhs.borrow(), rhs.borrow());
+
+                let (Some(lhs_list), Some(rhs_list)) = (lhs.as_list(), rhs.as_list()) else {
+                    return Err(self.operands_error(
+                        backtrace,
+                        &node,
+                        &lhs,
+                        &rhs,
+                        format!(
+                            "cannot concatenate {} and {}, both must be lists",
+                            lhs.as_type_with_article(),
+                            rhs.as_type_with_article()
+                        ),
+                    ));
+                };
 
-                    let mut out = Vec::with_capacity(lhs.0.len() + rhs.0.len());
+                let mut out = Vec::with_capacity(lhs_list.0.len() + rhs_list.0.len());
 
-                    out.extend(lhs.0.iter().cloned());
-                    out.extend(rhs.0.iter().cloned());
+                out.extend(lhs_list.0.iter().cloned());
+                out.extend(rhs_list.0.iter().cloned());
 
-                    Ok(NixValue::List(NixList(Rc::new(out))).wrap_var())
-                }),
+                Ok(NixValue::List(NixList(Rc::new(out))).wrap_var())
+            }
 
             ast::BinOpKind::Update => {
-                if let None = lhs.borrow().as_attr_set() {
-                    todo!("Error handling");
+                if !lhs.borrow().is_attr_set() {
+                    let lhs_node = node.lhs().unwrap();
+
+                    return Err(backtrace.to_labeled_error(
+                        vec![NixLabel::new(
+                            NixSpan::from_ast_node(&self.file, &lhs_node).into(),
+                            NixLabelMessage::Custom(format!(
+                                "this is {}",
+                                lhs.borrow().as_type_with_article()
+                            )),
+                            NixLabelKind::Error,
+                        )],
+                        format!(
+                            "cannot update {} with //, it must be a set",
+                            lhs.borrow().as_type_with_article()
+                        ),
+                    ));
                 }
 
                 Ok(LazyNixValue::UpdateResolve {
@@ -329,156 +517,246 @@ impl Scope {
                 .wrap_var())
             }
             ast::BinOpKind::Add => match lhs.borrow().deref() {
-                NixValue::String(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .cast_to_string()
-                    .ok_or_else(|| todo!("Error handling"))
-                    .map(|rhs| NixValue::String(format!("{lhs}{rhs}")).wrap_var()),
-                NixValue::Int(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .as_int()
-                    .ok_or_else(|| todo!("Error handling: Int cast"))
-                    .map(|rhs| *lhs + rhs)
-                    .map(NixValue::Int)
-                    .map(NixValue::wrap_var),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Cannot add",
-                    None,
-                )),
-            },
-            ast::BinOpKind::Sub => match lhs.borrow().deref() {
-                NixValue::Int(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .as_int()
-                    .ok_or_else(|| todo!("Error handling: Int cast"))
-                    .map(|rhs| *lhs - rhs)
-                    .map(NixValue::Int)
-                    .map(NixValue::wrap_var),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Cannot sub",
-                    None,
-                )),
-            },
+                NixValue::String(lhs) => {
+                    let rhs_node = node.rhs().unwrap();
+                    let rhs = self
+                        .visit_expr(backtrace, rhs_node.clone())?
+                        .resolve(backtrace)?;
 
-            ast::BinOpKind::Mul => match lhs.borrow().deref() {
-                NixValue::Int(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .as_int()
-                    .ok_or_else(|| todo!("Error handling: Int cast"))
-                    .map(|rhs| *lhs * rhs)
-                    .map(NixValue::Int)
-                    .map(NixValue::wrap_var),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Cannot mul",
-                    None,
-                )),
-            },
-            ast::BinOpKind::Div => match lhs.borrow().deref() {
-                NixValue::Int(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .as_int()
-                    .ok_or_else(|| todo!("Error handling: Int cast"))
-                    .map(|rhs| *lhs / rhs)
-                    .map(NixValue::Int)
-                    .map(NixValue::wrap_var),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Cannot div",
-                    None,
-                )),
+                    let rhs = self.coerce_to_string(backtrace, &rhs_node, &rhs.borrow(), true)?;
+
+                    let mut lhs = lhs.clone();
+                    lhs.push(&rhs);
+
+                    Ok(NixValue::String(lhs).wrap_var())
+                }
+                NixValue::Path(lhs) => self.visit_path_append(backtrace, &node, lhs),
+                lhs => self.visit_arith(backtrace, &node, NixArithOp::Add, lhs),
             },
-            ast::BinOpKind::And => lhs
-                .borrow()
-                .as_bool()
-                .ok_or_else(|| todo!("Error handling"))
-                .and_then(|lhs| {
-                    lhs.then(|| self.visit_expr(backtrace, node.rhs().unwrap()))
-                        .unwrap_or_else(|| Ok(NixValue::Bool(false).wrap_var()))
-                }),
+            ast::BinOpKind::Sub => {
+                self.visit_arith(backtrace, &node, NixArithOp::Sub, &lhs.borrow())
+            }
+            ast::BinOpKind::Mul => {
+                self.visit_arith(backtrace, &node, NixArithOp::Mul, &lhs.borrow())
+            }
+            ast::BinOpKind::Div => {
+                self.visit_arith(backtrace, &node, NixArithOp::Div, &lhs.borrow())
+            }
+            ast::BinOpKind::And => {
+                if self.expect_bool(backtrace, &node.lhs().unwrap(), &lhs.borrow())? {
+                    self.visit_bool_rhs(backtrace, &node)
+                } else {
+                    Ok(NixValue::Bool(false).wrap_var())
+                }
+            }
             ast::BinOpKind::Equal => self
                 .visit_expr(backtrace, node.rhs().unwrap())
                 .and_then(|rhs| rhs.resolve(backtrace))
-                .and_then(|rhs| rhs.borrow().deref().try_eq(&*lhs.borrow(), backtrace))
+                .and_then(|rhs| NixValue::try_eq_wrapped(&lhs, &rhs, backtrace))
                 .map(NixValue::Bool)
                 .map(NixValue::wrap_var),
-            ast::BinOpKind::Implication => lhs
-                .borrow()
-                .as_bool()
-                .ok_or_else(|| todo!("Error handling"))
-                .and_then(|lhs| {
-                    lhs.then(|| self.visit_expr(backtrace, node.rhs().unwrap()))
-                        .unwrap_or_else(|| Ok(NixValue::Bool(true).wrap_var()))
-                }),
-            ast::BinOpKind::Less => match lhs.borrow().deref() {
-                NixValue::Int(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .as_int()
-                    .ok_or_else(|| todo!("Error handling"))
-                    .map(|rhs| NixValue::Bool(*lhs < rhs).wrap_var()),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Cannot less",
-                    None,
-                )),
-            },
-            ast::BinOpKind::LessOrEq => match lhs.borrow().deref() {
-                NixValue::Int(lhs) => self
-                    .visit_expr(backtrace, node.rhs().unwrap())?
-                    .resolve(backtrace)?
-                    .borrow()
-                    .as_int()
-                    .ok_or_else(|| todo!("Error handling"))
-                    .map(|rhs| NixValue::Bool(*lhs <= rhs).wrap_var()),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Cannot LessOrEq",
-                    None,
-                )),
-            },
-            ast::BinOpKind::More => Err(NixError::todo(
-                NixSpan::from_ast_node(&self.file, &node).into(),
-                "More op",
-                None,
-            )),
-            ast::BinOpKind::MoreOrEq => Err(NixError::todo(
-                NixSpan::from_ast_node(&self.file, &node).into(),
-                "MoreOrEq op",
-                None,
-            )),
+            ast::BinOpKind::Implication => {
+                if self.expect_bool(backtrace, &node.lhs().unwrap(), &lhs.borrow())? {
+                    self.visit_bool_rhs(backtrace, &node)
+                } else {
+                    Ok(NixValue::Bool(true).wrap_var())
+                }
+            }
+            // Everything is defined with `<`, like Nix does
+            ast::BinOpKind::Less => self.visit_compare(backtrace, &node, lhs, false, false),
+            ast::BinOpKind::LessOrEq => self.visit_compare(backtrace, &node, lhs, true, true),
+            ast::BinOpKind::More => self.visit_compare(backtrace, &node, lhs, true, false),
+            ast::BinOpKind::MoreOrEq => self.visit_compare(backtrace, &node, lhs, false, true),
             ast::BinOpKind::NotEqual => self
                 .visit_expr(backtrace, node.rhs().unwrap())
                 .and_then(|rhs| rhs.resolve(backtrace))
-                .and_then(|rhs| rhs.borrow().deref().try_eq(&*lhs.borrow(), backtrace))
+                .and_then(|rhs| NixValue::try_eq_wrapped(&lhs, &rhs, backtrace))
                 .map(std::ops::Not::not)
                 .map(NixValue::Bool)
                 .map(NixValue::wrap_var),
-            ast::BinOpKind::Or => lhs
-                .borrow()
-                .as_bool()
-                .ok_or_else(|| todo!("Error handling"))
-                .and_then(|lhs| {
-                    (!lhs)
-                        .then(|| self.visit_expr(backtrace, node.rhs().unwrap()))
-                        .unwrap_or_else(|| Ok(NixValue::Bool(true).wrap_var()))
-                }),
+            ast::BinOpKind::Or => {
+                if self.expect_bool(backtrace, &node.lhs().unwrap(), &lhs.borrow())? {
+                    Ok(NixValue::Bool(true).wrap_var())
+                } else {
+                    self.visit_bool_rhs(backtrace, &node)
+                }
+            }
         }
     }
 
+    /// The rhs of `&&`, `||` and `->` when the lhs doesn't decide, it must be a bool too
+    fn visit_bool_rhs(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::BinOp,
+    ) -> NixResult<NixVar> {
+        let rhs_node = node.rhs().unwrap();
+        let rhs = self
+            .visit_expr(backtrace, rhs_node.clone())?
+            .resolve(backtrace)?;
+
+        let rhs = self.expect_bool(backtrace, &rhs_node, &rhs.borrow())?;
+
+        Ok(NixValue::Bool(rhs).wrap_var())
+    }
+
+    /// Conditions of `if`, `assert` and the logical operators
+    fn expect_bool(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::Expr,
+        value: &NixValue,
+    ) -> NixResult<bool> {
+        value.as_bool().ok_or_else(|| {
+            backtrace.to_labeled_error(
+                vec![NixLabel::new(
+                    NixSpan::from_ast_node(&self.file, node).into(),
+                    NixLabelMessage::Empty,
+                    NixLabelKind::Error,
+                )],
+                format!(
+                    "expected a bool but found {}: {value}",
+                    value.as_type_with_article()
+                ),
+            )
+        })
+    }
+
+    /// Operands of types the operator doesn't take, each one says its type
+    fn operands_error(
+        &self,
+        backtrace: &NixBacktrace,
+        node: &ast::BinOp,
+        lhs: &NixValue,
+        rhs: &NixValue,
+        message: String,
+    ) -> NixError {
+        let label = |expr: ast::Expr, value: &NixValue| {
+            NixLabel::new(
+                NixSpan::from_ast_node(&self.file, &expr).into(),
+                NixLabelMessage::Custom(format!("this is {}", value.as_type_with_article())),
+                NixLabelKind::Error,
+            )
+        };
+
+        backtrace.to_labeled_error(
+            vec![
+                label(node.lhs().unwrap(), lhs),
+                label(node.rhs().unwrap(), rhs),
+            ],
+            message,
+        )
+    }
+
+    fn binop_error(
+        &self,
+        backtrace: &NixBacktrace,
+        node: &ast::BinOp,
+        message: String,
+    ) -> NixError {
+        backtrace.to_labeled_error(
+            vec![NixLabel::new(
+                NixSpan::from_ast_node(&self.file, node).into(),
+                NixLabelMessage::Empty,
+                NixLabelKind::Error,
+            )],
+            message,
+        )
+    }
+
+    /// `./dir + "/file"`, the result is still a path and nothing is copied
+    fn visit_path_append(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::BinOp,
+        lhs: &Path,
+    ) -> NixResult<NixVar> {
+        let rhs = self
+            .visit_expr(backtrace, node.rhs().unwrap())?
+            .resolve(backtrace)?;
+        let rhs = rhs.borrow();
+        let lhs_path = lhs;
+
+        let rhs = match &*rhs {
+            NixValue::String(rhs) if rhs.has_context() => {
+                return Err(self.binop_error(
+                    backtrace,
+                    node,
+                    "a string that refers to a store path cannot be appended to a path".to_owned(),
+                ))
+            }
+            NixValue::Path(rhs) => rhs.display().to_string(),
+            rhs => match rhs.cast_to_string() {
+                Some(rhs) => rhs,
+                None => {
+                    return Err(self.operands_error(
+                        backtrace,
+                        node,
+                        &NixValue::Path(lhs_path.to_path_buf()),
+                        rhs,
+                        format!("cannot add {} to a path", rhs.as_type_with_article()),
+                    ))
+                }
+            },
+        };
+
+        let path = format!("{}{rhs}", lhs.display());
+
+        Ok(NixValue::Path(normalize_path(&path)).wrap_var())
+    }
+
+    /// Numeric operators, they behave like `builtins.add` and the others
+    fn visit_arith(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::BinOp,
+        op: NixArithOp,
+        lhs: &NixValue,
+    ) -> NixResult<NixVar> {
+        let rhs = self
+            .visit_expr(backtrace, node.rhs().unwrap())?
+            .resolve(backtrace)?;
+
+        let rhs = rhs.borrow();
+
+        let value = NixValue::arith(op, lhs, &rhs).map_err(|message| {
+            // Numbers only fail by dividing by zero or overflowing
+            if NixNumber::from_value(lhs).is_some() && NixNumber::from_value(&rhs).is_some() {
+                self.binop_error(backtrace, node, message)
+            } else {
+                self.operands_error(backtrace, node, lhs, &rhs, message)
+            }
+        })?;
+
+        Ok(value.wrap_var())
+    }
+
+    /// `lhs < rhs`, or `rhs < lhs` when `swap`, negated when `negate`
+    fn visit_compare(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::BinOp,
+        lhs: NixValueWrapped,
+        swap: bool,
+        negate: bool,
+    ) -> NixResult<NixVar> {
+        let rhs = self
+            .visit_expr(backtrace, node.rhs().unwrap())?
+            .resolve(backtrace)?;
+
+        let less = if swap {
+            rhs.borrow().less_than(&lhs.borrow(), backtrace)?
+        } else {
+            lhs.borrow().less_than(&rhs.borrow(), backtrace)?
+        };
+
+        let less = less.map_err(|message| {
+            self.operands_error(backtrace, node, &lhs.borrow(), &rhs.borrow(), message)
+        })?;
+
+        Ok(NixValue::Bool(less != negate).wrap_var())
+    }
+
     pub fn visit_error(
         self: &Rc<Self>,
         _backtrace: &NixBacktrace,
@@ -491,18 +769,33 @@ impl Scope {
         ))
     }
 
+    /// `e ? a.b`: only the sets along the path are forced, never the last
+    /// value, and a step that isn't a set is `false` instead of an error
     pub fn visit_hasattr(
         self: &Rc<Self>,
         backtrace: &NixBacktrace,
         node: ast::HasAttr,
     ) -> NixResult<NixVar> {
-        let value = self.visit_expr(backtrace, node.expr().unwrap())?;
+        let mut value = self.visit_expr(backtrace, node.expr().unwrap())?;
+
+        // Not `resolve_attr_path`, there's no need to build the errors of the
+        // missing attributes just to throw them away
+        for attr_node in node.attrpath().unwrap().attrs() {
+            let attr = self.resolve_attr(backtrace, &attr_node)?;
 
-        let has_attr = self
-            .resolve_attr_path(backtrace, value, node.attrpath().unwrap().attrs())?
-            .is_ok();
+            let next = match &*value.resolve(backtrace)?.borrow() {
+                NixValue::AttrSet(set) => set.get(&attr).cloned(),
+                _ => None,
+            };
 
-        Ok(NixValue::Bool(has_attr).wrap_var())
+            let Some(next) = next else {
+                return Ok(NixValue::Bool(false).wrap_var());
+            };
+
+            value = next;
+        }
+
+        Ok(NixValue::Bool(true).wrap_var())
     }
 
     pub fn visit_ident(
@@ -510,8 +803,8 @@ impl Scope {
         _backtrace: &NixBacktrace,
         node: ast::Ident,
     ) -> NixResult<NixVar> {
-        let ident = node.ident_token().unwrap();
-        let varname = ident.text().to_string();
+        // Not `ident_token`, `f or` applies `f` to a variable named `or`
+        let varname = node.syntax().text().to_string();
 
         self.get_variable(varname.clone()).ok_or_else(|| {
             NixError::from_message(
@@ -520,7 +813,11 @@ impl Scope {
                     NixLabelMessage::VariableNotFound,
                     NixLabelKind::Error,
                 ),
-                format!("Variable '\x1b[1;95m{varname}\x1b[0m' not found"),
+                if self.in_with() {
+                    format!("Variable '\x1b[1;95m{varname}\x1b[0m' not found, neither in the sets of the enclosing `with`")
+                } else {
+                    format!("Variable '\x1b[1;95m{varname}\x1b[0m' not found")
+                },
             )
         })
     }
@@ -530,15 +827,12 @@ impl Scope {
         backtrace: &NixBacktrace,
         node: ast::IfElse,
     ) -> NixResult<NixVar> {
+        let condition_node = node.condition().unwrap();
         let condition = self
-            .visit_expr(backtrace, node.condition().unwrap())?
+            .visit_expr(backtrace, condition_node.clone())?
             .resolve(backtrace)?;
 
-        let Some(condition) = condition.borrow().as_bool() else {
-            todo!("Error handling")
-        };
-
-        if condition {
+        if self.expect_bool(backtrace, &condition_node, &condition.borrow())? {
             self.visit_expr(backtrace, node.body().unwrap())
         } else {
             self.visit_expr(backtrace, node.else_body().unwrap())
@@ -547,11 +841,15 @@ impl Scope {
 
     pub fn visit_lambda(
         self: &Rc<Self>,
-        _backtrace: &NixBacktrace,
+        backtrace: &NixBacktrace,
         node: ast::Lambda,
     ) -> NixResult<NixVar> {
         let param = match node.param().unwrap() {
-            ast::Param::Pattern(pattern) => NixLambdaParam::Pattern(pattern),
+            ast::Param::Pattern(pattern) => {
+                self.check_pattern(backtrace, &pattern)?;
+
+                NixLambdaParam::Pattern(pattern)
+            }
             ast::Param::IdentParam(ident) => NixLambdaParam::Ident(
                 ident
                     .ident()
@@ -569,8 +867,75 @@ impl Scope {
         )
     }
 
-    pub fn visit_legacylet(self: &Rc<Self>, _backtrace: &NixBacktrace, _node: ast::LegacyLet) -> ! {
-        unimplemented!("This is legacy")
+    /// Names of a pattern and its `@` binding are defined once, like Nix
+    /// checks while parsing
+    fn check_pattern(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        pattern: &ast::Pattern,
+    ) -> NixResult<()> {
+        let mut names: Vec<ast::Ident> = pattern
+            .pat_entries()
+            .filter_map(|entry| entry.ident())
+            .chain(pattern.pat_bind().and_then(|bind| bind.ident()))
+            .collect();
+
+        // `args@{ ... }` binds before the entries, `{ ... }@args` after them
+        names.sort_by_key(|ident| ident.syntax().text_range().start());
+
+        for (idx, ident) in names.iter().enumerate() {
+            let name = ident.syntax().text().to_string();
+
+            let Some(first) = names[..idx]
+                .iter()
+                .find(|previous| previous.syntax().text() == name.as_str())
+            else {
+                continue;
+            };
+
+            return Err(backtrace.to_labeled_error(
+                vec![
+                    NixLabel::new(
+                        NixSpan::from_ast_node(&self.file, ident).into(),
+                        NixLabelMessage::Custom("Duplicated argument".to_owned()),
+                        NixLabelKind::Error,
+                    ),
+                    NixLabel::new(
+                        NixSpan::from_ast_node(&self.file, first).into(),
+                        NixLabelMessage::Custom("First defined here".to_owned()),
+                        NixLabelKind::Help,
+                    ),
+                ],
+                format!("Duplicated function argument '\x1b[1;95m{name}\x1b[0m'"),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// `let { body = ...; }`, a `rec { }` that evaluates to its `body`
+    pub fn visit_legacylet(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: ast::LegacyLet,
+    ) -> NixResult<NixVar> {
+        let scope = self.visit_rec_entries(backtrace, node.entries())?;
+
+        let body = scope
+            .variables
+            .borrow()
+            .get(backtrace, &"body".to_owned())?;
+
+        body.ok_or_else(|| {
+            backtrace.to_labeled_error(
+                vec![NixLabel::new(
+                    NixSpan::from_ast_node(&self.file, &node).into(),
+                    NixLabelMessage::AttributeMissing,
+                    NixLabelKind::Error,
+                )],
+                "attribute 'body' missing, `let { }` evaluates to it",
+            )
+        })
     }
 
     pub fn visit_letin(
@@ -578,19 +943,34 @@ impl Scope {
         backtrace: &NixBacktrace,
         node: ast::LetIn,
     ) -> NixResult<NixVar> {
+        // Bindings get their own scope, `self.variables` may be a `with` namespace
+        let scope = self.clone().new_child();
+
         for entry in node.entries() {
-            self.insert_entry_to_attrset(
+            if let ast::Entry::AttrpathValue(entry) = &entry {
+                if let Some(ast::Attr::Dynamic(dynamic)) = entry.attrpath().unwrap().attrs().next()
+                {
+                    return Err(backtrace.to_labeled_error(
+                        vec![NixLabel::new(
+                            NixSpan::from_ast_node(&self.file, &dynamic).into(),
+                            NixLabelMessage::Empty,
+                            NixLabelKind::Error,
+                        )],
+                        "dynamic attributes not allowed in let",
+                    ));
+                }
+            }
+
+            scope.insert_entry_to_attrset(
                 &self.new_backtrace(backtrace, &entry),
-                self.variables.clone(),
+                scope.variables.clone(),
                 entry,
             )?;
         }
 
         let body = node.body().unwrap();
 
-        self.clone()
-            .new_child()
-            .visit_expr(&self.new_backtrace(backtrace, &body), body)
+        scope.visit_expr(&self.new_backtrace(backtrace, &body), body)
     }
 
     pub fn visit_list(
@@ -621,11 +1001,18 @@ impl Scope {
             ast::LiteralKind::Integer(value) => {
                 Ok(NixValue::Int(value.value().unwrap()).wrap_var())
             }
-            ast::LiteralKind::Uri(_) => Err(NixError::todo(
-                NixSpan::from_ast_node(&self.file, &node).into(),
-                "Uri literal",
-                None,
-            )),
+            // Plain strings, like Nix
+            ast::LiteralKind::Uri(uri) => {
+                if !self.file.warned_uri.replace(true) {
+                    log::warn(&format!(
+                        "URL literals are deprecated, quote '{}' at {}",
+                        uri.syntax().text(),
+                        NixSpan::from_ast_node(&self.file, &node).location()
+                    ));
+                }
+
+                Ok(NixValue::String(uri.syntax().text().into()).wrap_var())
+            }
         }
     }
 
@@ -642,6 +1029,15 @@ impl Scope {
         backtrace: &NixBacktrace,
         node: ast::Path,
     ) -> NixResult<NixVar> {
+        let text = node.syntax().text().to_string();
+
+        if let Some(lookup) = text
+            .strip_prefix('<')
+            .and_then(|text| text.strip_suffix('>'))
+        {
+            return self.visit_search_path(backtrace, &node, lookup);
+        }
+
         let mut path = String::new();
 
         for (idx, part) in node.parts().enumerate() {
@@ -649,53 +1045,125 @@ impl Scope {
                 ast::InterpolPart::Literal(str) => {
                     let str = str.syntax().text();
 
-                    if idx == 0 {
-                        if &str[0..1] == "/" {
-                            path += str;
-                        } else {
-                            let dirname = self.file.path.parent().expect("Cannot get parent");
-
-                            if str.get(1..2) == Some(".") {
-                                let Some(parent) = dirname.parent() else {
-                                    return Err(NixError::todo(
-                                        NixSpan::from_ast_node(&self.file, &node).into(),
-                                        "Error handling: path doesn't have parent",
-                                        None,
-                                    ));
-                                };
-                                path += &parent.display().to_string();
-                                path += &str[2..];
-                            } else {
-                                path += &dirname.display().to_string();
-                                path += &str[1..];
-                            }
-                        }
+                    if idx > 0 || str.starts_with('/') {
+                        path += str;
+                    } else if let Some(rest) = str.strip_prefix('~') {
+                        path += &self.home_dir(backtrace, &node, &text)?;
+                        path += rest;
                     } else {
-                        if path.chars().rev().next() != Some('/') {
-                            path += "/";
-                        }
+                        // Relative to the directory of the file, `..` are
+                        // resolved below with the rest
+                        let Some(dirname) = self.file.path.parent() else {
+                            return Err(backtrace.to_labeled_error(
+                                vec![NixLabel::new(
+                                    NixSpan::from_ast_node(&self.file, &node).into(),
+                                    NixLabelMessage::Empty,
+                                    NixLabelKind::Error,
+                                )],
+                                format!(
+                                    "cannot resolve '{text}', '{}' has no parent directory",
+                                    self.file.path.display()
+                                ),
+                            ));
+                        };
 
+                        path += &dirname.display().to_string();
+                        path += "/";
                         path += str;
                     }
                 }
                 ast::InterpolPart::Interpolation(interpol) => {
-                    let str = self
-                        .visit_expr(backtrace, interpol.expr().unwrap())?
-                        .resolve(backtrace)?
-                        .borrow()
-                        .cast_to_string()
-                        .unwrap();
-
-                    if idx == 1 && path.get(0..1) == Some("/") && str.get(0..1) == Some("/") {
-                        path.pop();
+                    let expr = interpol.expr().unwrap();
+                    let value = self
+                        .visit_expr(backtrace, expr.clone())?
+                        .resolve(backtrace)?;
+                    let str = self.coerce_to_string(backtrace, &expr, &value.borrow(), false)?;
+
+                    if str.has_context() {
+                        return Err(backtrace.to_labeled_error(
+                            vec![NixLabel::new(
+                                NixSpan::from_ast_node(&self.file, &expr).into(),
+                                NixLabelMessage::Empty,
+                                NixLabelKind::Error,
+                            )],
+                            "a string that refers to a store path cannot be appended to a path",
+                        ));
                     }
 
-                    path += &str;
+                    path += str.as_str();
                 }
             }
         }
 
-        Ok(NixValue::Path(path.try_into().expect("TODO: Error handling")).wrap_var())
+        Ok(NixValue::Path(normalize_path(&path)).wrap_var())
+    }
+
+    /// `$HOME` of `~/file`, which depends on the environment
+    fn home_dir(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::Path,
+        text: &str,
+    ) -> NixResult<String> {
+        let label = || {
+            vec![NixLabel::new(
+                NixSpan::from_ast_node(&self.file, node).into(),
+                NixLabelMessage::Empty,
+                NixLabelKind::Error,
+            )]
+        };
+
+        if impurities::options().pure {
+            return Err(backtrace.to_labeled_error(
+                label(),
+                format!("the path '{text}' can not be resolved in pure mode"),
+            ));
+        }
+
+        impurities::record(backtrace, impurities::Impurity::EnvVar("HOME".to_owned()));
+
+        std::env::var("HOME").map_err(|_| {
+            backtrace.to_labeled_error(
+                label(),
+                format!("the path '{text}' can not be resolved, $HOME is not set"),
+            )
+        })
+    }
+
+    /// `<nixpkgs/lib>`
+    fn visit_search_path(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::Path,
+        lookup: &str,
+    ) -> NixResult<NixVar> {
+        if impurities::options().pure {
+            return Err(backtrace.to_labeled_error(
+                vec![NixLabel::new(
+                    NixSpan::from_ast_node(&self.file, node).into(),
+                    NixLabelMessage::Empty,
+                    NixLabelKind::Error,
+                )],
+                format!("cannot look up '<{lookup}>' in pure evaluation mode"),
+            ));
+        }
+
+        let Some(path) = search_path::find_file(lookup) else {
+            return Err(backtrace.to_labeled_error(
+                vec![NixLabel::new(
+                    NixSpan::from_ast_node(&self.file, node).into(),
+                    NixLabelMessage::Custom("Not found in the search path".to_owned()),
+                    NixLabelKind::Error,
+                )],
+                format!(
+                    "file '\x1b[1;95m{lookup}\x1b[0m' was not found in the Nix search path (add it using $NIX_PATH or -I)"
+                ),
+            ));
+        };
+
+        impurities::record_path(backtrace, &path);
+
+        Ok(NixValue::Path(path).wrap_var())
     }
 
     pub fn visit_root(
@@ -727,20 +1195,27 @@ impl Scope {
         backtrace: &NixBacktrace,
         node: ast::Str,
     ) -> NixResult<NixVar> {
-        let mut content = String::new();
+        let mut content = NixString::default();
 
-        for part in node.parts() {
+        // Literals come unescaped and without the indentation of `''` strings
+        for part in node.normalized_parts() {
             match part {
                 ast::InterpolPart::Literal(str) => {
-                    content += str.syntax().text();
+                    content.push(&str.into());
                 }
                 ast::InterpolPart::Interpolation(interpol) => {
-                    content += &self
-                        .visit_expr(backtrace, interpol.expr().unwrap())?
-                        .resolve(backtrace)?
-                        .borrow()
-                        .cast_to_string()
-                        .unwrap();
+                    let expr = interpol.expr().unwrap();
+                    let value = self
+                        .visit_expr(backtrace, expr.clone())?
+                        .resolve(backtrace)?;
+
+                    // Interpolated strings bring their context along
+                    content.push(&self.coerce_to_string(
+                        backtrace,
+                        &expr,
+                        &value.borrow(),
+                        true,
+                    )?);
                 }
             }
         }
@@ -748,6 +1223,38 @@ impl Scope {
         Ok(NixValue::String(content).wrap_var())
     }
 
+    /// Coerce an interpolated value or the rhs of `"str" + value`. Only strings,
+    /// paths and sets with `__toString` or `outPath` are allowed, and paths are
+    /// copied into the store unlike other coercions:
+    ///
+    /// | Expression        | Path becomes          |
+    /// |-------------------|-----------------------|
+    /// | `"${./file}"`     | its store path        |
+    /// | `"str" + ./file`  | its store path        |
+    /// | `./dir/${./file}` | the literal path      |
+    /// | `./dir + "/file"` | a path                |
+    /// | `toString ./file` | the literal path      |
+    fn coerce_to_string(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        node: &ast::Expr,
+        value: &NixValue,
+        copy_to_store: bool,
+    ) -> NixResult<NixString> {
+        value
+            .coerce_to_string(backtrace, false, copy_to_store)
+            .map_err(|err| {
+                backtrace.to_labeled_error(
+                    vec![NixLabel::new(
+                        NixSpan::from_ast_node(&self.file, node).into(),
+                        NixLabelMessage::Empty,
+                        NixLabelKind::Error,
+                    )],
+                    err.message,
+                )
+            })
+    }
+
     pub fn visit_unaryop(
         self: &Rc<Self>,
         backtrace: &NixBacktrace,
@@ -760,21 +1267,39 @@ impl Scope {
 
         match node.operator().unwrap() {
             ast::UnaryOpKind::Invert => {
-                let Some(value) = value.as_bool() else {
-                    todo!("Error handling");
-                };
+                let value = self.expect_bool(backtrace, &node.expr().unwrap(), &value)?;
 
                 Ok(NixValue::Bool(!value).wrap_var())
             }
-            ast::UnaryOpKind::Negate => match value.deref() {
-                NixValue::Int(v) => Ok(NixValue::Int(-v).wrap_var()),
-                NixValue::Float(v) => Ok(NixValue::Float(-v).wrap_var()),
-                _ => Err(NixError::todo(
-                    NixSpan::from_ast_node(&self.file, &node).into(),
-                    "Error Handling: should be a number",
-                    None,
-                )),
-            },
+            // `-x` is `0 - x` like in Nix, so `-i64::MIN` overflows
+            ast::UnaryOpKind::Negate => {
+                if NixNumber::from_value(&value).is_none() {
+                    return Err(backtrace.to_labeled_error(
+                        vec![NixLabel::new(
+                            NixSpan::from_ast_node(&self.file, &node.expr().unwrap()).into(),
+                            NixLabelMessage::Custom(format!(
+                                "this is {}",
+                                value.as_type_with_article()
+                            )),
+                            NixLabelKind::Error,
+                        )],
+                        format!("cannot negate {}", value.as_type_with_article()),
+                    ));
+                }
+
+                NixValue::arith(NixArithOp::Sub, &NixValue::Int(0), &value)
+                    .map(NixValue::wrap_var)
+                    .map_err(|message| {
+                        backtrace.to_labeled_error(
+                            vec![NixLabel::new(
+                                NixSpan::from_ast_node(&self.file, &node).into(),
+                                NixLabelMessage::Empty,
+                                NixLabelKind::Error,
+                            )],
+                            message,
+                        )
+                    })
+            }
         }
     }
 
@@ -788,11 +1313,41 @@ impl Scope {
             .resolve(backtrace)?;
 
         if !namespace.borrow().is_attr_set() {
-            todo!("Error handling")
+            let namespace_node = node.namespace().unwrap();
+
+            return Err(backtrace.to_labeled_error(
+                vec![NixLabel::new(
+                    NixSpan::from_ast_node(&self.file, &namespace_node).into(),
+                    NixLabelMessage::Empty,
+                    NixLabelKind::Error,
+                )],
+                format!(
+                    "expected a set as the namespace of with but found {}",
+                    namespace.borrow().as_type()
+                ),
+            ));
         }
 
-        let scope = self.clone().new_child_from(namespace);
+        let scope = self.clone().new_with(namespace);
 
         scope.visit_expr(backtrace, node.body().unwrap())
     }
 }
+
+/// Resolve `.`, `..` and repeated slashes without touching the filesystem,
+/// like the paths Nix stores. `..` at the root stays at the root
+fn normalize_path(path: &str) -> PathBuf {
+    let mut out = PathBuf::new();
+
+    for component in Path::new(path).components() {
+        match component {
+            Component::ParentDir => {
+                out.pop();
+            }
+            Component::CurDir => {}
+            component => out.push(component),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,484 @@
+//! Backends of `builtins.fetchTree`, each input type returns a source info set
+//!
+//! https://nix.dev/manual/nix/latest/language/builtins#builtins-fetchTree
+
+mod git;
+mod http;
+
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use std::{env, fs, io};
+
+use flate2::read::GzDecoder;
+use openssl::base64;
+use xz2::read::XzDecoder;
+
+use crate::builtins::hash::{self, Algorithm};
+use crate::impurities::{self, Impurity};
+use crate::store::{self, nar, ContentAddressMethod};
+use crate::{NixAttrSet, NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue};
+
+pub use git::fetch_git;
+
+const SUPPORTED_TYPES: &[&str] = &["git", "github", "path", "tarball"];
+
+/// `sha256-<base64>`, the SRI form used by `narHash`
+pub fn to_sri(digest: &[u8]) -> String {
+    format!("sha256-{}", base64::encode_block(digest))
+}
+
+/// Newest modification time in the tree, in seconds since the epoch
+fn newest_mtime(path: &Path) -> io::Result<u64> {
+    let metadata = fs::symlink_metadata(path)?;
+
+    let mut newest = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path)? {
+            newest = newest.max(newest_mtime(&entry?.path())?);
+        }
+    }
+
+    Ok(newest)
+}
+
+/// `%Y%m%d%H%M%S` in UTC, the format of `lastModifiedDate`
+fn format_date(timestamp: u64) -> String {
+    let days = (timestamp / 86400) as i64;
+    let seconds = timestamp % 86400;
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}{month:02}{day:02}{:02}{:02}{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+fn get_string(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    input: &NixAttrSet,
+    attr: &str,
+) -> NixResult<Option<String>> {
+    let Some(value) = input.get(attr) else {
+        return Ok(None);
+    };
+
+    let value = value.resolve(backtrace)?;
+    let value = value.borrow();
+
+    let string = match &*value {
+        NixValue::String(string) => Some(string.to_string()),
+        NixValue::Path(path) => Some(path.display().to_string()),
+        _ => None,
+    };
+
+    string.map(Some).ok_or_else(|| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "{builtin}: expected '{attr}' to be a string, got {}",
+                value.as_type()
+            ),
+        )
+    })
+}
+
+/// Fetch the input described by `input` (`{ type = ...; ... }`)
+pub fn fetch_tree(backtrace: &NixBacktrace, input: &NixAttrSet) -> NixResult<NixAttrSet> {
+    let Some(ty) = get_string(backtrace, "fetchTree", input, "type")? else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            "fetchTree: the input is missing the 'type' attribute",
+        ));
+    };
+
+    match ty.as_str() {
+        "git" => fetch_git(backtrace, input),
+        "path" => fetch_path(backtrace, input),
+        "github" | "tarball" => Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("fetchTree: '{ty}' inputs are not supported yet"),
+        )),
+        _ => Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "fetchTree: unsupported input type '{ty}', expected one of: {}",
+                SUPPORTED_TYPES.join(", ")
+            ),
+        )),
+    }
+}
+
+/// Local paths aren't copied into the store, `outPath` is the path itself
+fn fetch_path(backtrace: &NixBacktrace, input: &NixAttrSet) -> NixResult<NixAttrSet> {
+    let Some(path) = get_string(backtrace, "fetchTree", input, "path")? else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            "fetchTree: 'path' inputs require the 'path' attribute",
+        ));
+    };
+
+    let path = PathBuf::from(path);
+
+    if !path.is_absolute() {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("fetchTree: path '{}' is not absolute", path.display()),
+        ));
+    }
+
+    impurities::record_path(backtrace, &path);
+
+    let io_error = |err: io::Error| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("fetchTree: cannot read '{}': {err}", path.display()),
+        )
+    };
+
+    let nar_hash = to_sri(&nar::hash_path(&path).map_err(io_error)?);
+
+    if let Some(expected) = get_string(backtrace, "fetchTree", input, "narHash")? {
+        if expected != nar_hash {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!(
+                    "NAR hash mismatch in input '{}', expected '{expected}' but got '{nar_hash}'",
+                    path.display()
+                ),
+            ));
+        }
+    }
+
+    let last_modified = match input.get("lastModified") {
+        Some(value) => value
+            .resolve(backtrace)?
+            .borrow()
+            .as_int()
+            .map(|int| int as u64)
+            .ok_or_else(|| {
+                backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    "fetchTree: expected 'lastModified' to be an integer",
+                )
+            })?,
+        None => newest_mtime(&path).map_err(io_error)?,
+    };
+
+    let mut out = NixAttrSet::new();
+
+    out.insert(
+        "lastModified".to_owned(),
+        NixValue::Int(last_modified as i64).wrap_var(),
+    );
+    out.insert(
+        "lastModifiedDate".to_owned(),
+        NixValue::String(format_date(last_modified).into()).wrap_var(),
+    );
+    out.insert(
+        "narHash".to_owned(),
+        NixValue::String(nar_hash.into()).wrap_var(),
+    );
+    out.insert("outPath".to_owned(), NixValue::Path(path).wrap_var());
+
+    Ok(out)
+}
+
+/// `$XDG_CACHE_HOME/nix-compiler/<kind>`, where fetched content is kept
+pub fn cache_dir(kind: &str) -> Option<PathBuf> {
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+
+    Some(cache.join("nix-compiler").join(kind))
+}
+
+/// Content of `url`, `file://` URLs are read directly
+fn download(url: &str) -> Result<Vec<u8>, String> {
+    match url.strip_prefix("file://") {
+        Some(path) => fs::read(path).map_err(|err| format!("cannot read '{path}': {err}")),
+        None => http::get(url),
+    }
+}
+
+/// `url`, `name` and `sha256` of `{ url; name ? ...; sha256 ? null; }`, the
+/// input of `fetchurl` and `fetchTarball`
+pub fn url_input(
+    backtrace: &NixBacktrace,
+    builtin: &str,
+    input: &NixAttrSet,
+) -> NixResult<(String, Option<String>, Option<String>)> {
+    if let Some(key) = input
+        .keys()
+        .find(|key| !["url", "name", "sha256"].contains(&key.as_str()))
+    {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("{builtin}: unsupported argument '{key}'"),
+        ));
+    }
+
+    let Some(url) = get_string(backtrace, builtin, input, "url")? else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("{builtin}: the 'url' attribute is required"),
+        ));
+    };
+
+    let name = get_string(backtrace, builtin, input, "name")?;
+    let sha256 = get_string(backtrace, builtin, input, "sha256")?;
+
+    Ok((url, name, sha256))
+}
+
+/// `builtins.fetchurl`, the result is a flat fixed-output store path. With
+/// `sha256` a cached download with that hash is used instead of fetching again
+pub fn fetch_url(
+    backtrace: &NixBacktrace,
+    url: &str,
+    name: Option<String>,
+    sha256: Option<String>,
+) -> NixResult<String> {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("fetchurl: {message}"),
+        )
+    };
+
+    let name = name.unwrap_or_else(|| {
+        url.trim_end_matches('/')
+            .rsplit('/')
+            .next()
+            .unwrap_or_default()
+            // Hidden files still make a valid name
+            .trim_start_matches('.')
+            .to_owned()
+    });
+
+    if let Err(err) = store::check_name(&name) {
+        return Err(error(format!("{err}, give one with `name`")));
+    }
+
+    let expected = match sha256 {
+        Some(sha256) => Some(
+            store::parse_sha256(&sha256)
+                .ok_or_else(|| error(format!("invalid SHA-256 hash '{sha256}'")))?,
+        ),
+        None => {
+            impurities::record(backtrace, Impurity::Download(url.to_owned()));
+            None
+        }
+    };
+
+    // Named by the nix32 SHA-256 of their content
+    let cache = cache_dir("fetchurl");
+    let cached = |digest: &[u8]| {
+        cache
+            .as_ref()
+            .map(|cache| cache.join(store::to_base32(digest)))
+    };
+
+    let path = match expected
+        .as_deref()
+        .and_then(cached)
+        .filter(|path| path.is_file())
+    {
+        Some(path) => path,
+        None => {
+            let content =
+                download(url).map_err(|err| error(format!("cannot download '{url}': {err}")))?;
+            let digest = hash::digest(Algorithm::SHA256, &content);
+
+            if let Some(expected) = &expected {
+                if *expected != digest {
+                    return Err(error(format!(
+                        "hash mismatch in file downloaded from '{url}', specified: {}, got: {}",
+                        to_sri(expected),
+                        to_sri(&digest)
+                    )));
+                }
+            }
+
+            let path = cached(&digest).ok_or_else(|| {
+                error(String::from(
+                    "no cache directory, neither $XDG_CACHE_HOME nor $HOME are set",
+                ))
+            })?;
+
+            let io_error =
+                |err: io::Error| error(format!("cannot write '{}': {err}", path.display()));
+
+            fs::create_dir_all(path.parent().unwrap()).map_err(io_error)?;
+            fs::write(&path, &content).map_err(io_error)?;
+
+            path
+        }
+    };
+
+    let digest = match expected {
+        Some(expected) => expected,
+        None => hash::digest(
+            Algorithm::SHA256,
+            &fs::read(&path)
+                .map_err(|err| error(format!("cannot read '{}': {err}", path.display())))?,
+        ),
+    };
+
+    let store_path = store::make_fixed_output_path(
+        ContentAddressMethod::Flat,
+        Algorithm::SHA256,
+        &digest,
+        &name,
+    );
+
+    store::register_origin(&store_path, &path);
+
+    Ok(store_path)
+}
+
+/// Unpack a tarball, compressed with gzip, xz or not at all, into `dest`
+fn unpack_tarball(content: &[u8], dest: &Path) -> io::Result<()> {
+    let reader: Box<dyn Read + '_> = match content {
+        [0x1f, 0x8b, ..] => Box::new(GzDecoder::new(content)),
+        [0xfd, b'7', b'z', b'X', b'Z', 0, ..] => Box::new(XzDecoder::new(content)),
+        _ => Box::new(content),
+    };
+
+    let mut archive = tar::Archive::new(reader);
+    archive.set_preserve_permissions(true);
+    archive.unpack(dest)
+}
+
+/// `builtins.fetchTarball`, the unpacked tree without its top-level directory
+/// as a `source` store path. With `sha256`, the NAR hash of the tree, it's
+/// kept unpacked and isn't fetched again
+pub fn fetch_tarball(
+    backtrace: &NixBacktrace,
+    url: &str,
+    name: Option<String>,
+    sha256: Option<String>,
+) -> NixResult<String> {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("fetchTarball: {message}"),
+        )
+    };
+
+    let name = name.unwrap_or_else(|| String::from("source"));
+
+    if let Err(err) = store::check_name(&name) {
+        return Err(error(err));
+    }
+
+    let expected = match &sha256 {
+        Some(sha256) => Some(
+            store::parse_sha256(sha256)
+                .ok_or_else(|| error(format!("invalid SHA-256 hash '{sha256}'")))?,
+        ),
+        None => {
+            impurities::record(backtrace, Impurity::Download(url.to_owned()));
+            None
+        }
+    };
+
+    let cache = cache_dir("tarballs").ok_or_else(|| {
+        error(String::from(
+            "no cache directory, neither $XDG_CACHE_HOME nor $HOME are set",
+        ))
+    })?;
+
+    // Keyed by the URL and the hash, trees without one are always fetched again
+    let key = hash::hex_digest(
+        Algorithm::SHA256,
+        format!("{url}\0{}", sha256.as_deref().unwrap_or_default()).as_bytes(),
+    );
+    let unpacked = cache.join(&key);
+    let root = unpacked.join("root");
+
+    if expected.is_none() || !fs::exists(&root).unwrap_or(false) {
+        let content =
+            download(url).map_err(|err| error(format!("cannot download '{url}': {err}")))?;
+
+        let io_error = |err: io::Error| error(format!("cannot unpack '{url}': {err}"));
+
+        let tmp = cache.join(format!("{key}.tmp"));
+
+        if fs::exists(&tmp).unwrap_or(false) {
+            fs::remove_dir_all(&tmp).map_err(io_error)?;
+        }
+
+        fs::create_dir_all(&tmp).map_err(io_error)?;
+        unpack_tarball(&content, &tmp).map_err(io_error)?;
+
+        let entries = fs::read_dir(&tmp)
+            .map_err(io_error)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()
+            .map_err(io_error)?;
+
+        let [top] = entries.as_slice() else {
+            return Err(error(format!(
+                "tarball '{url}' must contain a single file or directory, but it has {} top-level entries",
+                entries.len()
+            )));
+        };
+
+        if fs::exists(&unpacked).unwrap_or(false) {
+            fs::remove_dir_all(&unpacked).map_err(io_error)?;
+        }
+
+        fs::create_dir_all(&unpacked).map_err(io_error)?;
+        fs::rename(top, &root).map_err(io_error)?;
+        fs::remove_dir_all(&tmp).map_err(io_error)?;
+    }
+
+    let nar_hash = nar::hash_path(&root)
+        .map_err(|err| error(format!("cannot read '{}': {err}", root.display())))?;
+
+    if let Some(expected) = expected {
+        if expected != nar_hash {
+            // Don't keep a tree that doesn't match its key
+            let _ = fs::remove_dir_all(&unpacked);
+
+            return Err(error(format!(
+                "hash mismatch in tarball downloaded from '{url}', specified: {}, got: {}",
+                to_sri(&expected),
+                to_sri(&nar_hash)
+            )));
+        }
+    }
+
+    Ok(store::add_source(&root, &name, &nar_hash))
+}
@@ -0,0 +1,378 @@
+//! `builtins.fetchGit` and `fetchTree { type = "git"; }`, on top of the `git`
+//! binary
+//!
+//! Every URL gets a bare mirror in the cache, each fetched revision is then
+//! checked out once into its own directory without the `.git`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use super::{cache_dir, format_date, get_string, to_sri};
+use crate::builtins::hash::{self, Algorithm};
+use crate::impurities::{self, Impurity};
+use crate::log;
+use crate::store::{self, nar};
+use crate::value::{NixContextElem, NixString, NixStringContext};
+use crate::{NixAttrSet, NixBacktrace, NixLabelKind, NixLabelMessage, NixResult, NixValue};
+
+const SUPPORTED_ATTRS: &[&str] = &["name", "ref", "rev", "shallow", "submodules", "type", "url"];
+
+/// `rev` of a dirty working tree
+const DIRTY_REV: &str = "0000000000000000000000000000000000000000";
+
+struct GitInput {
+    url: String,
+    name: String,
+    git_ref: Option<String>,
+    rev: Option<String>,
+    shallow: bool,
+    submodules: bool,
+}
+
+struct Git<'a> {
+    backtrace: &'a NixBacktrace,
+}
+
+impl Git<'_> {
+    fn error(&self, message: String) -> crate::NixError {
+        self.backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("fetchGit: {message}"),
+        )
+    }
+
+    /// Run git in `dir`, failures show its stderr
+    fn run(&self, dir: &Path, args: &[&str]) -> NixResult<String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .map_err(|err| self.error(format!("cannot run git: {err}")))?;
+
+        if !output.status.success() {
+            return Err(self.error(format!(
+                "`git {}` failed:\n{}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .trim_end()
+            .to_owned())
+    }
+
+    fn succeeds(&self, dir: &Path, args: &[&str]) -> bool {
+        Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+}
+
+fn get_bool(backtrace: &NixBacktrace, input: &NixAttrSet, attr: &str) -> NixResult<bool> {
+    let Some(value) = input.get(attr) else {
+        return Ok(false);
+    };
+
+    let value = value.resolve(backtrace)?;
+    let value = value.borrow();
+
+    value.as_bool().ok_or_else(|| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "fetchGit: expected '{attr}' to be a bool, got {}",
+                value.as_type()
+            ),
+        )
+    })
+}
+
+impl GitInput {
+    fn from_attr_set(backtrace: &NixBacktrace, input: &NixAttrSet) -> NixResult<Self> {
+        if let Some(key) = input
+            .keys()
+            .find(|key| !SUPPORTED_ATTRS.contains(&key.as_str()))
+        {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                format!("fetchGit: unsupported argument '{key}'"),
+            ));
+        }
+
+        let Some(url) = get_string(backtrace, "fetchGit", input, "url")? else {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                "fetchGit: the 'url' attribute is required",
+            ));
+        };
+
+        Ok(GitInput {
+            url,
+            name: get_string(backtrace, "fetchGit", input, "name")?
+                .unwrap_or_else(|| String::from("source")),
+            git_ref: get_string(backtrace, "fetchGit", input, "ref")?,
+            rev: get_string(backtrace, "fetchGit", input, "rev")?,
+            shallow: get_bool(backtrace, input, "shallow")?,
+            submodules: get_bool(backtrace, input, "submodules")?,
+        })
+    }
+
+    /// The repository on disk if `url` is a local path
+    fn local_path(&self) -> Option<PathBuf> {
+        let path = self.url.strip_prefix("file://").unwrap_or(&self.url);
+
+        path.starts_with('/').then(|| PathBuf::from(path))
+    }
+}
+
+/// Copy the tracked files of the working tree at `repo`, as they are on disk
+fn copy_worktree(git: &Git, repo: &Path, dest: &Path) -> NixResult<()> {
+    let io_error =
+        |err: std::io::Error| git.error(format!("cannot copy '{}': {err}", repo.display()));
+
+    if fs::exists(dest).unwrap_or(false) {
+        fs::remove_dir_all(dest).map_err(io_error)?;
+    }
+
+    fs::create_dir_all(dest).map_err(io_error)?;
+
+    for file in git.run(repo, &["ls-files", "-z"])?.split('\0') {
+        let from = repo.join(file);
+
+        // Deleted but not staged yet
+        let Ok(metadata) = fs::symlink_metadata(&from) else {
+            continue;
+        };
+
+        let to = dest.join(file);
+
+        if let Some(parent) = to.parent() {
+            fs::create_dir_all(parent).map_err(io_error)?;
+        }
+
+        if metadata.is_symlink() {
+            std::os::unix::fs::symlink(fs::read_link(&from).map_err(io_error)?, &to)
+                .map_err(io_error)?;
+        } else if metadata.is_file() {
+            fs::copy(&from, &to).map_err(io_error)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove the `.git` entries a checkout leaves, in submodules too
+fn remove_git_dirs(path: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if entry.file_name() == ".git" {
+            if file_type.is_dir() {
+                fs::remove_dir_all(entry.path())?;
+            } else {
+                fs::remove_file(entry.path())?;
+            }
+        } else if file_type.is_dir() {
+            remove_git_dirs(&entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch `input` into the mirror and check it out, returns the checkout and
+/// the revision
+fn checkout(git: &Git, cache: &Path, input: &GitInput) -> NixResult<(PathBuf, String)> {
+    let io_error = |err: std::io::Error| git.error(format!("cannot write the cache: {err}"));
+
+    let key = hash::hex_digest(Algorithm::SHA256, input.url.as_bytes());
+    let mirror = cache.join("mirrors").join(&key);
+
+    if !fs::exists(&mirror).unwrap_or(false) {
+        fs::create_dir_all(&mirror).map_err(io_error)?;
+        git.run(&mirror, &["init", "--quiet", "--bare"])?;
+    }
+
+    // A shallow mirror doesn't have the history `revCount` needs
+    let unshallow = !input.shallow && fs::exists(mirror.join("shallow")).unwrap_or(false);
+
+    let cached = !unshallow
+        && input.rev.as_ref().is_some_and(|rev| {
+            git.succeeds(&mirror, &["cat-file", "-e", &format!("{rev}^{{commit}}")])
+        });
+
+    if !cached {
+        let target = input
+            .rev
+            .as_deref()
+            .or(input.git_ref.as_deref())
+            .unwrap_or("HEAD");
+
+        let mut args = vec!["fetch", "--quiet", "--force"];
+
+        if input.shallow {
+            args.push("--depth=1");
+        } else if unshallow {
+            args.push("--unshallow");
+        }
+
+        args.extend([input.url.as_str(), target]);
+
+        git.run(&mirror, &args)?;
+    }
+
+    let rev = match &input.rev {
+        Some(rev) => git.run(&mirror, &["rev-parse", &format!("{rev}^{{commit}}")])?,
+        None => git.run(&mirror, &["rev-parse", "FETCH_HEAD^{commit}"])?,
+    };
+
+    let suffix = if input.submodules { "-submodules" } else { "" };
+    let dest = cache.join("checkouts").join(format!("{rev}{suffix}"));
+
+    if !fs::exists(&dest).unwrap_or(false) {
+        let tmp = cache.join("checkouts").join(format!("{rev}{suffix}.tmp"));
+
+        if fs::exists(&tmp).unwrap_or(false) {
+            fs::remove_dir_all(&tmp).map_err(io_error)?;
+        }
+
+        fs::create_dir_all(&tmp).map_err(io_error)?;
+
+        let mirror_str = mirror.display().to_string();
+
+        // Fetched revisions may not have a ref in the mirror, a clone would
+        // skip them
+        git.run(&tmp, &["init", "--quiet"])?;
+        git.run(&tmp, &["fetch", "--quiet", &mirror_str, &rev])?;
+        git.run(&tmp, &["checkout", "--quiet", &rev])?;
+
+        if input.submodules {
+            // Relative submodule URLs are relative to the original repository
+            git.run(&tmp, &["remote", "add", "origin", &input.url])?;
+            git.run(
+                &tmp,
+                &["submodule", "update", "--quiet", "--init", "--recursive"],
+            )?;
+        }
+
+        remove_git_dirs(&tmp).map_err(io_error)?;
+        fs::rename(&tmp, &dest).map_err(io_error)?;
+    }
+
+    Ok((dest, rev))
+}
+
+/// Fetch the `{ url; ref ? "HEAD"; rev ? null; ... }` input, the result is
+/// the source info set of `fetchGit`
+pub fn fetch_git(backtrace: &NixBacktrace, input: &NixAttrSet) -> NixResult<NixAttrSet> {
+    let input = GitInput::from_attr_set(backtrace, input)?;
+    let git = Git { backtrace };
+
+    if let Err(err) = store::check_name(&input.name) {
+        return Err(git.error(err));
+    }
+
+    let cache = cache_dir("git").ok_or_else(|| {
+        git.error(String::from(
+            "no cache directory, neither $XDG_CACHE_HOME nor $HOME are set",
+        ))
+    })?;
+
+    let local = input.local_path();
+
+    let dirty = match &local {
+        Some(repo)
+            if input.rev.is_none() && input.git_ref.is_none() && repo.join(".git").exists() =>
+        {
+            !git.run(repo, &["status", "--porcelain"])?.is_empty()
+        }
+        _ => false,
+    };
+
+    if input.rev.is_none() {
+        impurities::record(backtrace, Impurity::Download(input.url.clone()));
+    }
+
+    let (path, rev, rev_count, last_modified) = if let (true, Some(repo)) = (dirty, &local) {
+        log::warn(&format!("Git tree '{}' is dirty", repo.display()));
+
+        let key = hash::hex_digest(Algorithm::SHA256, input.url.as_bytes());
+        let dest = cache.join("dirty").join(key);
+
+        copy_worktree(&git, repo, &dest)?;
+
+        // The last commit, if there's one
+        let last_modified = git
+            .run(repo, &["log", "-1", "--format=%ct"])
+            .ok()
+            .and_then(|time| time.parse().ok())
+            .unwrap_or(0);
+
+        (dest, DIRTY_REV.to_owned(), Some(0), last_modified)
+    } else {
+        let (dest, rev) = checkout(&git, &cache, &input)?;
+        let mirror = cache
+            .join("mirrors")
+            .join(hash::hex_digest(Algorithm::SHA256, input.url.as_bytes()));
+
+        let rev_count = if input.shallow {
+            None
+        } else {
+            Some(
+                git.run(&mirror, &["rev-list", "--count", &rev])?
+                    .parse()
+                    .unwrap_or(0),
+            )
+        };
+
+        let last_modified = git
+            .run(&mirror, &["log", "-1", "--format=%ct", &rev])?
+            .parse()
+            .unwrap_or(0);
+
+        (dest, rev, rev_count, last_modified)
+    };
+
+    let nar_hash = nar::hash_path(&path)
+        .map_err(|err| git.error(format!("cannot read '{}': {err}", path.display())))?;
+
+    let store_path = store::add_source(&path, &input.name, &nar_hash);
+
+    let mut out = NixAttrSet::new();
+
+    let mut insert = |key: &str, value: NixValue| out.insert(key.to_owned(), value.wrap_var());
+
+    insert("lastModified", NixValue::Int(last_modified as i64));
+    insert(
+        "lastModifiedDate",
+        NixValue::String(format_date(last_modified).into()),
+    );
+    insert("narHash", NixValue::String(to_sri(&nar_hash).into()));
+    insert(
+        "outPath",
+        NixValue::String(NixString::new(
+            store_path.clone(),
+            NixStringContext::from([NixContextElem::Opaque(store_path)]),
+        )),
+    );
+    insert("shortRev", NixValue::String(rev[..7].into()));
+    insert("rev", NixValue::String(rev.into()));
+    insert("submodules", NixValue::Bool(input.submodules));
+
+    if let Some(rev_count) = rev_count {
+        insert("revCount", NixValue::Int(rev_count));
+    }
+
+    Ok(out)
+}
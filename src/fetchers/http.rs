@@ -0,0 +1,174 @@
+//! Minimal blocking HTTP/1.1 client for the fetchers, `GET` only
+//!
+//! Responses are read until the server closes the connection, redirects are
+//! followed and chunked bodies are decoded.
+
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+use openssl::ssl::{SslConnector, SslMethod};
+
+const MAX_REDIRECTS: usize = 10;
+
+struct Url<'a> {
+    tls: bool,
+    host: &'a str,
+    port: u16,
+    path: &'a str,
+}
+
+impl<'a> Url<'a> {
+    fn parse(url: &'a str) -> Result<Self, String> {
+        let (tls, rest) = if let Some(rest) = url.strip_prefix("https://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("http://") {
+            (false, rest)
+        } else {
+            return Err(format!("unsupported URL '{url}', expected http or https"));
+        };
+
+        let (authority, path) = rest.find('/').map_or((rest, "/"), |idx| rest.split_at(idx));
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse()
+                    .map_err(|_| format!("invalid port '{port}' in '{url}'"))?,
+            ),
+            None => (authority, if tls { 443 } else { 80 }),
+        };
+
+        Ok(Url {
+            tls,
+            host,
+            port,
+            path,
+        })
+    }
+}
+
+fn request(url: &Url) -> Result<Vec<u8>, String> {
+    let stream = TcpStream::connect((url.host, url.port))
+        .map_err(|err| format!("cannot connect to '{}': {err}", url.host))?;
+
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: nix-compiler\r\nAccept-Encoding: identity\r\nConnection: close\r\n\r\n",
+        url.path, url.host
+    );
+
+    let mut response = vec![];
+
+    let io_error = |err: std::io::Error| format!("cannot read from '{}': {err}", url.host);
+
+    if url.tls {
+        let connector = SslConnector::builder(SslMethod::tls())
+            .map_err(|err| err.to_string())?
+            .build();
+
+        let mut stream = connector
+            .connect(url.host, stream)
+            .map_err(|err| format!("TLS handshake with '{}' failed: {err}", url.host))?;
+
+        stream.write_all(request.as_bytes()).map_err(io_error)?;
+        stream.read_to_end(&mut response).map_err(io_error)?;
+    } else {
+        let mut stream = stream;
+
+        stream.write_all(request.as_bytes()).map_err(io_error)?;
+        stream.read_to_end(&mut response).map_err(io_error)?;
+    }
+
+    Ok(response)
+}
+
+fn decode_chunked(mut body: &[u8]) -> Result<Vec<u8>, String> {
+    let mut out = vec![];
+
+    loop {
+        let line_end = body
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or("truncated chunked body")?;
+
+        let size = std::str::from_utf8(&body[..line_end])
+            .ok()
+            .and_then(|line| usize::from_str_radix(line.split(';').next()?.trim(), 16).ok())
+            .ok_or("invalid chunk size")?;
+
+        body = &body[line_end + 2..];
+
+        if size == 0 {
+            return Ok(out);
+        }
+
+        if body.len() < size {
+            return Err(String::from("truncated chunked body"));
+        }
+
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// Body of `url`, non 2xx responses are errors
+pub fn get(url: &str) -> Result<Vec<u8>, String> {
+    let mut url = url.to_owned();
+
+    for _ in 0..MAX_REDIRECTS {
+        let response = request(&Url::parse(&url)?)?;
+
+        let header_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .ok_or_else(|| format!("invalid HTTP response from '{url}'"))?;
+
+        let head = String::from_utf8_lossy(&response[..header_end]);
+        let body = &response[header_end + 4..];
+
+        let mut lines = head.lines();
+
+        let status = lines
+            .next()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|status| status.parse::<u16>().ok())
+            .ok_or_else(|| format!("invalid HTTP response from '{url}'"))?;
+
+        let header = |name: &str| {
+            head.lines().skip(1).find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name)
+                    .then(|| value.trim().to_owned())
+            })
+        };
+
+        match status {
+            200..=299 => {
+                let chunked = header("Transfer-Encoding")
+                    .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"));
+
+                return if chunked {
+                    decode_chunked(body).map_err(|err| format!("{err} from '{url}'"))
+                } else {
+                    Ok(body.to_vec())
+                };
+            }
+            300..=399 => {
+                let Some(location) = header("Location") else {
+                    return Err(format!("redirect without a location from '{url}'"));
+                };
+
+                url = if location.contains("://") {
+                    location
+                } else {
+                    let parsed = Url::parse(&url)?;
+                    let scheme = if parsed.tls { "https" } else { "http" };
+
+                    format!("{scheme}://{}:{}{location}", parsed.host, parsed.port)
+                };
+            }
+            _ => return Err(format!("HTTP error {status} from '{url}'")),
+        }
+    }
+
+    Err(format!("too many redirects from '{url}'"))
+}
@@ -1,11 +1,20 @@
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use crate::check::strip_ansi;
+use crate::fetchers;
+use crate::impurities;
 use crate::result::NixBacktrace;
-use crate::{LazyNixValue, NixAttrSet, NixResult, NixValue, NixValueWrapped, Scope};
+use crate::{
+    FileScope, LazyNixValue, NixAttrSet, NixError, NixLabelKind, NixLabelMessage, NixResult,
+    NixValue, NixValueWrapped, NixVar, Scope,
+};
 
 pub fn resolve_flake(backtrace: &NixBacktrace, result: NixValueWrapped) -> NixResult {
     let result = result.borrow();
 
     let Some(flake) = result.as_attr_set() else {
-        todo!("Error handling");
+        return Err(expected_set(backtrace, "the flake", &result));
     };
 
     let inputs = flake
@@ -17,7 +26,7 @@ pub fn resolve_flake(backtrace: &NixBacktrace, result: NixValueWrapped) -> NixRe
     let inputs = inputs.borrow();
 
     let Some(inputs) = inputs.as_attr_set() else {
-        todo!("inputs should be attr set");
+        return Err(expected_set(backtrace, "the flake inputs", &inputs));
     };
 
     let inputs = inputs.iter().map::<NixResult<_>, _>(|(key, var)| {
@@ -25,28 +34,37 @@ pub fn resolve_flake(backtrace: &NixBacktrace, result: NixValueWrapped) -> NixRe
         let var = var.borrow();
 
         let Some(var) = var.as_attr_set() else {
-            todo!("input should be attr set")
+            let what = format!("the flake input '{key}'");
+
+            return Err(expected_set(backtrace, &what, &var));
         };
 
-        let path = var
-            .get("path")
-            .expect("TODO: Cloning repos")
+        let path = var.get("path").expect("TODO: Cloning repos").clone();
+
+        // Local inputs are fetched like any other, so they get the same source info
+        let mut input = NixAttrSet::new();
+        input.insert(
+            "type".to_owned(),
+            NixValue::String("path".into()).wrap_var(),
+        );
+        input.insert("path".to_owned(), path);
+
+        let mut out = fetchers::fetch_tree(backtrace, &input)?;
+
+        let path = out["outPath"]
             .resolve(backtrace)?
             .borrow()
             .as_path()
-            .unwrap_or_else(|| todo!("Eror handling"));
+            .unwrap();
 
         let flake_path = path.join("flake.nix");
 
         let flake = Scope::import_path(backtrace, flake_path)?;
 
-        let mut out = NixAttrSet::new();
-
         out.insert(
             "_type".to_owned(),
-            NixValue::String("flake".to_owned()).wrap_var(),
+            NixValue::String("flake".into()).wrap_var(),
         );
-        out.insert("outPath".to_owned(), NixValue::Path(path).wrap_var());
 
         out.insert(
             "outputs".to_owned(),
@@ -62,7 +80,14 @@ pub fn resolve_flake(backtrace: &NixBacktrace, result: NixValueWrapped) -> NixRe
     let outputs = outputs.borrow();
 
     let Some(lambda) = outputs.as_lambda() else {
-        todo!("outputs should be a lambda")
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!(
+                "the flake outputs: expected a lambda but found {}",
+                outputs.as_type_with_article()
+            ),
+        ));
     };
 
     let mut value = NixAttrSet::new();
@@ -85,3 +110,386 @@ pub fn resolve_flake(backtrace: &NixBacktrace, result: NixValueWrapped) -> NixRe
         .call(backtrace, NixValue::AttrSet(value).wrap_var())?
         .resolve(backtrace)
 }
+
+/// Fetch the source of a flake reference: `path:<path>`, `git+file://<path>`
+/// or an absolute path
+fn fetch_flake_ref(backtrace: &NixBacktrace, flake_ref: &str) -> NixResult<NixAttrSet> {
+    let error = |message: String| {
+        backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("getFlake: {message}"),
+        )
+    };
+
+    let (location, query) = flake_ref.split_once('?').unwrap_or((flake_ref, ""));
+
+    let (scheme, path) = match location.split_once(':') {
+        _ if location.starts_with('/') => ("path", location),
+        Some((scheme, rest)) if scheme != "flake" => {
+            (scheme, rest.strip_prefix("//").unwrap_or(rest))
+        }
+        _ => {
+            return Err(error(format!(
+                "cannot find flake '{flake_ref}', the flake registry is not supported"
+            )))
+        }
+    };
+
+    let string = |value: &str| NixValue::String(value.into()).wrap_var();
+
+    let mut input = NixAttrSet::new();
+
+    match scheme {
+        "path" => {
+            input.insert("type".to_owned(), string("path"));
+            input.insert("path".to_owned(), string(path));
+        }
+        "git+file" => {
+            input.insert("type".to_owned(), string("git"));
+            input.insert("url".to_owned(), string(path));
+        }
+        _ => {
+            return Err(error(format!(
+                "unsupported flake reference scheme '{scheme}' in '{flake_ref}'"
+            )))
+        }
+    }
+
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+
+        let value = match (scheme, key) {
+            ("git+file", "ref" | "rev") => string(value),
+            ("git+file", "shallow" | "submodules") => NixValue::Bool(value == "1").wrap_var(),
+            _ => {
+                return Err(error(format!(
+                    "unsupported parameter '{key}' in flake reference '{flake_ref}'"
+                )))
+            }
+        };
+
+        input.insert(key.to_owned(), value);
+    }
+
+    fetchers::fetch_tree(backtrace, &input)
+}
+
+/// `builtins.getFlake`, the outputs of the flake with its source info merged in
+pub fn get_flake(backtrace: &NixBacktrace, flake_ref: &str) -> NixResult<NixAttrSet> {
+    let source_info = fetch_flake_ref(backtrace, flake_ref)?;
+
+    let out_path = source_info["outPath"].clone();
+
+    let Some(path) = out_path.resolve(backtrace)?.borrow().as_path() else {
+        unreachable!("fetchers return a path or a store path as outPath");
+    };
+
+    let outputs = Scope::import_path(backtrace, path.join("flake.nix"))?;
+
+    let Some(mut out) = outputs.borrow().as_attr_set().cloned() else {
+        return Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("getFlake: the outputs of '{flake_ref}' are not a set"),
+        ));
+    };
+
+    out.extend(source_info.clone());
+
+    out.insert(
+        "_type".to_owned(),
+        NixValue::String("flake".into()).wrap_var(),
+    );
+    out.insert("outPath".to_owned(), out_path);
+    out.insert(
+        "outputs".to_owned(),
+        LazyNixValue::Concrete(outputs).wrap_var(),
+    );
+    out.insert(
+        "sourceInfo".to_owned(),
+        NixValue::AttrSet(source_info).wrap_var(),
+    );
+
+    Ok(out)
+}
+
+const PER_SYSTEM_OUTPUTS: &[&str] = &["apps", "checks", "devShells", "formatter", "packages"];
+
+/// Entry point of `nix-compiler flake (metadata | show) [path]`
+pub fn run(mut args: impl Iterator<Item = String>) -> i32 {
+    let command = args.next();
+    let path = args.next().unwrap_or_else(|| ".".to_owned());
+
+    impurities::set_options(impurities::EvalOptions {
+        pure: true,
+        ..impurities::options()
+    });
+
+    let result = match command.as_deref() {
+        Some("metadata") => metadata(&path),
+        Some("show") => show(&path),
+        _ => {
+            eprintln!("Usage: nix-compiler flake (metadata | show) [path]");
+            return 1;
+        }
+    };
+
+    match result {
+        Ok(out) => {
+            print!("{out}");
+            0
+        }
+        Err(err) => {
+            eprintln!("{err}");
+            1
+        }
+    }
+}
+
+fn flake_file(path: &str) -> PathBuf {
+    let path = PathBuf::from(path);
+
+    if path.is_dir() {
+        path.join("flake.nix")
+    } else {
+        path
+    }
+}
+
+/// Force `var` and return the error message inline if it fails, like `nix flake show`
+fn try_resolve(backtrace: &NixBacktrace, var: &NixVar) -> Result<NixValueWrapped, String> {
+    var.resolve(backtrace)
+        .map_err(|err| format!("«error: {}»", strip_ansi(&err.message)))
+}
+
+fn expected_set(backtrace: &NixBacktrace, what: &str, value: &NixValue) -> NixError {
+    backtrace.to_error(
+        NixLabelKind::Error,
+        NixLabelMessage::Empty,
+        format!(
+            "{what}: expected a set but found {}",
+            value.as_type_with_article()
+        ),
+    )
+}
+
+fn get_string(backtrace: &NixBacktrace, set: &NixAttrSet, attr: &str) -> NixResult<Option<String>> {
+    let Some(var) = set.get(attr) else {
+        return Ok(None);
+    };
+
+    Ok(var.resolve(backtrace)?.borrow().as_string().cloned())
+}
+
+pub fn metadata(path_arg: &str) -> NixResult<String> {
+    let (backtrace, flake) = FileScope::get_file(None, flake_file(path_arg))?;
+    let flake = flake.borrow();
+
+    let Some(flake) = flake.as_attr_set() else {
+        return Err(expected_set(&backtrace, "the flake", &flake));
+    };
+
+    let mut out = String::new();
+
+    if let Some(description) = get_string(&backtrace, flake, "description")? {
+        let _ = writeln!(out, "Description:   {description}");
+    }
+
+    let _ = writeln!(out, "Path:          {path_arg}");
+
+    let Some(inputs) = flake.get("inputs") else {
+        return Ok(out);
+    };
+
+    let inputs = inputs.resolve(&backtrace)?;
+    let inputs = inputs.borrow();
+
+    let Some(inputs) = inputs.as_attr_set() else {
+        return Err(expected_set(&backtrace, "the flake inputs", &inputs));
+    };
+
+    out.push_str("Inputs:\n");
+
+    for (idx, (name, input)) in inputs.iter().enumerate() {
+        let branch = if idx + 1 == inputs.len() {
+            "└───"
+        } else {
+            "├───"
+        };
+
+        let input = input.resolve(&backtrace)?;
+        let input = input.borrow();
+
+        let Some(input) = input.as_attr_set() else {
+            let what = format!("the flake input '{name}'");
+
+            return Err(expected_set(&backtrace, &what, &input));
+        };
+
+        let url = get_string(&backtrace, input, "url")?;
+        let path = match input.get("path") {
+            Some(path) => path.resolve(&backtrace)?.borrow().as_path(),
+            None => None,
+        };
+
+        let _ = write!(
+            out,
+            "{branch}{name}: {}",
+            url.as_deref().unwrap_or("<no url>")
+        );
+
+        if let Some(input_path) = path {
+            // Relative to the flake so the output doesn't depend on where it is checked out
+            let flake_dir = flake_file(path_arg).canonicalize().ok();
+            let flake_dir = flake_dir.as_deref().and_then(Path::parent);
+
+            let input_path = flake_dir
+                .and_then(|dir| input_path.strip_prefix(dir).ok())
+                .map(|relative| Path::new(".").join(relative))
+                .unwrap_or(input_path);
+
+            let _ = write!(out, " ({})", input_path.display());
+        }
+
+        out.push('\n');
+    }
+
+    Ok(out)
+}
+
+pub fn show(path: &str) -> NixResult<String> {
+    let (backtrace, flake) = FileScope::get_file(None, flake_file(path))?;
+    let outputs = resolve_flake(&backtrace, flake)?;
+    let outputs = outputs.borrow();
+
+    let Some(outputs) = outputs.as_attr_set() else {
+        return Err(expected_set(
+            &backtrace,
+            "the result of the flake outputs",
+            &outputs,
+        ));
+    };
+
+    let mut out = format!("{path}\n");
+
+    for (idx, (kind, value)) in outputs.iter().enumerate() {
+        let is_last = idx + 1 == outputs.len();
+        let (branch, prefix) = tree_branch(is_last);
+
+        let value = match try_resolve(&backtrace, value) {
+            Ok(value) => value,
+            Err(err) => {
+                let _ = writeln!(out, "{branch}{kind}: {err}");
+                continue;
+            }
+        };
+
+        let _ = writeln!(out, "{branch}{kind}");
+
+        let value = value.borrow();
+        let Some(value) = value.as_attr_set() else {
+            continue;
+        };
+
+        if PER_SYSTEM_OUTPUTS.contains(&kind.as_str()) {
+            show_systems(&mut out, &backtrace, kind, value, prefix);
+        } else {
+            show_leaves(&mut out, &backtrace, kind, value, prefix);
+        }
+    }
+
+    Ok(out)
+}
+
+fn tree_branch(is_last: bool) -> (&'static str, &'static str) {
+    if is_last {
+        ("└───", "    ")
+    } else {
+        ("├───", "│   ")
+    }
+}
+
+fn show_systems(
+    out: &mut String,
+    backtrace: &NixBacktrace,
+    kind: &str,
+    systems: &NixAttrSet,
+    prefix: &str,
+) {
+    for (idx, (system, value)) in systems.iter().enumerate() {
+        let (branch, next_prefix) = tree_branch(idx + 1 == systems.len());
+
+        // `formatter.<system>` is a single package
+        if kind == "formatter" {
+            let description = describe_leaf(backtrace, kind, value);
+            let _ = writeln!(out, "{prefix}{branch}{system}: {description}");
+            continue;
+        }
+
+        let value = match try_resolve(backtrace, value) {
+            Ok(value) => value,
+            Err(err) => {
+                let _ = writeln!(out, "{prefix}{branch}{system}: {err}");
+                continue;
+            }
+        };
+
+        let _ = writeln!(out, "{prefix}{branch}{system}");
+
+        let value = value.borrow();
+        if let Some(value) = value.as_attr_set() {
+            show_leaves(
+                out,
+                backtrace,
+                kind,
+                value,
+                &format!("{prefix}{next_prefix}"),
+            );
+        }
+    }
+}
+
+fn show_leaves(
+    out: &mut String,
+    backtrace: &NixBacktrace,
+    kind: &str,
+    leaves: &NixAttrSet,
+    prefix: &str,
+) {
+    for (idx, (name, value)) in leaves.iter().enumerate() {
+        let (branch, _) = tree_branch(idx + 1 == leaves.len());
+        let description = describe_leaf(backtrace, kind, value);
+
+        let _ = writeln!(out, "{prefix}{branch}{name}: {description}");
+    }
+}
+
+fn describe_leaf(backtrace: &NixBacktrace, kind: &str, value: &NixVar) -> String {
+    let value = match try_resolve(backtrace, value) {
+        Ok(value) => value,
+        Err(err) => return err,
+    };
+
+    let value = value.borrow();
+
+    let name = || {
+        value
+            .as_attr_set()
+            .and_then(|set| get_string(backtrace, set, "name").ok().flatten())
+            .unwrap_or_else(|| "<unnamed>".to_owned())
+    };
+
+    match kind {
+        "apps" => "app".to_owned(),
+        "checks" => format!("derivation '{}'", name()),
+        "devShells" => format!("development environment '{}'", name()),
+        "formatter" | "packages" => format!("package '{}'", name()),
+        "nixosConfigurations" => "NixOS configuration".to_owned(),
+        "nixosModules" => "NixOS module".to_owned(),
+        "overlays" => "Nixpkgs overlay".to_owned(),
+        "templates" => "template".to_owned(),
+        _ if value.is_function() => "function".to_owned(),
+        _ => value.as_type().to_owned(),
+    }
+}
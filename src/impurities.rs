@@ -0,0 +1,167 @@
+//! Record of everything an evaluation read from outside its sources
+//!
+//! Pure evaluation (flakes) must not depend on the environment, every builtin
+//! that reaches outside records here instead of checking purity on its own.
+
+use std::cell::{Cell, RefCell};
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::store;
+use crate::{NixBacktrace, NixError, NixLabelKind, NixLabelMessage};
+
+thread_local! {
+    static IMPURITIES: RefCell<Impurities> = RefCell::new(Impurities::default());
+    static OPTIONS: Cell<EvalOptions> = Cell::new(EvalOptions::default());
+}
+
+/// How the evaluation treats the environment and its limits, set once by `main`
+#[derive(Clone, Copy, Debug)]
+pub struct EvalOptions {
+    /// `--pure-eval` and flakes, builtins that would read the environment
+    /// give empty results instead
+    pub pure: bool,
+    /// `--max-call-depth`, nested function calls before giving up instead of
+    /// overflowing the stack
+    pub max_call_depth: usize,
+}
+
+impl Default for EvalOptions {
+    fn default() -> Self {
+        Self {
+            pure: false,
+            // Like Nix's `max-call-depth`
+            max_call_depth: 10_000,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Impurity {
+    /// `builtins.getEnv`
+    EnvVar(String),
+    /// Absolute path read outside the evaluation root
+    Path(PathBuf),
+    /// `builtins.storePath`, the store content isn't known by the sources
+    StorePath(String),
+    /// `builtins.currentTime`
+    CurrentTime,
+    /// Download without an expected hash, its content can change
+    Download(String),
+}
+
+impl Impurity {
+    fn category(&self) -> &'static str {
+        match self {
+            Impurity::EnvVar(_) => "environment variables",
+            Impurity::Path(_) => "paths outside the root",
+            Impurity::StorePath(_) => "store paths",
+            Impurity::CurrentTime => "time",
+            Impurity::Download(_) => "unhashed downloads",
+        }
+    }
+}
+
+impl fmt::Display for Impurity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Impurity::EnvVar(name) => write!(f, "environment variable '{name}'"),
+            Impurity::Path(path) => write!(f, "path '{}'", path.display()),
+            Impurity::StorePath(path) => write!(f, "store path '{path}'"),
+            Impurity::CurrentTime => f.write_str("current time"),
+            Impurity::Download(url) => write!(f, "unhashed download of '{url}'"),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Impurities {
+    /// Directory of the evaluated file, reads inside of it are pure
+    root: Option<PathBuf>,
+    entries: Vec<(Impurity, NixBacktrace)>,
+}
+
+impl Impurities {
+    /// Fail with the first impurity, pointing where it happened
+    pub fn check_pure(&self) -> Result<(), NixError> {
+        let Some((impurity, backtrace)) = self.entries.first() else {
+            return Ok(());
+        };
+
+        Err(backtrace.to_error(
+            NixLabelKind::Error,
+            NixLabelMessage::Empty,
+            format!("Pure evaluation cannot access the {impurity}"),
+        ))
+    }
+
+    /// Short report, one line per category with every distinct impurity
+    pub fn report(&self) -> String {
+        let mut categories: Vec<(&str, Vec<String>)> = vec![];
+
+        for (impurity, _) in &self.entries {
+            let value = match impurity {
+                Impurity::EnvVar(name) => name.clone(),
+                Impurity::Path(path) => path.display().to_string(),
+                Impurity::StorePath(path) => path.clone(),
+                Impurity::CurrentTime => String::from("builtins.currentTime"),
+                Impurity::Download(url) => url.clone(),
+            };
+
+            let category = impurity.category();
+
+            match categories.iter_mut().find(|(name, _)| *name == category) {
+                Some((_, values)) if values.contains(&value) => {}
+                Some((_, values)) => values.push(value),
+                None => categories.push((category, vec![value])),
+            }
+        }
+
+        if categories.is_empty() {
+            return String::from("Impurities: none\n");
+        }
+
+        let mut out = String::from("Impurities:\n");
+
+        for (category, values) in categories {
+            let _ = writeln!(out, "  {category}: {}", values.join(", "));
+        }
+
+        out
+    }
+}
+
+pub fn set_options(options: EvalOptions) {
+    OPTIONS.set(options);
+}
+
+pub fn options() -> EvalOptions {
+    OPTIONS.get()
+}
+
+pub fn set_root(root: impl AsRef<Path>) {
+    IMPURITIES.with_borrow_mut(|impurities| impurities.root = Some(root.as_ref().to_path_buf()));
+}
+
+pub fn record(backtrace: &NixBacktrace, impurity: Impurity) {
+    IMPURITIES.with_borrow_mut(|impurities| impurities.entries.push((impurity, backtrace.clone())));
+}
+
+/// Record `path` if it's outside both the root and the store
+pub fn record_path(backtrace: &NixBacktrace, path: &Path) {
+    let is_pure = path.starts_with(store::store_dir())
+        || IMPURITIES.with_borrow(|impurities| {
+            impurities
+                .root
+                .as_ref()
+                .is_some_and(|root| path.starts_with(root))
+        });
+
+    if !is_pure {
+        record(backtrace, Impurity::Path(path.to_path_buf()));
+    }
+}
+
+pub fn with<T>(f: impl FnOnce(&Impurities) -> T) -> T {
+    IMPURITIES.with_borrow(f)
+}
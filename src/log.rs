@@ -0,0 +1,39 @@
+//! Messages printed while evaluating: `trace`, `traceVerbose` and `warn`
+//!
+//! Everything goes to stderr so it doesn't mix with the result.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::LazyLock;
+
+static VERBOSE_ENV: LazyLock<bool> = LazyLock::new(|| {
+    std::env::var("NIX_COMPILER_VERBOSE").is_ok_and(|env| !env.is_empty() && env != "0")
+});
+
+static VERBOSE_FLAG: AtomicBool = AtomicBool::new(false);
+
+/// `--trace-verbose`, the same as setting `NIX_COMPILER_VERBOSE`
+pub fn enable_verbose() {
+    VERBOSE_FLAG.store(true, Ordering::Relaxed);
+}
+
+/// Whether `traceVerbose` prints
+pub fn is_verbose() -> bool {
+    VERBOSE_FLAG.load(Ordering::Relaxed) || *VERBOSE_ENV
+}
+
+fn write_trace(out: &mut impl Write, message: &str) -> io::Result<()> {
+    writeln!(out, "\x1b[1mtrace:\x1b[0m {message}")
+}
+
+fn write_warning(out: &mut impl Write, message: &str) -> io::Result<()> {
+    writeln!(out, "\x1b[1;35mwarning:\x1b[0m {message}")
+}
+
+pub fn trace(message: &str) {
+    let _ = write_trace(&mut io::stderr().lock(), message);
+}
+
+pub fn warn(message: &str) {
+    let _ = write_warning(&mut io::stderr().lock(), message);
+}
@@ -1,22 +1,170 @@
+mod attr_path;
 pub mod builtins;
+mod check;
+mod derivation;
 mod expr;
+mod fetchers;
 pub mod flake;
+mod impurities;
+mod log;
 mod result;
 mod scope;
+mod search_path;
+mod store;
 mod value;
 
 pub use builtins::{NixBuiltin, NixBuiltinInfo};
 pub use result::{
-    NixBacktrace, NixBacktraceKind, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult,
-    NixSpan,
+    NixBacktrace, NixBacktraceKind, NixError, NixErrorKind, NixLabel, NixLabelKind,
+    NixLabelMessage, NixResult, NixSpan,
 };
 pub use scope::{FileScope, Scope};
 use std::env;
-pub use value::{LazyNixValue, NixAttrSet, NixLambdaParam, NixValue, NixValueWrapped, NixVar};
+use std::path::PathBuf;
+pub use value::{
+    LazyNixValue, NixAttrSet, NixLambdaParam, NixValue, NixValueWrapped, NixVar, ToNixMode,
+};
+
+/// Stack of the evaluation thread, deep enough for `max-call-depth` nested calls
+const EVAL_STACK_SIZE: usize = 1 << 30;
 
 fn main() {
+    // Only what the evaluation uses of the stack is allocated
+    let eval = std::thread::Builder::new()
+        .name("eval".to_owned())
+        .stack_size(EVAL_STACK_SIZE)
+        .spawn(run)
+        .expect("Cannot spawn the evaluation thread");
+
+    if let Err(panic) = eval.join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+fn set_store_dir(store_dir: &str) {
+    match store::StoreConfig::new(store_dir) {
+        Ok(config) => store::set_config(config),
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn run() {
     let mut iter = env::args().skip(1).peekable();
 
+    // `--store` takes precedence
+    if let Ok(store_dir) = env::var("NIX_STORE_DIR") {
+        set_store_dir(&store_dir);
+    }
+
+    if iter.peek().is_some_and(|arg| arg == "check") {
+        iter.next();
+        std::process::exit(check::run(iter));
+    }
+
+    if iter.peek().is_some_and(|arg| arg == "flake") {
+        iter.next();
+        std::process::exit(flake::run(iter));
+    }
+
+    let mut import_graph = None;
+    let mut to_nix = None;
+    let mut print_impurities = false;
+    let mut pure_eval = false;
+    let mut max_call_depth = None;
+    let mut attr_path = None;
+    let mut includes = vec![];
+
+    loop {
+        match iter.peek().map(String::as_str) {
+            Some("--import-graph") => {
+                iter.next();
+
+                let Some(path) = iter.next() else {
+                    eprintln!("Usage: nix-compiler --import-graph <output> <file>");
+                    std::process::exit(1);
+                };
+
+                scope::import_graph::enable();
+
+                import_graph = Some(PathBuf::from(path));
+            }
+            Some("--to-nix") => {
+                iter.next();
+                to_nix = Some(ToNixMode::Strict);
+            }
+            Some("--to-nix=with-out-paths") => {
+                iter.next();
+                to_nix = Some(ToNixMode::WithOutPaths);
+            }
+            Some("--print-impurities") => {
+                iter.next();
+                print_impurities = true;
+            }
+            Some("--pure-eval") => {
+                iter.next();
+                pure_eval = true;
+            }
+            Some("--max-call-depth") => {
+                iter.next();
+
+                let Some(depth) = iter.next().and_then(|depth| depth.parse().ok()) else {
+                    eprintln!("Usage: nix-compiler --max-call-depth <number> <file>");
+                    std::process::exit(1);
+                };
+
+                max_call_depth = Some(depth);
+            }
+            Some("--store") => {
+                iter.next();
+
+                let Some(store_dir) = iter.next() else {
+                    eprintln!("Usage: nix-compiler --store <dir> <file>");
+                    std::process::exit(1);
+                };
+
+                set_store_dir(&store_dir);
+            }
+            Some("--trace-verbose") => {
+                iter.next();
+                log::enable_verbose();
+            }
+            Some("-I" | "--include") => {
+                iter.next();
+
+                let Some(entry) = iter.next() else {
+                    eprintln!("Usage: nix-compiler (-I | --include) [<prefix>=]<path> <file>");
+                    std::process::exit(1);
+                };
+
+                includes.push(entry);
+            }
+            Some("-A" | "--attr") => {
+                iter.next();
+
+                let Some(path) = iter.next() else {
+                    eprintln!("Usage: nix-compiler (-A | --attr) <attrpath> <file>");
+                    std::process::exit(1);
+                };
+
+                attr_path = Some(path);
+            }
+            _ => break,
+        }
+    }
+
+    search_path::init(&includes);
+
+    let write_import_graph = || {
+        if let Some(path) = &import_graph {
+            if let Err(err) = scope::import_graph::write(path) {
+                eprintln!("Cannot write import graph to {}: {err}", path.display());
+            }
+        }
+    };
+
     let is_evaluation = iter
         .peek()
         .is_some_and(|arg| arg == "-e" || arg == "--eval");
@@ -26,13 +174,72 @@ fn main() {
     }
 
     let Some(arg) = iter.next() else {
-        eprintln!("Usage: nix-compiler <file>");
-        eprintln!("Usage: nix-compiler (--eval | -e) <expr>");
+        eprintln!("Usage: nix-compiler [OPTIONS] <file>");
+        eprintln!("Usage: nix-compiler [OPTIONS] (--eval | -e) <expr>");
+        eprintln!("Usage: nix-compiler check [--json] <path>...");
+        eprintln!("Usage: nix-compiler flake (metadata | show) [path]");
+        eprintln!();
+        eprintln!("Options:");
+        eprintln!("  --import-graph <output>   Write the import graph as DOT, or JSON for `.json`");
+        eprintln!("  --to-nix[=with-out-paths] Print the result as Nix source");
+        eprintln!("  --print-impurities        Report what was read outside the evaluated sources");
+        eprintln!("  --pure-eval               Don't read the environment, like flakes");
+        eprintln!("  --max-call-depth <number> Nested function calls allowed, 10000 by default");
+        eprintln!(
+            "  --store <dir>             Store directory, NIX_STORE_DIR or /nix/store by default"
+        );
+        eprintln!("  --trace-verbose           Print `builtins.traceVerbose` messages too");
+        eprintln!(
+            "  -A, --attr <attrpath>     Only evaluate and print this attribute of the result"
+        );
+        eprintln!("  -I, --include [<prefix>=]<path>");
+        eprintln!("                            Add to the search path of `<...>`, before NIX_PATH");
+        eprintln!();
+        eprintln!("Flake outputs can also be selected with `flake.nix#<attrpath>`");
         return;
     };
 
+    // `path/flake.nix#packages.x86_64-linux.default`
+    let (arg, attr_path) = match arg.split_once('#') {
+        Some((file, fragment)) if !is_evaluation && file.ends_with("flake.nix") => {
+            if attr_path.is_some() {
+                eprintln!("Cannot use both `-A` and a `#` attribute path");
+                std::process::exit(1);
+            }
+
+            (file.to_owned(), Some(fragment.to_owned()))
+        }
+        _ => (arg, attr_path),
+    };
+
+    let attr_path = attr_path
+        .map(|path| attr_path::AttrPathComponent::parse_path(&path))
+        .transpose()
+        .unwrap_or_else(|err| {
+            eprintln!("Invalid attribute path: {err}");
+            std::process::exit(1);
+        })
+        .unwrap_or_default();
+
     let is_flake = !is_evaluation && arg.ends_with("flake.nix");
 
+    let defaults = impurities::EvalOptions::default();
+
+    impurities::set_options(impurities::EvalOptions {
+        pure: pure_eval || is_flake,
+        max_call_depth: max_call_depth.unwrap_or(defaults.max_call_depth),
+    });
+
+    if is_evaluation {
+        impurities::set_root(std::env::current_dir().unwrap());
+    } else if let Some(root) = PathBuf::from(&arg)
+        .canonicalize()
+        .ok()
+        .and_then(|path| path.parent().map(PathBuf::from))
+    {
+        impurities::set_root(root);
+    }
+
     let file = if is_evaluation {
         FileScope::repl_file(std::env::current_dir().unwrap(), arg)
     } else {
@@ -41,6 +248,7 @@ fn main() {
 
     let (backtrace, result) = file.unwrap_or_else(|err| {
         eprintln!("{err}");
+        write_import_graph();
         std::process::exit(1);
     });
 
@@ -50,14 +258,44 @@ fn main() {
         result
     };
 
-    let outputs = LazyNixValue::Concrete(outputs)
-        .wrap_var()
-        .resolve_set(true, &backtrace)
-        .unwrap_or_else(|err| {
+    let outputs = attr_path::select_path(
+        LazyNixValue::Concrete(outputs).wrap_var(),
+        &attr_path,
+        &backtrace,
+    )
+    .and_then(|outputs| outputs.resolve_set(true, &backtrace))
+    .unwrap_or_else(|err| {
+        eprintln!("{err}");
+        write_import_graph();
+        std::process::exit(1);
+    });
+
+    if print_impurities {
+        eprint!("{}", impurities::with(|impurities| impurities.report()));
+    }
+
+    // Flakes are evaluated purely
+    if is_flake {
+        if let Err(err) = impurities::with(|impurities| impurities.check_pure()) {
             eprintln!("{err}");
+            write_import_graph();
             std::process::exit(1);
-        });
+        }
+    }
+
+    if let Some(mode) = to_nix {
+        match outputs.borrow().to_nix(mode) {
+            Ok(source) => println!("{source}"),
+            Err(err) => {
+                eprintln!("{err}");
+                write_import_graph();
+                std::process::exit(1);
+            }
+        }
+    } else {
+        println!("Result (Expanded): {:#}", outputs.borrow());
+        println!("Result (Minimized): {}", outputs.borrow());
+    }
 
-    println!("Result (Expanded): {:#}", outputs.borrow());
-    println!("Result (Minimized): {}", outputs.borrow());
+    write_import_graph();
 }
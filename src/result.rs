@@ -4,7 +4,7 @@ use std::fmt::{self, Write};
 use std::rc::Rc;
 
 use backtrace::BACKTRACE_ENV;
-use rnix::{parser, SyntaxKind};
+use rnix::{parser, SyntaxKind, TextRange};
 use rowan::ast::AstNode;
 use thiserror::Error;
 
@@ -20,6 +20,20 @@ pub struct NixError {
     pub message: String,
     pub labels: Vec<NixLabel>,
     pub backtrace: Rc<Option<NixBacktrace>>,
+    pub kind: NixErrorKind,
+}
+
+/// What raised an error, `tryEval` only catches `throw` and failed asserts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NixErrorKind {
+    /// Everything else: type errors, missing attributes, IO...
+    Eval,
+    /// `assert` with a false condition
+    Assert,
+    /// `builtins.throw`
+    Throw,
+    /// `builtins.abort`
+    Abort,
 }
 
 #[derive(Clone, Debug)]
@@ -112,19 +126,55 @@ impl fmt::Display for NixError {
 impl std::error::Error for NixError {}
 
 impl NixError {
+    pub fn with_kind(mut self, kind: NixErrorKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Whether `tryEval` catches it
+    pub fn is_catchable(&self) -> bool {
+        matches!(self.kind, NixErrorKind::Assert | NixErrorKind::Throw)
+    }
+
     pub fn from_message(label: NixLabel, message: impl ToString) -> Self {
         Self {
             message: message.to_string(),
             labels: vec![label.into()],
             backtrace: None.into(),
+            kind: NixErrorKind::Eval,
         }
     }
 
     pub fn from_parse_error(file: &Rc<FileScope>, error: parser::ParseError) -> Self {
         use parser::ParseError::*;
+
+        let unexpected_at = |range: TextRange, message: &str| {
+            (
+                message.to_owned(),
+                vec![NixLabel::new(
+                    NixSpan::from_text_range(file, range).into(),
+                    NixLabelMessage::UnexpectedToken,
+                    NixLabelKind::Error,
+                )],
+            )
+        };
+
+        let eof_label = |message: String| {
+            let end = file.content.len();
+
+            (
+                message,
+                vec![NixLabel::new(
+                    NixSpan::from_offset(file, end, end).into(),
+                    NixLabelMessage::Custom("Unexpected end of file".to_owned()),
+                    NixLabelKind::Error,
+                )],
+            )
+        };
+
         let (message, labels) = match error {
-            Unexpected(_) => todo!(),
-            UnexpectedExtra(_) => todo!(),
+            Unexpected(range) => unexpected_at(range, "Unexpected token"),
+            UnexpectedExtra(range) => unexpected_at(range, "Unexpected extra token"),
             UnexpectedWanted(unexpected, range, expected) => {
                 if expected.len() == 1 {
                     let range_start: usize = range.start().into();
@@ -156,14 +206,43 @@ impl NixError {
                         vec![unexpected_label, expected_label],
                     )
                 } else {
-                    todo!()
+                    let expected = expected
+                        .iter()
+                        .map(|kind| format!("'{}'", syntax_kind_to_string(*kind)))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+
+                    unexpected_at(
+                        range,
+                        &format!(
+                            "Unexpected token '{}', expected one of {expected}",
+                            syntax_kind_to_string(unexpected)
+                        ),
+                    )
                 }
             }
-            UnexpectedDoubleBind(_) => todo!(),
-            UnexpectedEOF => todo!(),
-            UnexpectedEOFWanted(_) => todo!(),
-            DuplicatedArgs(_, _) => todo!(),
-            RecursionLimitExceeded => todo!(),
+            UnexpectedDoubleBind(range) => unexpected_at(range, "Unexpected double bind"),
+            UnexpectedEOF => eof_label("Unexpected end of file".to_owned()),
+            UnexpectedEOFWanted(expected) => {
+                let expected = expected
+                    .iter()
+                    .map(|kind| format!("'{}'", syntax_kind_to_string(*kind)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+
+                eof_label(format!(
+                    "Unexpected end of file, expected one of {expected}"
+                ))
+            }
+            DuplicatedArgs(range, ident) => (
+                format!("Duplicated function argument '\x1b[1;95m{ident}\x1b[0m'"),
+                vec![NixLabel::new(
+                    NixSpan::from_text_range(file, range).into(),
+                    NixLabelMessage::Custom("Duplicated argument".to_owned()),
+                    NixLabelKind::Error,
+                )],
+            ),
+            RecursionLimitExceeded => eof_label("Parser recursion limit exceeded".to_owned()),
             _ => unreachable!(),
         };
 
@@ -171,6 +250,7 @@ impl NixError {
             message,
             labels,
             backtrace: None.into(),
+            kind: NixErrorKind::Eval,
         }
     }
 
@@ -189,6 +269,7 @@ impl NixError {
             message,
             labels: vec![label],
             backtrace: backtrace.into(),
+            kind: NixErrorKind::Eval,
         }
     }
 }
@@ -196,6 +277,10 @@ impl NixError {
 impl NixSpan {
     fn get_line_column(file: &FileScope, mut offset: usize) -> (usize, usize, usize) {
         loop {
+            if offset == 0 || file.content.is_empty() {
+                break (1, 0, 0);
+            }
+
             let last_newline = offset
                 - file.content[..offset]
                     .chars()
@@ -230,12 +315,23 @@ impl NixSpan {
     }
 
     pub fn from_ast_node(file: &Rc<FileScope>, node: &impl AstNode) -> Self {
+        Self::from_text_range(file, node.syntax().text_range())
+    }
+
+    pub fn from_text_range(file: &Rc<FileScope>, range: TextRange) -> Self {
         Self::from_offset(
             file,
-            usize::from(node.syntax().text_range().start()) + 1,
-            usize::from(node.syntax().text_range().end()),
+            usize::from(range.start()) + 1,
+            usize::from(range.end()),
         )
     }
+
+    /// `<file>:<line>:<column>` of the start, for messages
+    pub fn location(&self) -> String {
+        let (line, column, _) = self.start;
+
+        format!("{}:{line}:{}", self.file.path.display(), column + 1)
+    }
 }
 
 impl<T: AstNode> From<(&Rc<FileScope>, &T)> for NixSpan {
@@ -264,27 +360,27 @@ fn syntax_kind_to_string(kind: SyntaxKind) -> &'static str {
 
         // Keywords
         SyntaxKind::TOKEN_ASSERT => "assert",
-        SyntaxKind::TOKEN_ELSE => todo!(),
-        SyntaxKind::TOKEN_IF => todo!(),
-        SyntaxKind::TOKEN_IN => todo!(),
-        SyntaxKind::TOKEN_INHERIT => todo!(),
-        SyntaxKind::TOKEN_LET => todo!(),
-        SyntaxKind::TOKEN_OR => todo!(),
-        SyntaxKind::TOKEN_REC => todo!(),
-        SyntaxKind::TOKEN_THEN => todo!(),
-        SyntaxKind::TOKEN_WITH => todo!(),
+        SyntaxKind::TOKEN_ELSE => "else",
+        SyntaxKind::TOKEN_IF => "if",
+        SyntaxKind::TOKEN_IN => "in",
+        SyntaxKind::TOKEN_INHERIT => "inherit",
+        SyntaxKind::TOKEN_LET => "let",
+        SyntaxKind::TOKEN_OR => "or",
+        SyntaxKind::TOKEN_REC => "rec",
+        SyntaxKind::TOKEN_THEN => "then",
+        SyntaxKind::TOKEN_WITH => "with",
 
         // Literals
-        SyntaxKind::TOKEN_FLOAT => todo!(),
-        SyntaxKind::TOKEN_IDENT => todo!(),
-        SyntaxKind::TOKEN_INTEGER => todo!(),
-        SyntaxKind::TOKEN_INTERPOL_END => todo!(),
-        SyntaxKind::TOKEN_INTERPOL_START => todo!(),
-        SyntaxKind::TOKEN_PATH => todo!(),
-        SyntaxKind::TOKEN_URI => todo!(),
-        SyntaxKind::TOKEN_STRING_CONTENT => todo!(),
-        SyntaxKind::TOKEN_STRING_END => todo!(),
-        SyntaxKind::TOKEN_STRING_START => todo!(),
+        SyntaxKind::TOKEN_FLOAT => "<float>",
+        SyntaxKind::TOKEN_IDENT => "<identifier>",
+        SyntaxKind::TOKEN_INTEGER => "<integer>",
+        SyntaxKind::TOKEN_INTERPOL_END => "}",
+        SyntaxKind::TOKEN_INTERPOL_START => "${",
+        SyntaxKind::TOKEN_PATH => "<path>",
+        SyntaxKind::TOKEN_URI => "<uri>",
+        SyntaxKind::TOKEN_STRING_CONTENT => "<string content>",
+        SyntaxKind::TOKEN_STRING_END => "\"",
+        SyntaxKind::TOKEN_STRING_START => "\"",
 
         // Punctuation
         SyntaxKind::TOKEN_ELLIPSIS => "...",
@@ -297,60 +393,60 @@ fn syntax_kind_to_string(kind: SyntaxKind) -> &'static str {
         SyntaxKind::TOKEN_SEMICOLON => ";",
 
         // Operators
-        SyntaxKind::TOKEN_ASSIGN => todo!(),
-        SyntaxKind::TOKEN_AT => todo!(),
-        SyntaxKind::TOKEN_COLON => todo!(),
-        SyntaxKind::TOKEN_COMMA => todo!(),
-        SyntaxKind::TOKEN_DOT => todo!(),
-        SyntaxKind::TOKEN_QUESTION => todo!(),
-        SyntaxKind::TOKEN_CONCAT => todo!(),
-        SyntaxKind::TOKEN_INVERT => todo!(),
-        SyntaxKind::TOKEN_UPDATE => todo!(),
-        SyntaxKind::TOKEN_ADD => todo!(),
-        SyntaxKind::TOKEN_SUB => todo!(),
-        SyntaxKind::TOKEN_MUL => todo!(),
-        SyntaxKind::TOKEN_DIV => todo!(),
-        SyntaxKind::TOKEN_AND_AND => todo!(),
-        SyntaxKind::TOKEN_EQUAL => todo!(),
-        SyntaxKind::TOKEN_IMPLICATION => todo!(),
-        SyntaxKind::TOKEN_LESS => todo!(),
-        SyntaxKind::TOKEN_LESS_OR_EQ => todo!(),
-        SyntaxKind::TOKEN_MORE => todo!(),
-        SyntaxKind::TOKEN_MORE_OR_EQ => todo!(),
-        SyntaxKind::TOKEN_NOT_EQUAL => todo!(),
-        SyntaxKind::TOKEN_OR_OR => todo!(),
-
-        SyntaxKind::NODE_APPLY => todo!(),
-        SyntaxKind::NODE_ASSERT => todo!(),
-        SyntaxKind::NODE_ATTRPATH => todo!(),
-        SyntaxKind::NODE_DYNAMIC => todo!(),
-        SyntaxKind::NODE_ERROR => todo!(),
-        SyntaxKind::NODE_IDENT => todo!(),
-        SyntaxKind::NODE_IF_ELSE => todo!(),
-        SyntaxKind::NODE_SELECT => todo!(),
-        SyntaxKind::NODE_INHERIT => todo!(),
-        SyntaxKind::NODE_INHERIT_FROM => todo!(),
-        SyntaxKind::NODE_STRING => todo!(),
-        SyntaxKind::NODE_INTERPOL => todo!(),
-        SyntaxKind::NODE_LAMBDA => todo!(),
-        SyntaxKind::NODE_IDENT_PARAM => todo!(),
-        SyntaxKind::NODE_LEGACY_LET => todo!(),
-        SyntaxKind::NODE_LET_IN => todo!(),
-        SyntaxKind::NODE_LIST => todo!(),
-        SyntaxKind::NODE_BIN_OP => todo!(),
-        SyntaxKind::NODE_PAREN => todo!(),
-        SyntaxKind::NODE_PATTERN => todo!(),
-        SyntaxKind::NODE_PAT_BIND => todo!(),
-        SyntaxKind::NODE_PAT_ENTRY => todo!(),
-        SyntaxKind::NODE_ROOT => todo!(),
-        SyntaxKind::NODE_ATTR_SET => todo!(),
-        SyntaxKind::NODE_ATTRPATH_VALUE => todo!(),
-        SyntaxKind::NODE_UNARY_OP => todo!(),
-        SyntaxKind::NODE_LITERAL => todo!(),
-        SyntaxKind::NODE_WITH => todo!(),
-        SyntaxKind::NODE_PATH => todo!(),
-        SyntaxKind::NODE_HAS_ATTR => todo!(),
-        _ => todo!(),
+        SyntaxKind::TOKEN_ASSIGN => "=",
+        SyntaxKind::TOKEN_AT => "@",
+        SyntaxKind::TOKEN_COLON => ":",
+        SyntaxKind::TOKEN_COMMA => ",",
+        SyntaxKind::TOKEN_DOT => ".",
+        SyntaxKind::TOKEN_QUESTION => "?",
+        SyntaxKind::TOKEN_CONCAT => "++",
+        SyntaxKind::TOKEN_INVERT => "!",
+        SyntaxKind::TOKEN_UPDATE => "//",
+        SyntaxKind::TOKEN_ADD => "+",
+        SyntaxKind::TOKEN_SUB => "-",
+        SyntaxKind::TOKEN_MUL => "*",
+        SyntaxKind::TOKEN_DIV => "/",
+        SyntaxKind::TOKEN_AND_AND => "&&",
+        SyntaxKind::TOKEN_EQUAL => "==",
+        SyntaxKind::TOKEN_IMPLICATION => "->",
+        SyntaxKind::TOKEN_LESS => "<",
+        SyntaxKind::TOKEN_LESS_OR_EQ => "<=",
+        SyntaxKind::TOKEN_MORE => ">",
+        SyntaxKind::TOKEN_MORE_OR_EQ => ">=",
+        SyntaxKind::TOKEN_NOT_EQUAL => "!=",
+        SyntaxKind::TOKEN_OR_OR => "||",
+
+        SyntaxKind::NODE_APPLY => "<apply>",
+        SyntaxKind::NODE_ASSERT => "<assert>",
+        SyntaxKind::NODE_ATTRPATH => "<attrpath>",
+        SyntaxKind::NODE_DYNAMIC => "<dynamic>",
+        SyntaxKind::NODE_ERROR => "<error>",
+        SyntaxKind::NODE_IDENT => "<identifier>",
+        SyntaxKind::NODE_IF_ELSE => "<if-else>",
+        SyntaxKind::NODE_SELECT => "<select>",
+        SyntaxKind::NODE_INHERIT => "<inherit>",
+        SyntaxKind::NODE_INHERIT_FROM => "<inherit-from>",
+        SyntaxKind::NODE_STRING => "<string>",
+        SyntaxKind::NODE_INTERPOL => "<interpolation>",
+        SyntaxKind::NODE_LAMBDA => "<lambda>",
+        SyntaxKind::NODE_IDENT_PARAM => "<identifier>",
+        SyntaxKind::NODE_LEGACY_LET => "<legacy-let>",
+        SyntaxKind::NODE_LET_IN => "<let-in>",
+        SyntaxKind::NODE_LIST => "<list>",
+        SyntaxKind::NODE_BIN_OP => "<binary-operation>",
+        SyntaxKind::NODE_PAREN => "<parenthesis>",
+        SyntaxKind::NODE_PATTERN => "<pattern>",
+        SyntaxKind::NODE_PAT_BIND => "<pattern-bind>",
+        SyntaxKind::NODE_PAT_ENTRY => "<pattern-entry>",
+        SyntaxKind::NODE_ROOT => "<root>",
+        SyntaxKind::NODE_ATTR_SET => "<attrset>",
+        SyntaxKind::NODE_ATTRPATH_VALUE => "<attrpath-value>",
+        SyntaxKind::NODE_UNARY_OP => "<unary-operation>",
+        SyntaxKind::NODE_LITERAL => "<literal>",
+        SyntaxKind::NODE_WITH => "<with>",
+        SyntaxKind::NODE_PATH => "<path>",
+        SyntaxKind::NODE_HAS_ATTR => "<has-attr>",
+        _ => "<unknown>",
     }
 }
 
@@ -7,7 +7,9 @@ use thiserror::Error;
 
 use crate::FileScope;
 
-use super::{print_labels, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixSpan};
+use super::{
+    print_labels, NixError, NixErrorKind, NixLabel, NixLabelKind, NixLabelMessage, NixSpan,
+};
 
 pub static BACKTRACE_ENV: LazyLock<BacktraceEnv> = LazyLock::new(|| {
     std::env::var("NIX_BACKTRACE")
@@ -123,6 +125,7 @@ impl NixBacktrace {
             message: message.to_string(),
             labels: vec![label],
             backtrace,
+            kind: NixErrorKind::Eval,
         }
     }
 
@@ -131,6 +134,7 @@ impl NixBacktrace {
             labels,
             message: message.to_string(),
             backtrace: Some(self.clone()).into(),
+            kind: NixErrorKind::Eval,
         }
     }
 
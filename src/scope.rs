@@ -1,16 +1,22 @@
+mod constants;
 mod file;
+pub mod import_graph;
 
 use std::ffi::OsStr;
 use std::path::Path;
 use std::rc::Rc;
 
-use rnix::ast;
+use rnix::{ast, TextRange, TextSize};
+use rowan::ast::AstNode;
 
+pub use constants::Constants;
 pub use file::FileScope;
 
 use crate::result::{NixLabel, NixLabelKind, NixLabelMessage, NixSpan};
+use crate::value::NixString;
 use crate::{
-    builtins, flake, NixAttrSet, NixBacktrace, NixResult, NixValue, NixValueWrapped, NixVar,
+    builtins, flake, LazyNixValue, NixAttrSet, NixBacktrace, NixBacktraceKind, NixError, NixResult,
+    NixValue, NixValueWrapped, NixVar,
 };
 
 #[derive(Debug)]
@@ -18,6 +24,8 @@ pub struct Scope {
     pub backtrace: Option<NixBacktrace>,
     pub file: Rc<FileScope>,
     pub variables: NixValueWrapped,
+    /// Set of a `with`, only looked up when no lexical binding has the name
+    pub with_namespace: Option<NixValueWrapped>,
     pub parent: Option<Rc<Scope>>,
 }
 
@@ -30,10 +38,24 @@ impl Scope {
         }
 
         let mut globals = NixAttrSet::new();
-        let builtins = builtins::get_builtins();
+        let mut builtins = builtins::get_builtins();
+
+        if let Some(builtins) = builtins.as_attr_set_mut() {
+            let span = NixSpan::from_text_range(
+                &file_scope,
+                TextRange::up_to(TextSize::of(file_scope.content.as_str())),
+            );
+            let backtrace = NixBacktrace(Rc::new(span), None.into(), NixBacktraceKind::None);
+
+            builtins.insert(
+                "currentTime".to_owned(),
+                LazyNixValue::new_eval(backtrace, Box::new(builtins::current_time)).wrap_var(),
+            );
+        }
 
         insert!(globals; abort = builtins::Abort::generate());
         insert!(globals; baseNameOf = builtins::BaseNameOf::generate());
+        insert!(globals; derivation = builtins::Derivation::generate());
         insert!(globals; false = NixValue::Bool(false));
         insert!(globals; import = builtins::Import::generate());
         insert!(globals; map = builtins::Map::generate());
@@ -47,6 +69,7 @@ impl Scope {
         let parent = Rc::new(Scope {
             file: file_scope.clone(),
             variables: NixValue::AttrSet(globals).wrap(),
+            with_namespace: None,
             parent: None,
             backtrace: None,
         });
@@ -54,6 +77,7 @@ impl Scope {
         Rc::new(Self {
             file: file_scope,
             variables: NixValue::AttrSet(NixAttrSet::new()).wrap(),
+            with_namespace: None,
             parent: Some(parent),
             backtrace: None,
         })
@@ -63,15 +87,18 @@ impl Scope {
         Rc::new(Scope {
             file: self.file.clone(),
             variables: NixValue::AttrSet(NixAttrSet::new()).wrap(),
+            with_namespace: None,
             parent: Some(self),
             backtrace: None,
         })
     }
 
-    pub fn new_child_from(self: Rc<Self>, variables: NixValueWrapped) -> Rc<Scope> {
+    /// Scope of the body of `with namespace; body`
+    pub fn new_with(self: Rc<Self>, namespace: NixValueWrapped) -> Rc<Scope> {
         Rc::new(Scope {
             file: self.file.clone(),
-            variables,
+            variables: NixValue::AttrSet(NixAttrSet::new()).wrap(),
+            with_namespace: Some(namespace),
             parent: Some(self),
             backtrace: None,
         })
@@ -85,24 +112,47 @@ impl Scope {
             .insert(varname, value)
     }
 
+    /// Any lexical binding (`let`, `rec`, arguments) beats every `with`, then
+    /// the innermost `with` wins, like in Nix
     pub fn get_variable(self: &Rc<Self>, varname: String) -> Option<NixVar> {
+        self.get_lexical_variable(&varname)
+            .or_else(|| self.get_with_variable(&varname))
+    }
+
+    fn get_lexical_variable(&self, varname: &str) -> Option<NixVar> {
         self.variables
             .borrow()
             .as_attr_set()
             .unwrap()
-            .get(&varname)
+            .get(varname)
             .cloned()
             .or_else(|| {
                 self.parent
                     .as_ref()
-                    .and_then(|parent| parent.get_variable(varname))
+                    .and_then(|parent| parent.get_lexical_variable(varname))
+            })
+    }
+
+    fn get_with_variable(&self, varname: &str) -> Option<NixVar> {
+        self.with_namespace
+            .as_ref()
+            .and_then(|namespace| namespace.borrow().as_attr_set()?.get(varname).cloned())
+            .or_else(|| {
+                self.parent
+                    .as_ref()
+                    .and_then(|parent| parent.get_with_variable(varname))
             })
     }
 
+    /// Whether a `with` encloses this scope, for the errors of missing variables
+    pub fn in_with(&self) -> bool {
+        self.with_namespace.is_some() || self.parent.as_ref().is_some_and(|parent| parent.in_with())
+    }
+
     pub fn import_path(backtrace: &NixBacktrace, path: impl AsRef<Path>) -> NixResult {
         let path = path.as_ref();
 
-        println!("Importing {path:#?}");
+        eprintln!("Importing {path:#?}");
 
         let (backtrace, result) = FileScope::get_file(Some(backtrace.clone()), path)?;
 
@@ -124,9 +174,23 @@ impl Scope {
             let attr = self.resolve_attr(backtrace, &attr_node)?;
 
             let value = value.resolve(backtrace)?;
-            let set_value = match value.borrow().get(backtrace, &attr) {
-                Ok(v) => v,
-                Err(e) => return Ok(Err(e)),
+
+            // Both are recoverable, `or` gives its default for them
+            let set_value = match &*value.borrow() {
+                NixValue::AttrSet(set) => set.get(&attr).cloned(),
+                value => {
+                    return Ok(Err(backtrace.to_labeled_error(
+                        vec![NixLabel::new(
+                            NixSpan::from_ast_node(&self.file, &attr_node).into(),
+                            NixLabelMessage::Empty,
+                            NixLabelKind::Error,
+                        )],
+                        format!(
+                            "expected a set but found {} while selecting '{attr}'",
+                            value.as_type()
+                        ),
+                    )))
+                }
             };
 
             let Some(set_value) = set_value else {
@@ -146,60 +210,216 @@ impl Scope {
         }
     }
 
-    pub fn resolve_attr_set_path<'a>(
+    /// Walk `attr_path` of a definition (`a.b.c = ...`) creating the missing
+    /// sets. `path` gets the names walked, an attribute already defined as
+    /// something else than a set is an error
+    pub fn resolve_attr_set_path(
         self: &Rc<Self>,
         backtrace: &NixBacktrace,
-        value: NixValueWrapped,
-        mut attr_path: impl Iterator<Item = ast::Attr>,
-    ) -> NixResult<NixResult<NixValueWrapped>> {
-        if let Some(attr) = attr_path.next() {
-            let attr = self.resolve_attr(backtrace, &attr)?;
-
-            let set_value = match value.borrow().get(backtrace, &attr) {
-                Ok(v) => v,
-                Err(e) => return Ok(Err(e)),
+        mut value: NixValueWrapped,
+        attr_path: impl Iterator<Item = ast::Attr>,
+        path: &mut Vec<String>,
+    ) -> NixResult<Option<NixValueWrapped>> {
+        for attr_node in attr_path {
+            let Some(attr) = self.resolve_attr_key(backtrace, &attr_node)? else {
+                return Ok(None);
             };
 
-            let Some(set_value) = set_value else {
+            path.push(attr.clone());
+
+            let existing = value.borrow().as_attr_set().unwrap().get(&attr).cloned();
+
+            let Some(existing) = existing else {
                 // If `value` doesn't have `attr`, then create it
                 // as empty `AttrSet`
-                let (last, _) = value
+                let set = NixValue::AttrSet(NixAttrSet::new()).wrap();
+                let position = NixSpan::from_ast_node(&self.file, &attr_node).into();
+
+                value
                     .borrow_mut()
-                    .insert(attr, NixValue::AttrSet(NixAttrSet::new()).wrap_var())
-                    .unwrap();
+                    .as_attr_set_mut()
+                    .unwrap()
+                    .insert_with_position(
+                        attr,
+                        LazyNixValue::Concrete(set.clone()).wrap_var(),
+                        position,
+                    );
 
-                return self.resolve_attr_set_path(backtrace, last.resolve(backtrace)?, attr_path);
+                value = set;
+                continue;
             };
 
-            let set_value = set_value.resolve(backtrace)?;
+            let Some(set) = Self::mergeable_attr_set(backtrace, &existing)? else {
+                let previous = value
+                    .borrow()
+                    .as_attr_set()
+                    .unwrap()
+                    .position(&attr)
+                    .cloned();
 
-            if !set_value.borrow().is_attr_set() {
-                todo!("Error handling for {:#}", set_value.borrow());
+                return Err(self.duplicate_attr_error(backtrace, path, &attr_node, previous));
             };
 
-            self.resolve_attr_set_path(backtrace, set_value, attr_path)
+            value = set;
+        }
+
+        Ok(Some(value))
+    }
+
+    /// Sets written in place (`a = { ... };` or made by `a.b = ...;`) can get
+    /// more attributes from other definitions, like Nix does while parsing
+    pub fn mergeable_attr_set(
+        backtrace: &NixBacktrace,
+        var: &NixVar,
+    ) -> NixResult<Option<NixValueWrapped>> {
+        let is_literal = match &*var.0.borrow() {
+            LazyNixValue::Concrete(value) => {
+                return Ok(value.borrow().is_attr_set().then(|| value.clone()))
+            }
+            LazyNixValue::Pending(_, _, ast::Expr::AttrSet(_)) => true,
+            _ => false,
+        };
+
+        if is_literal {
+            var.resolve(backtrace).map(Some)
         } else {
-            Ok(Ok(value))
+            Ok(None)
         }
     }
 
+    pub fn duplicate_attr_error(
+        &self,
+        backtrace: &NixBacktrace,
+        path: &[String],
+        attr_node: &ast::Attr,
+        previous: Option<Rc<NixSpan>>,
+    ) -> NixError {
+        let path = path.join(".");
+        let mut labels = vec![NixLabel::new(
+            NixSpan::from_ast_node(&self.file, attr_node).into(),
+            NixLabelMessage::Empty,
+            NixLabelKind::Error,
+        )];
+
+        let message = match previous {
+            Some(previous) => {
+                let message = format!(
+                    "attribute '{path}' already defined at {}",
+                    previous.location()
+                );
+
+                labels.push(NixLabel::new(
+                    previous,
+                    NixLabelMessage::Custom("First defined here".to_owned()),
+                    NixLabelKind::Help,
+                ));
+
+                message
+            }
+            None => format!("attribute '{path}' already defined"),
+        };
+
+        backtrace.to_labeled_error(labels, message)
+    }
+
     pub fn resolve_attr(
         self: &Rc<Self>,
         backtrace: &NixBacktrace,
         attr: &ast::Attr,
     ) -> NixResult<String> {
         match attr {
-            ast::Attr::Ident(ident) => Ok(ident.ident_token().unwrap().text().to_owned()),
-            ast::Attr::Dynamic(dynamic) => Ok(self
-                .visit_expr(backtrace, dynamic.expr().unwrap())?
-                .resolve(backtrace)?
-                .borrow()
-                .cast_to_string()
-                .expect("Cannot cast as string")),
-            ast::Attr::Str(str) => self
-                .visit_str(backtrace, str.clone())
+            // `or` is a contextual keyword and may not be tagged as an ident token
+            ast::Attr::Ident(ident) => Ok(ident.syntax().text().to_string()),
+            ast::Attr::Dynamic(dynamic) => self
+                .resolve_dynamic_attr(backtrace, dynamic)?
+                .ok_or_else(|| {
+                    backtrace.to_labeled_error(
+                        vec![NixLabel::new(
+                            NixSpan::from_ast_node(&self.file, dynamic).into(),
+                            NixLabelMessage::Custom("This is null".to_owned()),
+                            NixLabelKind::Error,
+                        )],
+                        "Value is null while a string was expected",
+                    )
+                }),
+            ast::Attr::Str(str) => {
                 // visit_str always returns a string concrete
-                .map(|v| v.as_concrete().unwrap().borrow().cast_to_string().unwrap()),
+                let value = self
+                    .visit_str(backtrace, str.clone())?
+                    .as_concrete()
+                    .unwrap();
+                let value = value.borrow();
+                let NixValue::String(name) = &*value else {
+                    unreachable!("visit_str returns a string");
+                };
+
+                self.attr_name_without_context(backtrace, str, name)
+            }
+        }
+    }
+
+    /// Name of an attribute being defined, `${null}` keys are skipped so they give `None`
+    pub fn resolve_attr_key(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        attr: &ast::Attr,
+    ) -> NixResult<Option<String>> {
+        match attr {
+            ast::Attr::Dynamic(dynamic) => self.resolve_dynamic_attr(backtrace, dynamic),
+            attr => self.resolve_attr(backtrace, attr).map(Some),
+        }
+    }
+
+    fn resolve_dynamic_attr(
+        self: &Rc<Self>,
+        backtrace: &NixBacktrace,
+        dynamic: &ast::Dynamic,
+    ) -> NixResult<Option<String>> {
+        let value = self
+            .visit_expr(backtrace, dynamic.expr().unwrap())?
+            .resolve(backtrace)?;
+        let value = value.borrow();
+
+        match &*value {
+            NixValue::Null => Ok(None),
+            NixValue::String(name) => self
+                .attr_name_without_context(backtrace, dynamic, name)
+                .map(Some),
+            value => Err(backtrace.to_labeled_error(
+                vec![NixLabel::new(
+                    NixSpan::from_ast_node(&self.file, dynamic).into(),
+                    NixLabelMessage::Empty,
+                    NixLabelKind::Error,
+                )],
+                format!(
+                    "expected a string as attribute name but found {}",
+                    value.as_type()
+                ),
+            )),
         }
     }
+
+    /// Attribute names can't refer to store paths, like `forceStringNoCtx` in Nix
+    fn attr_name_without_context(
+        &self,
+        backtrace: &NixBacktrace,
+        node: &impl AstNode,
+        name: &NixString,
+    ) -> NixResult<String> {
+        let Some(elem) = name.context().first() else {
+            return Ok(name.to_string());
+        };
+
+        Err(backtrace.to_labeled_error(
+            vec![NixLabel::new(
+                NixSpan::from_ast_node(&self.file, node).into(),
+                NixLabelMessage::Empty,
+                NixLabelKind::Error,
+            )],
+            format!(
+                "the string '{name}' is not allowed to refer to a store path (such as '{}')",
+                elem.path()
+            ),
+        ))
+    }
 }
@@ -0,0 +1,143 @@
+//! Closed literal expressions, evaluated once per file instead of on every visit
+//!
+//! Lambda bodies and default values are visited again on each call, so a
+//! constant list or attrset inside a hot function would be rebuilt every time.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+use rnix::ast::{self, HasEntry};
+use rnix::{SyntaxKind, SyntaxNode, TextRange};
+use rowan::ast::AstNode;
+
+use crate::value::NixList;
+use crate::{NixAttrSet, NixBacktrace, NixResult, NixValue, NixVar};
+
+type NodeKey = (SyntaxKind, TextRange);
+
+#[derive(Default)]
+pub struct Constants {
+    /// Outermost constant expressions, the ones inside them are never visited
+    nodes: RefCell<HashSet<NodeKey>>,
+    values: RefCell<HashMap<NodeKey, NixVar>>,
+}
+
+fn key(node: &SyntaxNode) -> NodeKey {
+    (node.kind(), node.text_range())
+}
+
+fn is_literal_str(node: &ast::Str) -> bool {
+    node.parts()
+        .all(|part| matches!(part, ast::InterpolPart::Literal(_)))
+}
+
+fn is_constant(node: &ast::Expr) -> bool {
+    match node {
+        ast::Expr::Literal(node) => !matches!(node.kind(), ast::LiteralKind::Uri(_)),
+        ast::Expr::Str(node) => is_literal_str(node),
+        ast::Expr::Paren(node) => node.expr().is_some_and(|expr| is_constant(&expr)),
+        ast::Expr::List(node) => node.items().all(|item| is_constant(&item)),
+        // Only plain `name = value;` entries, `rec`, `inherit` and nested paths
+        // depend on the scope or on each other
+        ast::Expr::AttrSet(node) => {
+            node.rec_token().is_none()
+                && node.entries().all(|entry| {
+                    let ast::Entry::AttrpathValue(entry) = entry else {
+                        return false;
+                    };
+
+                    let mut attrs = entry.attrpath().into_iter().flat_map(|path| path.attrs());
+
+                    matches!(
+                        (attrs.next(), attrs.next()),
+                        (Some(ast::Attr::Ident(_)), None)
+                    ) && entry.value().is_some_and(|value| is_constant(&value))
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Copy the attrsets of a memoized value, they can still be extended in place
+/// (`{ a = { }; a.b = 1; }`) and each evaluation must get its own
+fn fresh_copy(var: &NixVar) -> NixVar {
+    let Some(value) = var.as_concrete() else {
+        return var.clone();
+    };
+
+    let value = value.borrow();
+
+    match &*value {
+        NixValue::AttrSet(set) => {
+            let mut copy = NixAttrSet::new();
+
+            for (name, value) in set.iter() {
+                let value = fresh_copy(value);
+
+                match set.position(name) {
+                    Some(position) => {
+                        copy.insert_with_position(name.clone(), value, position.clone())
+                    }
+                    None => copy.insert(name.clone(), value),
+                };
+            }
+
+            NixValue::AttrSet(copy).wrap_var()
+        }
+        NixValue::List(list) => {
+            let items = list.0.iter().map(fresh_copy).collect::<Vec<_>>();
+
+            if items.iter().zip(list.0.iter()).all(|(a, b)| a == b) {
+                var.clone()
+            } else {
+                NixValue::List(NixList(Rc::new(items))).wrap_var()
+            }
+        }
+        _ => var.clone(),
+    }
+}
+
+impl Constants {
+    /// Find the outermost constant expressions of the file
+    pub fn analyze(&self, root: &ast::Root) {
+        let mut nodes = self.nodes.borrow_mut();
+        let mut pending = vec![root.syntax().clone()];
+
+        while let Some(node) = pending.pop() {
+            if ast::Expr::cast(node.clone()).is_some_and(|expr| is_constant(&expr)) {
+                nodes.insert(key(&node));
+            } else {
+                pending.extend(node.children());
+            }
+        }
+    }
+
+    /// Serve `node` from the table if it's constant, otherwise just `eval` it
+    pub fn memoize(
+        &self,
+        backtrace: &NixBacktrace,
+        node: &SyntaxNode,
+        eval: impl FnOnce() -> NixResult<NixVar>,
+    ) -> NixResult<NixVar> {
+        let key = key(node);
+
+        if !self.nodes.borrow().contains(&key) {
+            return eval();
+        }
+
+        if let Some(value) = self.values.borrow().get(&key) {
+            return Ok(fresh_copy(value));
+        }
+
+        let value = eval()?;
+
+        // Literals cannot fail, resolving them now keeps the scope of this
+        // visit out of the table
+        value.resolve_set(true, backtrace)?;
+
+        self.values.borrow_mut().insert(key, fresh_copy(&value));
+
+        Ok(value)
+    }
+}
@@ -1,27 +1,39 @@
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::rc::Rc;
 use std::{fmt, fs};
 
+use crate::impurities;
 use crate::{
     LazyNixValue, NixBacktrace, NixBacktraceKind, NixError, NixResult, NixSpan, NixValueWrapped,
     NixVar,
 };
 
-use super::Scope;
+use super::constants::Constants;
+use super::{import_graph, Scope};
 
 thread_local! {
     static FILE_CACHE: RefCell<HashMap<PathBuf, (Rc<NixSpan>, NixVar)>> = HashMap::new().into();
 }
 
-#[derive(PartialEq, Eq)]
 pub struct FileScope {
     pub path: PathBuf,
     pub content: String,
+    pub constants: Constants,
+    /// URL literals are deprecated, the warning is only printed once per file
+    pub warned_uri: Cell<bool>,
 }
 
+impl PartialEq for FileScope {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path && self.content == other.content
+    }
+}
+
+impl Eq for FileScope {}
+
 impl fmt::Debug for FileScope {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FileScope")
@@ -51,6 +63,11 @@ impl FileScope {
             let (backtrace, out) = {
                 let backtrace = backtrace.into();
 
+                if let Some(importer) = backtrace.as_ref() {
+                    import_graph::record(importer.0.clone(), &path);
+                    impurities::record_path(importer, &path);
+                }
+
                 let mut file_cache = file_cache.borrow_mut();
 
                 let entry = file_cache.entry(path);
@@ -68,6 +85,8 @@ impl FileScope {
                         let (backtrace, span, out) = Rc::new(FileScope {
                             content: fs::read_to_string(&path).unwrap(),
                             path,
+                            constants: Constants::default(),
+                            warned_uri: Cell::default(),
                         })
                         .raw_evaluate(backtrace)?;
 
@@ -85,9 +104,14 @@ impl FileScope {
     }
 
     pub fn repl_file(path: PathBuf, content: String) -> NixResult<(NixBacktrace, NixValueWrapped)> {
-        Rc::new(FileScope { path, content })
-            .raw_evaluate(None.into())
-            .and_then(|r| Ok((r.0.clone(), r.2.resolve(&r.0)?)))
+        Rc::new(FileScope {
+            path,
+            content,
+            constants: Constants::default(),
+            warned_uri: Cell::default(),
+        })
+        .raw_evaluate(None.into())
+        .and_then(|r| Ok((r.0.clone(), r.2.resolve(&r.0)?)))
     }
 
     fn raw_evaluate(
@@ -98,6 +122,8 @@ impl FileScope {
             .ok()
             .map_err(|error| NixError::from_parse_error(&self, error))?;
 
+        self.constants.analyze(&root);
+
         let span = Rc::new(NixSpan::from_ast_node(&self, &root));
         let backtrace = NixBacktrace(span.clone(), backtrace, NixBacktraceKind::File);
 
@@ -0,0 +1,90 @@
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fs, io};
+
+use crate::check::write_json_string;
+use crate::NixSpan;
+
+thread_local! {
+    /// `None` until `--import-graph` enables the recording
+    static IMPORT_GRAPH: RefCell<Option<Vec<ImportEdge>>> = const { RefCell::new(None) };
+}
+
+pub struct ImportEdge {
+    pub importer: Rc<NixSpan>,
+    pub imported: PathBuf,
+}
+
+pub fn enable() {
+    IMPORT_GRAPH.with_borrow_mut(|graph| *graph = Some(Vec::new()));
+}
+
+pub fn record(importer: Rc<NixSpan>, imported: &Path) {
+    IMPORT_GRAPH.with_borrow_mut(|graph| {
+        if let Some(graph) = graph {
+            graph.push(ImportEdge {
+                importer,
+                imported: imported.to_path_buf(),
+            });
+        }
+    });
+}
+
+pub fn to_dot(edges: &[ImportEdge]) -> String {
+    let mut out = String::from("digraph imports {\n");
+
+    for edge in edges {
+        let _ = writeln!(
+            out,
+            "  {:?} -> {:?} [label=\"{}:{}\"];",
+            edge.importer.file.path.display().to_string(),
+            edge.imported.display().to_string(),
+            edge.importer.start.0,
+            edge.importer.start.1 + 1,
+        );
+    }
+
+    out.push_str("}\n");
+    out
+}
+
+pub fn to_json(edges: &[ImportEdge]) -> String {
+    let mut out = String::from("[");
+
+    for (idx, edge) in edges.iter().enumerate() {
+        if idx > 0 {
+            out.push(',');
+        }
+
+        out.push_str("{\"from\":");
+        write_json_string(&mut out, &edge.importer.file.path.display().to_string());
+        let _ = write!(
+            out,
+            ",\"line\":{},\"column\":{},\"to\":",
+            edge.importer.start.0,
+            edge.importer.start.1 + 1,
+        );
+        write_json_string(&mut out, &edge.imported.display().to_string());
+        out.push('}');
+    }
+
+    out.push_str("]\n");
+    out
+}
+
+/// Write the recorded graph, as JSON if `path` ends with `.json` and as DOT otherwise
+pub fn write(path: &Path) -> io::Result<()> {
+    let content = IMPORT_GRAPH.with_borrow(|graph| {
+        let edges = graph.as_deref().unwrap_or_default();
+
+        if path.extension().is_some_and(|ext| ext == "json") {
+            to_json(edges)
+        } else {
+            to_dot(edges)
+        }
+    });
+
+    fs::write(path, content)
+}
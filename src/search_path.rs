@@ -0,0 +1,144 @@
+//! The search path of `<nixpkgs>` lookups, from `-I` and `NIX_PATH`
+//!
+//! https://nix.dev/manual/nix/2.24/command-ref/env-common#env-NIX_PATH
+
+use std::cell::RefCell;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::value::NixList;
+use crate::{NixAttrSet, NixValue};
+
+thread_local! {
+    static SEARCH_PATH: RefCell<Vec<SearchPathEntry>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchPathEntry {
+    /// Empty for bare directories, which match any lookup
+    pub prefix: String,
+    pub path: PathBuf,
+}
+
+impl SearchPathEntry {
+    /// `prefix=path` or a bare `path`, remote entries aren't supported
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let (prefix, path) = entry.split_once('=').unwrap_or(("", entry));
+
+        if path.starts_with("channel:") || path.starts_with("flake:") || path.contains("://") {
+            return Err(format!(
+                "unsupported search path entry '{entry}', only local paths are supported"
+            ));
+        }
+
+        // Relative entries are relative to the working directory
+        let path = env::current_dir()
+            .map(|cwd| cwd.join(path).components().collect())
+            .unwrap_or_else(|_| PathBuf::from(path));
+
+        Ok(SearchPathEntry {
+            prefix: prefix.trim_end_matches('/').to_owned(),
+            path,
+        })
+    }
+
+    /// Where `<lookup>` would be under this entry, if the prefix matches
+    fn resolve(&self, lookup: &str) -> Option<PathBuf> {
+        if self.prefix.is_empty() {
+            return Some(self.path.join(lookup));
+        }
+
+        let rest = lookup.strip_prefix(&self.prefix)?;
+
+        if rest.is_empty() {
+            Some(self.path.clone())
+        } else {
+            rest.strip_prefix('/').map(|rest| self.path.join(rest))
+        }
+    }
+}
+
+/// Split `NIX_PATH` at colons, except the ones of `channel:`, `flake:` and URLs
+fn split_nix_path(nix_path: &str) -> Vec<String> {
+    let mut entries = vec![];
+    let mut current = String::new();
+    let mut chars = nix_path.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != ':' {
+            current.push(c);
+            continue;
+        }
+
+        let value = current.rsplit('=').next().unwrap_or_default();
+        let is_scheme = matches!(value, "channel" | "flake")
+            || (chars.peek() == Some(&'/')
+                && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '+'));
+
+        if is_scheme {
+            current.push(c);
+        } else if !current.is_empty() {
+            entries.push(std::mem::take(&mut current));
+        }
+    }
+
+    if !current.is_empty() {
+        entries.push(current);
+    }
+
+    entries
+}
+
+/// `-I` entries go first in order, then the ones of `NIX_PATH`
+pub fn init(includes: &[String]) {
+    let nix_path = env::var("NIX_PATH").unwrap_or_default();
+
+    let entries = includes
+        .iter()
+        .cloned()
+        .chain(split_nix_path(&nix_path))
+        .filter_map(|entry| {
+            SearchPathEntry::parse(&entry)
+                .inspect_err(|err| eprintln!("warning: {err}"))
+                .ok()
+        })
+        .collect();
+
+    SEARCH_PATH.with_borrow_mut(|search_path| *search_path = entries);
+}
+
+/// First existing match of `<lookup>`
+pub fn find_file(lookup: &str) -> Option<PathBuf> {
+    SEARCH_PATH.with_borrow(|search_path| {
+        search_path
+            .iter()
+            .filter_map(|entry| entry.resolve(lookup))
+            .find(|path| Path::exists(path))
+    })
+}
+
+/// `builtins.nixPath`, the effective entries in lookup order
+pub fn to_value() -> NixValue {
+    let entries = SEARCH_PATH.with_borrow(|search_path| {
+        search_path
+            .iter()
+            .map(|entry| {
+                let mut set = NixAttrSet::new();
+
+                set.insert(
+                    "path".to_owned(),
+                    NixValue::String(entry.path.display().to_string().into()).wrap_var(),
+                );
+                set.insert(
+                    "prefix".to_owned(),
+                    NixValue::String(entry.prefix.as_str().into()).wrap_var(),
+                );
+
+                NixValue::AttrSet(set).wrap_var()
+            })
+            .collect()
+    });
+
+    NixValue::List(NixList(Rc::new(entries)))
+}
@@ -0,0 +1,387 @@
+//! Store path computation
+//!
+//! https://nix.dev/manual/nix/latest/protocols/store-path
+
+pub mod nar;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::builtins::hash::{self, Algorithm};
+use crate::fetchers;
+
+thread_local! {
+    static CONFIG: RefCell<StoreConfig> = RefCell::new(StoreConfig::default());
+}
+
+/// Where the store is, set once by `main`. It's part of what store paths are
+/// hashed from, so other directories give other paths.
+#[derive(Clone, Debug)]
+pub struct StoreConfig {
+    /// `--store` or `NIX_STORE_DIR`
+    pub store_dir: String,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self {
+            store_dir: String::from("/nix/store"),
+        }
+    }
+}
+
+impl StoreConfig {
+    /// The store at `store_dir`, which must be absolute like Nix requires
+    pub fn new(store_dir: &str) -> Result<Self, String> {
+        let store_dir = store_dir.trim_end_matches('/');
+
+        if !store_dir.starts_with('/') {
+            return Err(format!(
+                "store directory '{store_dir}' is not an absolute path"
+            ));
+        }
+
+        Ok(Self {
+            store_dir: store_dir.to_owned(),
+        })
+    }
+}
+
+pub fn set_config(config: StoreConfig) {
+    CONFIG.set(config);
+}
+
+pub fn store_dir() -> String {
+    CONFIG.with_borrow(|config| config.store_dir.clone())
+}
+
+/// Nix uses its own base32 alphabet (no `e`, `o`, `u` and `t`)
+const BASE32_CHARS: &[u8; 32] = b"0123456789abcdfghijklmnpqrsvwxyz";
+
+/// Encode bytes with Nix's base32 flavour (little endian, reversed)
+pub fn to_base32(bytes: &[u8]) -> String {
+    if bytes.is_empty() {
+        return String::new();
+    }
+
+    let len = (bytes.len() * 8 - 1) / 5 + 1;
+    let mut out = String::with_capacity(len);
+
+    for n in (0..len).rev() {
+        let b = n * 5;
+        let i = b / 8;
+        let j = b % 8;
+
+        let low = bytes[i] as u16 >> j;
+        let high = bytes.get(i + 1).map_or(0, |byte| (*byte as u16) << (8 - j));
+
+        out.push(BASE32_CHARS[((low | high) & 0x1f) as usize] as char);
+    }
+
+    out
+}
+
+/// Decode Nix's base32, `None` if it has characters out of the alphabet
+pub fn from_base32(s: &str) -> Option<Vec<u8>> {
+    let size = s.len() * 5 / 8;
+    let mut out = vec![0u8; size];
+
+    for (n, c) in s.bytes().rev().enumerate() {
+        let digit = BASE32_CHARS.iter().position(|b| *b == c)? as u16;
+
+        let b = n * 5;
+        let i = b / 8;
+        let j = b % 8;
+
+        let value = digit << j;
+
+        if let Some(byte) = out.get_mut(i) {
+            *byte |= value as u8;
+        }
+
+        if let Some(byte) = out.get_mut(i + 1) {
+            *byte |= (value >> 8) as u8;
+        }
+    }
+
+    Some(out)
+}
+
+/// A SHA-256 hash in any of the forms Nix accepts: hex, nix32, base64 or SRI
+/// (`sha256-<base64>`), optionally prefixed by `sha256:`
+pub fn parse_sha256(hash: &str) -> Option<Vec<u8>> {
+    hash::parse(hash, Some(Algorithm::SHA256))
+        .ok()
+        .map(|(_, digest)| digest)
+}
+
+/// Fold a hash into `size` bytes by xor-ing the overflow
+pub fn compress_hash(hash: &[u8], size: usize) -> Vec<u8> {
+    let mut out = vec![0; size];
+
+    for (idx, byte) in hash.iter().enumerate() {
+        out[idx % size] ^= byte;
+    }
+
+    out
+}
+
+/// `<store>/<hash>-<name>` for the fingerprint `<ty>:sha256:<hash>:<store>:<name>`
+pub fn make_store_path(ty: &str, hash: &str, name: &str) -> String {
+    let store_dir = store_dir();
+    let fingerprint = format!("{ty}:sha256:{hash}:{store_dir}:{name}");
+    let digest = hash::digest(Algorithm::SHA256, fingerprint.as_bytes());
+    let digest = to_base32(&compress_hash(&digest, 20));
+
+    format!("{store_dir}/{digest}-{name}")
+}
+
+/// How the contents of a fixed output are hashed, `outputHashMode`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentAddressMethod {
+    /// The file itself
+    Flat,
+    /// The NAR of the tree
+    Recursive,
+    /// The git object of the tree
+    Git,
+}
+
+impl ContentAddressMethod {
+    pub fn from_mode(mode: &str) -> Option<ContentAddressMethod> {
+        match mode {
+            "flat" => Some(ContentAddressMethod::Flat),
+            "recursive" => Some(ContentAddressMethod::Recursive),
+            "git" => Some(ContentAddressMethod::Git),
+            _ => None,
+        }
+    }
+
+    /// Written before the algorithm, like the `r:` of `r:sha256`
+    pub fn prefix(self) -> &'static str {
+        match self {
+            ContentAddressMethod::Flat => "",
+            ContentAddressMethod::Recursive => "r:",
+            ContentAddressMethod::Git => "git:",
+        }
+    }
+}
+
+/// Path of a fixed output whose contents hash to `digest`, like the files of
+/// `builtins.fetchurl`. Only a NAR SHA-256 gives the path of a source.
+pub fn make_fixed_output_path(
+    method: ContentAddressMethod,
+    algorithm: Algorithm,
+    digest: &[u8],
+    name: &str,
+) -> String {
+    if method == ContentAddressMethod::Recursive && algorithm == Algorithm::SHA256 {
+        return make_store_path("source", &hex::encode(digest), name);
+    }
+
+    let inner = format!(
+        "fixed:out:{}{}:{}:",
+        method.prefix(),
+        algorithm.name(),
+        hex::encode(digest)
+    );
+    let hash = hash::hex_digest(Algorithm::SHA256, inner.as_bytes());
+
+    make_store_path("output:out", &hash, name)
+}
+
+/// Path of a text file added with `builtins.toFile`, `references` are store paths
+pub fn make_text_path(name: &str, content: &str, references: &[String]) -> String {
+    let mut references = references.to_vec();
+    references.sort();
+    references.dedup();
+
+    let ty = references
+        .iter()
+        .fold(String::from("text"), |ty, reference| ty + ":" + reference);
+
+    let hash = hash::hex_digest(Algorithm::SHA256, content.as_bytes());
+
+    make_store_path(&ty, &hash, name)
+}
+
+/// Add the text file of `builtins.toFile`. There's no store to write to, the
+/// file is written once to the cache and reads of its store path go there.
+pub fn add_text(name: &str, content: &str, references: &[String]) -> Result<String, String> {
+    let store_path = make_text_path(name, content, references);
+
+    let Some(cache) = fetchers::cache_dir("store") else {
+        return Err(String::from(
+            "no cache directory, neither $XDG_CACHE_HOME nor $HOME are set",
+        ));
+    };
+
+    let path = cache.join(&store_path[store_dir().len() + 1..]);
+
+    if !path.is_file() {
+        let io_error = |err: std::io::Error| format!("cannot write '{}': {err}", path.display());
+
+        fs::create_dir_all(&cache).map_err(io_error)?;
+
+        // Written aside and renamed, a half written file would be taken as done
+        let tmp = path.with_extension("tmp");
+
+        fs::write(&tmp, content).map_err(io_error)?;
+        fs::rename(&tmp, &path).map_err(io_error)?;
+    }
+
+    register_origin(&store_path, &path);
+
+    Ok(store_path)
+}
+
+thread_local! {
+    /// Hashing a NAR reads the whole tree, each source is only hashed once
+    static SOURCE_PATHS: RefCell<HashMap<PathBuf, String>> = RefCell::new(HashMap::new());
+    /// Sources aren't really copied, reads of their store paths go to the originals
+    static SOURCE_ORIGINS: RefCell<HashMap<String, PathBuf>> = RefCell::new(HashMap::new());
+}
+
+/// Path a local file or directory gets when copied into the store, named after
+/// its base name and addressed by the hash of its NAR
+pub fn make_source_path(path: &Path) -> Result<String, String> {
+    if let Some(store_path) = SOURCE_PATHS.with_borrow(|paths| paths.get(path).cloned()) {
+        return Ok(store_path);
+    }
+
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    if let Err(err) = check_name(&name) {
+        return Err(format!(
+            "path '{}' cannot be copied to the store, {err}",
+            path.display()
+        ));
+    }
+
+    let nar_hash = nar::hash_path(path)
+        .map_err(|err| format!("cannot copy '{}' to the store: {err}", path.display()))?;
+
+    let store_path = add_source(path, &name, &nar_hash);
+
+    SOURCE_PATHS.with_borrow_mut(|paths| paths.insert(path.to_path_buf(), store_path.clone()));
+
+    Ok(store_path)
+}
+
+/// Store path of a tree with the SHA-256 `nar_hash`, named `name`, reads of
+/// it go to `path`
+pub fn add_source(path: &Path, name: &str, nar_hash: &[u8]) -> String {
+    let store_path = make_store_path("source", &hex::encode(nar_hash), name);
+
+    register_origin(&store_path, path);
+
+    store_path
+}
+
+/// Make reads of `store_path` go to `path`, nothing is really added to the store
+pub fn register_origin(store_path: &str, path: &Path) {
+    SOURCE_ORIGINS
+        .with_borrow_mut(|origins| origins.insert(store_path.to_owned(), path.to_path_buf()));
+}
+
+/// Where `path` is readable, sources added by `add_source` point back to
+/// their original location
+pub fn to_local_path(path: &str) -> PathBuf {
+    let store_dir = store_dir();
+
+    let Some(entry) = path
+        .strip_prefix(&store_dir)
+        .and_then(|rest| rest.strip_prefix('/'))
+    else {
+        return PathBuf::from(path);
+    };
+
+    // The store path is the entry right under the store directory, however
+    // deep that is
+    let (entry, rest) = entry.split_once('/').unwrap_or((entry, ""));
+    let store_path = format!("{store_dir}/{entry}");
+
+    SOURCE_ORIGINS.with_borrow(|origins| match origins.get(&store_path) {
+        Some(origin) => {
+            let rest = rest.trim_start_matches('/');
+
+            if rest.is_empty() {
+                origin.clone()
+            } else {
+                origin.join(rest)
+            }
+        }
+        None => PathBuf::from(path),
+    })
+}
+
+/// Store path names may only contain `[a-zA-Z0-9+\-._?=]`, cannot start with a
+/// dot and have at most 211 bytes. The error says which rule is broken, like
+/// Nix does.
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err(String::from("the name is empty"));
+    }
+
+    if name.len() > 211 {
+        return Err(format!("name '{name}' is longer than 211 characters"));
+    }
+
+    if name.starts_with('.') {
+        return Err(format!("name '{name}' cannot start with a period"));
+    }
+
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && !"+-._?=".contains(*c))
+    {
+        return Err(format!("invalid character '{c}' in name '{name}'"));
+    }
+
+    Ok(())
+}
+
+pub fn is_valid_name(name: &str) -> bool {
+    check_name(name).is_ok()
+}
+
+/// Check that `path` is `<store>/<hash>-<name>`, the error explains what's wrong
+pub fn check_store_path(path: &str) -> Result<(), String> {
+    let Some(base_name) = path
+        .strip_prefix(&store_dir())
+        .and_then(|path| path.strip_prefix('/'))
+    else {
+        return Err(format!("path '{path}' is not in the Nix store"));
+    };
+
+    // Only the store path itself, not something inside of it
+    let base_name = base_name.split('/').next().unwrap();
+
+    let Some((hash, name)) = base_name.split_once('-') else {
+        return Err(format!("path '{path}' is not a valid store path"));
+    };
+
+    if hash.len() != 32 || !hash.bytes().all(|c| BASE32_CHARS.contains(&c)) {
+        return Err(format!(
+            "path '{path}' has an invalid hash part '{hash}', expected 32 nix32 characters"
+        ));
+    }
+
+    if let Err(err) = check_name(name) {
+        return Err(format!("path '{path}' has an invalid name, {err}"));
+    }
+
+    Ok(())
+}
+
+/// Stands for the path of `output` until it's known, `/<base32 sha256("nix-output:<output>")>`
+pub fn hash_placeholder(output: &str) -> String {
+    let digest = hash::digest(Algorithm::SHA256, format!("nix-output:{output}").as_bytes());
+
+    format!("/{}", to_base32(&digest))
+}
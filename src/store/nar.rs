@@ -0,0 +1,93 @@
+//! Nix ARchive serialization, the canonical form of a path that `narHash` hashes
+//!
+//! https://nix.dev/manual/nix/latest/protocols/nix-archive
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use crate::builtins::hash::{Algorithm, Hasher};
+
+fn write_str(out: &mut impl Write, s: &[u8]) -> io::Result<()> {
+    out.write_all(&(s.len() as u64).to_le_bytes())?;
+    out.write_all(s)?;
+
+    let padding = (8 - s.len() % 8) % 8;
+    out.write_all(&[0; 8][..padding])
+}
+
+fn dump_node(path: &Path, out: &mut impl Write, keep: &impl Fn(&Path) -> bool) -> io::Result<()> {
+    let metadata = fs::symlink_metadata(path)?;
+    let file_type = metadata.file_type();
+
+    write_str(out, b"(")?;
+    write_str(out, b"type")?;
+
+    if file_type.is_symlink() {
+        let target = fs::read_link(path)?;
+
+        write_str(out, b"symlink")?;
+        write_str(out, b"target")?;
+        write_str(out, target.as_os_str().as_encoded_bytes())?;
+    } else if file_type.is_dir() {
+        write_str(out, b"directory")?;
+
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        entries.retain(|name| keep(&path.join(name)));
+
+        // Entries are sorted by their raw bytes
+        entries.sort();
+
+        for name in entries {
+            write_str(out, b"entry")?;
+            write_str(out, b"(")?;
+            write_str(out, b"name")?;
+            write_str(out, name.as_encoded_bytes())?;
+            write_str(out, b"node")?;
+            dump_node(&path.join(name), out, keep)?;
+            write_str(out, b")")?;
+        }
+    } else if file_type.is_file() {
+        write_str(out, b"regular")?;
+
+        if metadata.permissions().mode() & 0o100 != 0 {
+            write_str(out, b"executable")?;
+            write_str(out, b"")?;
+        }
+
+        write_str(out, b"contents")?;
+        write_str(out, &fs::read(path)?)?;
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("'{}' has an unsupported file type", path.display()),
+        ));
+    }
+
+    write_str(out, b")")
+}
+
+/// Serialize `path`, only the entries under it that `keep` accepts
+pub fn dump_filtered(
+    path: &Path,
+    out: &mut impl Write,
+    keep: &impl Fn(&Path) -> bool,
+) -> io::Result<()> {
+    write_str(out, b"nix-archive-1")?;
+    dump_node(path, out, keep)
+}
+
+/// SHA-256 of the NAR serialization of `path`
+pub fn hash_path(path: &Path) -> io::Result<Vec<u8>> {
+    hash_path_filtered(path, &|_| true)
+}
+
+pub fn hash_path_filtered(path: &Path, keep: &impl Fn(&Path) -> bool) -> io::Result<Vec<u8>> {
+    let mut hasher = Hasher::new(Algorithm::SHA256);
+    dump_filtered(path, &mut hasher, keep)?;
+    Ok(hasher.finish())
+}
@@ -1,21 +1,30 @@
+mod attrset;
 mod lazy;
+mod number;
+mod string;
+mod to_nix;
 mod var;
 
-use std::cell::RefCell;
-use std::collections::BTreeMap;
+use std::cell::{Cell, RefCell};
 use std::fmt::{self, Write};
 use std::ops::Deref;
 use std::path::PathBuf;
 use std::rc::Rc;
 
+pub use attrset::NixAttrSet;
 pub use lazy::LazyNixValue;
+pub use number::{NixArithOp, NixNumber};
+pub use string::{NixContextElem, NixString, NixStringContext};
+pub use to_nix::ToNixMode;
 pub use var::NixVar;
 
 use rnix::ast;
 
 use crate::builtins::NixBuiltin;
+use crate::impurities;
 use crate::scope::Scope;
-use crate::{NixBacktrace, NixError, NixResult};
+use crate::store;
+use crate::{NixBacktrace, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult, NixSpan};
 
 #[derive(Clone, PartialEq, Eq)]
 pub enum NixLambdaParam {
@@ -33,8 +42,6 @@ pub enum NixLambda {
 #[derive(Clone, PartialEq, Eq)]
 pub struct NixList(pub Rc<Vec<NixVar>>);
 
-pub type NixAttrSet = BTreeMap<String, NixVar>;
-
 /// https://nix.dev/manual/nix/2.24/language/types
 #[derive(Default)]
 pub enum NixValue {
@@ -47,7 +54,7 @@ pub enum NixValue {
     #[default]
     Null,
     Path(PathBuf),
-    String(String),
+    String(NixString),
 }
 
 pub type NixValueWrapped = Rc<RefCell<NixValue>>;
@@ -93,6 +100,11 @@ impl fmt::Debug for NixValue {
 impl fmt::Display for NixValue {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
+            NixValue::AttrSet(set) if set.resolved_drv_path().is_some() => {
+                let drv_path = set.resolved_drv_path().unwrap();
+
+                f.write_fmt(format_args!("«derivation {drv_path}»"))
+            }
             NixValue::AttrSet(set) => {
                 let width = f.width().unwrap_or_default();
                 let outside_pad = " ".repeat(width);
@@ -121,7 +133,13 @@ impl fmt::Display for NixValue {
                         f.write_char(' ')?;
                     }
 
-                    f.write_str(key)?;
+                    // `{ "" = 1; "a b" = 2; }`, names that aren't identifiers are quoted
+                    if to_nix::is_valid_ident(key) {
+                        f.write_str(key)?;
+                    } else {
+                        f.write_str(&to_nix::escape_string(key))?;
+                    }
+
                     f.write_str(" = ")?;
 
                     if f.alternate() {
@@ -209,12 +227,46 @@ impl fmt::Display for NixValue {
 }
 
 impl NixValue {
+    /// `==` on forced values, the same value is equal to itself whatever its type,
+    /// even functions (`let f = x: x; in f == f`)
+    pub fn try_eq_wrapped(
+        lhs: &NixValueWrapped,
+        rhs: &NixValueWrapped,
+        backtrace: &NixBacktrace,
+    ) -> NixResult<bool> {
+        if Rc::ptr_eq(lhs, rhs) {
+            return Ok(true);
+        }
+
+        let lhs = lhs.borrow();
+        let rhs = rhs.borrow();
+
+        lhs.try_eq(&rhs, backtrace)
+    }
+
     pub fn try_eq(&self, other: &Self, backtrace: &NixBacktrace) -> NixResult<bool> {
         match (self, other) {
             (Self::AttrSet(v1), Self::AttrSet(v2)) => {
-                // TODO: If both sets denote a derivation (type = "derivation"),
+                // If both sets denote a derivation (type = "derivation"),
                 // then compare their outPaths.
                 // https://github.com/NixOS/nix/blob/da7e3be8fc4338e9cd7bb49eac3cbcf5f0560850/src/libexpr/eval.cc#L2758-L2765
+                let denotes_derivation = |set: &NixAttrSet| -> NixResult<bool> {
+                    let Some(ty) = set.get("type") else {
+                        return Ok(false);
+                    };
+
+                    Ok(ty
+                        .resolve(backtrace)?
+                        .borrow()
+                        .as_string()
+                        .is_some_and(|ty| ty == "derivation"))
+                };
+
+                if denotes_derivation(v1)? && denotes_derivation(v2)? {
+                    if let (Some(a), Some(b)) = (v1.get("outPath"), v2.get("outPath")) {
+                        return a.try_eq(b, backtrace);
+                    }
+                }
 
                 if v1.len() != v2.len() {
                     return Ok(false);
@@ -233,9 +285,26 @@ impl NixValue {
             (Self::Float(v1), Self::Int(v2)) => Ok(*v1 == *v2 as f64),
             (Self::Int(v1), Self::Int(v2)) => Ok(v1 == v2),
             (Self::Int(v1), Self::Float(v2)) => Ok(*v1 as f64 == *v2),
-            // Functions are incomparable.
+            // Functions are incomparable, `==` is false instead of an error (Nix 2.24).
+            // The same function is only equal through the identity check of `try_eq_wrapped`,
+            // but builtins are shared values in Nix so they are equal by name
+            (Self::Lambda(NixLambda::Builtin(v1)), Self::Lambda(NixLambda::Builtin(v2))) => {
+                Ok(v1 == v2)
+            }
             (Self::Lambda(..), Self::Lambda(..)) => Ok(false),
-            (Self::List(v1), Self::List(v2)) => Ok(v1 == v2),
+            (Self::List(v1), Self::List(v2)) => {
+                if v1.0.len() != v2.0.len() {
+                    return Ok(false);
+                }
+
+                for (a, b) in v1.0.iter().zip(v2.0.iter()) {
+                    if !a.try_eq(b, backtrace)? {
+                        return Ok(false);
+                    }
+                }
+
+                Ok(true)
+            }
             (Self::Null, Self::Null) => Ok(true),
             (Self::Path(v1), Self::Path(v2)) => Ok(v1 == v2),
             (Self::String(v1), Self::String(v2)) => Ok(v1 == v2),
@@ -266,17 +335,6 @@ impl NixValue {
     }
 
     /// Returns (new_value, old_value)
-    pub fn insert(&mut self, attr: String, value: NixVar) -> Option<(NixVar, Option<NixVar>)> {
-        let NixValue::AttrSet(set) = self else {
-            todo!("Error handling");
-            // return Err(());
-        };
-
-        let old = set.insert(attr, value.clone());
-
-        Some((value, old))
-    }
-
     pub fn as_bool(&self) -> Option<bool> {
         if let NixValue::Bool(value) = self {
             Some(*value)
@@ -312,12 +370,16 @@ impl NixValue {
     pub fn as_path(&self) -> Option<PathBuf> {
         match self {
             NixValue::Path(path) => Some(path.to_path_buf()),
-            NixValue::String(string) => Some(PathBuf::from(string)),
+            NixValue::String(string) => Some(store::to_local_path(string)),
             _ => None,
         }
     }
 
     pub fn as_string(&self) -> Option<&String> {
+        self.as_nix_string().map(Deref::deref)
+    }
+
+    pub fn as_nix_string(&self) -> Option<&NixString> {
         match self {
             NixValue::String(string) => Some(string),
             _ => None,
@@ -338,6 +400,17 @@ impl NixValue {
         }
     }
 
+    /// `an int`, `a string`
+    pub fn as_type_with_article(&self) -> String {
+        let ty = self.as_type();
+
+        if ty.starts_with(['a', 'e', 'i', 'o', 'u']) {
+            format!("an {ty}")
+        } else {
+            format!("a {ty}")
+        }
+    }
+
     pub fn is_attr_set(&self) -> bool {
         matches!(self, NixValue::AttrSet(_))
     }
@@ -374,22 +447,122 @@ impl NixValue {
         self.cast_to_string().is_some()
     }
 
-    // https://nix.dev/manual/nix/2.24/language/builtins.html?highlight=abort#builtins-toString
+    /// `toString` of the values that don't need to be evaluated, sets and
+    /// lists need `coerce_to_string`
+    ///
+    /// https://nix.dev/manual/nix/2.24/language/builtins.html?highlight=abort#builtins-toString
     pub fn cast_to_string(&self) -> Option<String> {
-        // TODO: AttrSet to String
         match self {
-            NixValue::AttrSet(_) => todo!(),
             NixValue::Bool(false) => Some(String::from("")),
             NixValue::Bool(true) => Some(String::from("1")),
-            NixValue::Float(n) => Some(n.to_string()),
+            NixValue::Float(n) => Some(format_float(*n)),
             NixValue::Int(n) => Some(n.to_string()),
             NixValue::Null => Some(String::from("")),
             NixValue::Path(path) => Some(path.display().to_string()),
-            NixValue::String(str) => Some(str.clone()),
+            NixValue::String(str) => Some(str.to_string()),
             _ => None,
         }
     }
 
+    /// Nix's `coerceToString`. Strings, paths and sets with `__toString` or
+    /// `outPath` always coerce, the rest only with `coerce_more` (`toString`).
+    /// Paths are copied to the store with `copy_to_store` (interpolation) and
+    /// stay as they are otherwise.
+    pub fn coerce_to_string(
+        &self,
+        backtrace: &NixBacktrace,
+        coerce_more: bool,
+        copy_to_store: bool,
+    ) -> NixResult<NixString> {
+        match self {
+            NixValue::String(string) => Ok(string.clone()),
+            NixValue::Path(path) if copy_to_store => {
+                impurities::record_path(backtrace, path);
+
+                let store_path = store::make_source_path(path).map_err(|err| {
+                    backtrace.to_error(NixLabelKind::Error, NixLabelMessage::Empty, err)
+                })?;
+
+                let context = NixStringContext::from([NixContextElem::Opaque(store_path.clone())]);
+
+                Ok(NixString::new(store_path, context))
+            }
+            NixValue::AttrSet(set) => {
+                if let Some(to_string) = set.get("__toString") {
+                    let to_string = to_string.resolve(backtrace)?;
+                    let Some(to_string) = to_string.borrow().as_lambda().cloned() else {
+                        return Err(backtrace.to_error(
+                            NixLabelKind::Error,
+                            NixLabelMessage::Empty,
+                            "'__toString' must be a function",
+                        ));
+                    };
+
+                    let result = to_string
+                        .call(backtrace, NixValue::AttrSet(set.clone()).wrap_var())?
+                        .resolve(backtrace)?;
+                    let result = result.borrow();
+
+                    return result.coerce_to_string(backtrace, coerce_more, copy_to_store);
+                }
+
+                // Derivations are their output path
+                if let Some(out_path) = set.get("outPath") {
+                    let out_path = out_path.resolve(backtrace)?;
+                    let out_path = out_path.borrow();
+
+                    return out_path.coerce_to_string(backtrace, coerce_more, copy_to_store);
+                }
+
+                Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    "cannot coerce a set to a string",
+                ))
+            }
+            NixValue::List(list) if coerce_more => {
+                let mut out = NixString::default();
+
+                for (idx, item) in list.0.iter().enumerate() {
+                    let item = item.resolve(backtrace)?;
+                    let item = item.borrow();
+
+                    out.push(&item.coerce_to_string(backtrace, coerce_more, copy_to_store)?);
+
+                    // Empty lists don't add a separator, like in Nix
+                    let is_empty_list = item.as_list().is_some_and(|list| list.0.is_empty());
+
+                    if idx + 1 < list.0.len() && !is_empty_list {
+                        out.push(&NixString::from(" "));
+                    }
+                }
+
+                Ok(out)
+            }
+            NixValue::Lambda(_) => Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                "cannot coerce a function to a string",
+            )),
+            value => match value.cast_to_nix_string() {
+                Some(string) if coerce_more || value.is_path() => Ok(string),
+                _ => Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!("cannot coerce {} to a string", value.as_type_with_article()),
+                )),
+            },
+        }
+    }
+
+    /// Like `cast_to_string` but keeps the context of strings
+    pub fn cast_to_nix_string(&self) -> Option<NixString> {
+        match self {
+            NixValue::String(str) => Some(str.clone()),
+            value => value.cast_to_string().map(NixString::from),
+        }
+    }
+
     pub fn as_attr_set(&self) -> Option<&NixAttrSet> {
         if let NixValue::AttrSet(set) = self {
             Some(set)
@@ -410,7 +583,10 @@ impl NixValue {
 impl PartialEq for NixLambda {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (NixLambda::Apply(_, _, v1), NixLambda::Apply(_, _, v2)) => v1 == v2,
+            // The same expression closes over different scopes on each evaluation
+            (NixLambda::Apply(s1, _, v1), NixLambda::Apply(s2, _, v2)) => {
+                Rc::ptr_eq(s1, s2) && v1 == v2
+            }
             (NixLambda::Builtin(v1), NixLambda::Builtin(v2)) => v1 == v2,
             _ => false,
         }
@@ -421,6 +597,8 @@ impl NixLambda {
     pub fn call(&self, backtrace: &NixBacktrace, value: NixVar) -> NixResult<NixVar> {
         match self {
             NixLambda::Apply(scope, param, expr) => {
+                let _depth = CallDepth::enter(backtrace)?;
+
                 let scope = scope.clone().new_child();
 
                 match param {
@@ -428,10 +606,35 @@ impl NixLambda {
                         scope.set_variable(ident.clone(), value);
                     }
                     crate::NixLambdaParam::Pattern(pattern) => {
+                        let function = Rc::new(NixSpan::from_ast_node(&scope.file, pattern));
+                        let call_error = |help: NixLabel, message: String| {
+                            backtrace.to_labeled_error(
+                                vec![
+                                    NixLabel::new(
+                                        backtrace.0.clone(),
+                                        NixLabelMessage::Empty,
+                                        NixLabelKind::Error,
+                                    ),
+                                    help,
+                                ],
+                                format!("function at {} {message}", function.location()),
+                            )
+                        };
+
                         let argument_var = value.resolve(backtrace)?;
                         let argument = argument_var.borrow();
                         let Some(argument) = argument.as_attr_set() else {
-                            todo!("Error handling")
+                            return Err(call_error(
+                                NixLabel::new(
+                                    function.clone(),
+                                    NixLabelMessage::Custom("Expects a set".to_owned()),
+                                    NixLabelKind::Help,
+                                ),
+                                format!(
+                                    "called with {} but expects a set",
+                                    argument.as_type_with_article()
+                                ),
+                            ));
                         };
 
                         if let Some(pat_bind) = pattern.pat_bind() {
@@ -460,26 +663,53 @@ impl NixLambda {
 
                             if let Some(unused) = unused.as_mut() {
                                 if let Some(idx) = unused.iter().position(|&key| key == varname) {
-                                    unused.swap_remove(idx);
+                                    unused.remove(idx);
                                 }
                             }
 
                             let var = if let Some(var) = argument.get(varname).cloned() {
                                 var
                             } else if let Some(expr) = entry.default() {
-                                LazyNixValue::Pending(backtrace.clone(), scope.clone(), expr)
-                                    .wrap_var()
+                                // Evaluated in the scope of every parameter, with its own
+                                // frame so a cycle between defaults points at them
+                                LazyNixValue::Pending(
+                                    scope.new_backtrace(backtrace, &expr),
+                                    scope.clone(),
+                                    expr,
+                                )
+                                .wrap_var()
                             } else {
-                                todo!("Error handling: Require {varname}");
+                                return Err(call_error(
+                                    NixLabel::new(
+                                        NixSpan::from_ast_node(&scope.file, &entry).into(),
+                                        NixLabelMessage::Custom("Declared here".to_owned()),
+                                        NixLabelKind::Help,
+                                    ),
+                                    format!("called without required argument '{varname}'"),
+                                ));
                             };
 
                             scope.set_variable(varname.to_owned(), var.clone());
                         }
 
-                        if let Some(unused) = unused {
-                            if !unused.is_empty() {
-                                todo!("Handle error: Unused keys: {unused:?}")
-                            }
+                        if let Some(unused) = unused.filter(|unused| !unused.is_empty()) {
+                            let plural = if unused.len() == 1 { "" } else { "s" };
+                            let names = unused
+                                .iter()
+                                .map(|name| format!("'{name}'"))
+                                .collect::<Vec<_>>()
+                                .join(", ");
+
+                            return Err(call_error(
+                                NixLabel::new(
+                                    function.clone(),
+                                    NixLabelMessage::Custom(
+                                        "Add `...` to accept any argument".to_owned(),
+                                    ),
+                                    NixLabelKind::Help,
+                                ),
+                                format!("called with unexpected argument{plural} {names}"),
+                            ));
                         }
                     }
                 };
@@ -494,6 +724,50 @@ impl NixLambda {
     }
 }
 
+thread_local! {
+    static CALL_DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// A function call being evaluated, deep recursions give an error before
+/// overflowing the native stack
+struct CallDepth;
+
+impl CallDepth {
+    fn enter(backtrace: &NixBacktrace) -> NixResult<Self> {
+        let depth = CALL_DEPTH.get() + 1;
+
+        if depth > impurities::options().max_call_depth {
+            return Err(backtrace.to_error(
+                NixLabelKind::Error,
+                NixLabelMessage::Empty,
+                "stack overflow; max-call-depth exceeded",
+            ));
+        }
+
+        CALL_DEPTH.set(depth);
+
+        Ok(CallDepth)
+    }
+}
+
+impl Drop for CallDepth {
+    fn drop(&mut self) {
+        CALL_DEPTH.set(CALL_DEPTH.get() - 1);
+    }
+}
+
+/// Floats coerce like C's `%f` (`std::to_string` in Nix), `toString 1.5`
+/// is `"1.500000"`
+fn format_float(n: f64) -> String {
+    if n.is_nan() {
+        String::from(if n.is_sign_negative() { "-nan" } else { "nan" })
+    } else if n.is_infinite() {
+        String::from(if n > 0.0 { "inf" } else { "-inf" })
+    } else {
+        format!("{n:.6}")
+    }
+}
+
 impl From<NixValue> for NixVar {
     fn from(value: NixValue) -> Self {
         value.wrap_var()
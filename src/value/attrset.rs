@@ -0,0 +1,160 @@
+use std::collections::{btree_map, BTreeMap, HashMap};
+use std::fmt;
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use crate::result::NixSpan;
+use crate::{NixBacktrace, NixResult};
+
+use super::NixVar;
+
+/// Attributes of a set, with where each was defined for `unsafeGetAttrPos`
+///
+/// Derefs to the `BTreeMap` of the values. Attributes inserted without a
+/// position (builtins, sets built by builtins) don't have one.
+#[derive(Clone, Default)]
+pub struct NixAttrSet {
+    attrs: BTreeMap<String, NixVar>,
+    positions: HashMap<String, Rc<NixSpan>>,
+}
+
+impl NixAttrSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, key: String, value: NixVar) -> Option<NixVar> {
+        self.positions.remove(&key);
+        self.attrs.insert(key, value)
+    }
+
+    pub fn insert_with_position(
+        &mut self,
+        key: String,
+        value: NixVar,
+        position: Rc<NixSpan>,
+    ) -> Option<NixVar> {
+        self.positions.insert(key.clone(), position);
+        self.attrs.insert(key, value)
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<NixVar> {
+        self.positions.remove(key);
+        self.attrs.remove(key)
+    }
+
+    /// Where `key` was defined
+    pub fn position(&self, key: &str) -> Option<&Rc<NixSpan>> {
+        self.positions.get(key)
+    }
+
+    /// `type = "derivation"` with a `drvPath`, what `derivation` returns
+    pub fn is_derivation(&self, backtrace: &NixBacktrace) -> NixResult<bool> {
+        let Some(ty) = self.get("type") else {
+            return Ok(false);
+        };
+
+        let is_derivation = ty
+            .resolve(backtrace)?
+            .borrow()
+            .as_string()
+            .is_some_and(|ty| ty == "derivation");
+
+        Ok(is_derivation && self.contains_key("drvPath"))
+    }
+
+    /// `drvPath` of a derivation whose type and path are already resolved
+    pub fn resolved_drv_path(&self) -> Option<String> {
+        let ty = self.get("type")?.as_concrete()?;
+
+        if ty.borrow().as_string()? != "derivation" {
+            return None;
+        }
+
+        let drv_path = self.get("drvPath")?.as_concrete()?;
+        let drv_path = drv_path.borrow().as_string().cloned();
+
+        drv_path
+    }
+
+    /// `self // other`, keeping the positions of both
+    pub fn update(&mut self, other: &NixAttrSet) {
+        for (key, value) in &other.attrs {
+            match other.positions.get(key) {
+                Some(position) => {
+                    self.insert_with_position(key.clone(), value.clone(), position.clone())
+                }
+                None => self.insert(key.clone(), value.clone()),
+            };
+        }
+    }
+}
+
+impl Deref for NixAttrSet {
+    type Target = BTreeMap<String, NixVar>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.attrs
+    }
+}
+
+impl DerefMut for NixAttrSet {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.attrs
+    }
+}
+
+impl fmt::Debug for NixAttrSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.attrs, f)
+    }
+}
+
+impl PartialEq for NixAttrSet {
+    fn eq(&self, other: &Self) -> bool {
+        self.attrs == other.attrs
+    }
+}
+
+impl Eq for NixAttrSet {}
+
+impl<const N: usize> From<[(String, NixVar); N]> for NixAttrSet {
+    fn from(attrs: [(String, NixVar); N]) -> Self {
+        Self::from_iter(attrs)
+    }
+}
+
+impl FromIterator<(String, NixVar)> for NixAttrSet {
+    fn from_iter<T: IntoIterator<Item = (String, NixVar)>>(iter: T) -> Self {
+        Self {
+            attrs: BTreeMap::from_iter(iter),
+            positions: HashMap::new(),
+        }
+    }
+}
+
+impl Extend<(String, NixVar)> for NixAttrSet {
+    fn extend<T: IntoIterator<Item = (String, NixVar)>>(&mut self, iter: T) {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl IntoIterator for NixAttrSet {
+    type Item = (String, NixVar);
+    type IntoIter = btree_map::IntoIter<String, NixVar>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.attrs.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a NixAttrSet {
+    type Item = (&'a String, &'a NixVar);
+    type IntoIter = btree_map::Iter<'a, String, NixVar>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.attrs.iter()
+    }
+}
@@ -6,11 +6,11 @@ use std::rc::Rc;
 use rnix::ast;
 
 use crate::{
-    NixBacktrace, NixError, NixLabel, NixLabelKind, NixLabelMessage, NixResult, NixSpan,
-    NixValueWrapped, NixVar, Scope,
+    NixBacktrace, NixError, NixErrorKind, NixLabel, NixLabelKind, NixLabelMessage, NixResult,
+    NixSpan, NixValueWrapped, NixVar, Scope,
 };
 
-use super::{NixAttrSet, NixLambda, NixValue};
+use super::{NixLambda, NixValue};
 
 #[derive(Clone)]
 pub enum LazyNixValue {
@@ -29,6 +29,8 @@ pub enum LazyNixValue {
         scope: Rc<Scope>,
     },
     Resolving(NixBacktrace),
+    /// A resolve that failed, forcing it again gives the same error
+    Failed(NixError),
 }
 
 impl fmt::Debug for LazyNixValue {
@@ -37,8 +39,9 @@ impl fmt::Debug for LazyNixValue {
             LazyNixValue::Concrete(value) => fmt::Debug::fmt(value.borrow().deref(), f),
             LazyNixValue::Pending(..) => f.write_str("<not-resolved>"),
             LazyNixValue::Eval(..) => f.write_str("<not-resolved>"),
-            LazyNixValue::UpdateResolve { lhs, .. } => fmt::Debug::fmt(lhs.borrow().deref(), f),
+            LazyNixValue::UpdateResolve { .. } => f.write_str("<not-resolved>"),
             LazyNixValue::Resolving(..) => f.write_str("<resolving>"),
+            LazyNixValue::Failed(..) => f.write_str("<failed>"),
         }
     }
 }
@@ -49,8 +52,9 @@ impl fmt::Display for LazyNixValue {
             LazyNixValue::Concrete(value) => fmt::Display::fmt(value.borrow().deref(), f),
             LazyNixValue::Pending(..) => f.write_str("<not-resolved>"),
             LazyNixValue::Eval(..) => f.write_str("<not-resolved>"),
-            LazyNixValue::UpdateResolve { lhs, .. } => fmt::Display::fmt(lhs.borrow().deref(), f),
+            LazyNixValue::UpdateResolve { .. } => f.write_str("<not-resolved>"),
             LazyNixValue::Resolving(..) => f.write_str("<resolving>"),
+            LazyNixValue::Failed(..) => f.write_str("<failed>"),
         }
     }
 }
@@ -61,17 +65,16 @@ impl LazyNixValue {
         rhs: &Rc<RefCell<Self>>,
         backtrace: &NixBacktrace,
     ) -> NixResult<bool> {
-        let lhs = LazyNixValue::resolve(lhs, &backtrace)?;
-        let rhs = LazyNixValue::resolve(rhs, &backtrace)?;
+        // Both sides are forced before anything else, like Nix does
+        let lhs_value = LazyNixValue::resolve(lhs, &backtrace)?;
+        let rhs_value = LazyNixValue::resolve(rhs, &backtrace)?;
 
-        if lhs.as_ptr() == rhs.as_ptr() {
+        // The same thunk is equal to itself whatever its type
+        if Rc::ptr_eq(lhs, rhs) {
             return Ok(true);
         }
 
-        let lhs = lhs.borrow();
-        let rhs = rhs.borrow();
-
-        Ok(lhs.try_eq(&*rhs, backtrace)?)
+        NixValue::try_eq_wrapped(&lhs_value, &rhs_value, backtrace)
     }
 }
 
@@ -121,17 +124,27 @@ impl LazyNixValue {
         NixVar(Rc::new(RefCell::new(self)))
     }
 
+    /// `UpdateResolve` isn't concrete, its `lhs` is only part of the value
     pub fn as_concrete(&self) -> Option<NixValueWrapped> {
         if let LazyNixValue::Concrete(value) = self {
             Some(value.clone())
-        } else if let LazyNixValue::UpdateResolve { lhs, .. } = self {
-            Some(lhs.clone())
         } else {
             None
         }
     }
 
     pub fn resolve(this: &Rc<RefCell<Self>>, backtrace: &NixBacktrace) -> NixResult {
+        let mut value = Self::resolve_step(this, backtrace)?;
+
+        // Each step of an update chain (`a // b // c`) only merges one set
+        while matches!(&*this.borrow(), LazyNixValue::UpdateResolve { .. }) {
+            value = Self::resolve_step(this, backtrace)?;
+        }
+
+        Ok(value)
+    }
+
+    fn resolve_step(this: &Rc<RefCell<Self>>, backtrace: &NixBacktrace) -> NixResult {
         if let LazyNixValue::Concrete(value) = &*this.borrow() {
             return Ok(value.clone());
         }
@@ -141,6 +154,7 @@ impl LazyNixValue {
             LazyNixValue::Pending(ref backtrace, ..) => backtrace.clone(),
             LazyNixValue::Eval(ref backtrace, ..) => backtrace.clone(),
             LazyNixValue::UpdateResolve { ref backtrace, .. } => backtrace.clone(),
+            LazyNixValue::Failed(ref error) => return Err(error.clone()),
             LazyNixValue::Resolving(ref def_backtrace) => {
                 let label = NixLabelMessage::Empty;
                 let kind = NixLabelKind::Error;
@@ -159,14 +173,25 @@ impl LazyNixValue {
                         .to_owned(),
                     labels: vec![label, called_label],
                     backtrace: def_backtrace.clone(),
+                    kind: NixErrorKind::Eval,
                 });
             }
         };
 
         let old = this.replace(LazyNixValue::Resolving(backtrace.clone()));
 
+        // Otherwise the thunk would stay `Resolving` (or half updated) after
+        // the error is caught by `tryEval`
+        Self::resolve_old(this, old, backtrace).inspect_err(|error| {
+            *this.borrow_mut().deref_mut() = LazyNixValue::Failed(error.clone());
+        })
+    }
+
+    fn resolve_old(this: &Rc<RefCell<Self>>, old: Self, backtrace: &NixBacktrace) -> NixResult {
         match old {
-            LazyNixValue::Concrete(..) | LazyNixValue::Resolving(..) => unreachable!(),
+            LazyNixValue::Concrete(..) | LazyNixValue::Resolving(..) | LazyNixValue::Failed(..) => {
+                unreachable!()
+            }
             LazyNixValue::UpdateResolve {
                 lhs,
                 rhs,
@@ -177,12 +202,13 @@ impl LazyNixValue {
 
                 scope.visit_expr(&backtrace, rhs).and_then(|rhs| {
                     if matches!(&*rhs.0.borrow(), LazyNixValue::UpdateResolve { .. }) {
+                        // Cloned out so `rhs` isn't borrowed while `this` is updated
                         let LazyNixValue::UpdateResolve {
                             lhs: resolved_rhs,
                             rhs,
                             backtrace,
                             scope,
-                        } = &&*rhs.0.borrow()
+                        } = rhs.0.borrow().clone()
                         else {
                             unreachable!()
                         };
@@ -192,11 +218,9 @@ impl LazyNixValue {
                             .as_attr_set()
                             .ok_or_else(|| todo!("Error handling"))
                             .map(|rhs| {
-                                let lhs_set = lhs.borrow().as_attr_set().cloned().unwrap();
-                                let mut lhs = NixAttrSet::new();
+                                let mut lhs = lhs.borrow().as_attr_set().cloned().unwrap();
 
-                                lhs.extend(lhs_set);
-                                lhs.extend(rhs.clone());
+                                lhs.update(rhs);
 
                                 NixValue::AttrSet(lhs).wrap()
                             })?;
@@ -215,11 +239,9 @@ impl LazyNixValue {
                                 .as_attr_set()
                                 .ok_or_else(|| todo!("Error handling"))
                                 .map(|rhs| {
-                                    let lhs_set = lhs.borrow().as_attr_set().cloned().unwrap();
-                                    let mut lhs = NixAttrSet::new();
+                                    let mut lhs = lhs.borrow().as_attr_set().cloned().unwrap();
 
-                                    lhs.extend(lhs_set);
-                                    lhs.extend(rhs.clone());
+                                    lhs.update(rhs);
 
                                     let value = NixValue::AttrSet(lhs).wrap();
 
@@ -268,35 +290,80 @@ impl LazyNixValue {
     ) -> NixResult {
         let value = Self::resolve(this, backtrace)?;
 
-        if value.borrow().is_attr_set() {
-            let values = if let Some(set) = value.borrow().as_attr_set() {
-                set.values().cloned().collect::<Vec<_>>()
-            } else {
-                unreachable!()
-            };
+        if !recursive {
+            for var in Self::children(&value, false, backtrace)?.unwrap_or_default() {
+                var.resolve(backtrace)?;
+            }
 
-            for var in values {
-                if recursive {
-                    var.resolve_set(true, backtrace)?;
-                } else {
-                    var.resolve(backtrace)?;
+            return Ok(value);
+        }
+
+        enum Step {
+            Enter(Rc<RefCell<LazyNixValue>>),
+            Exit,
+        }
+
+        // Iterative, a deep value would overflow the native stack otherwise.
+        // `ancestors` are the sets and lists being resolved above the current
+        // one, a value containing itself (`let x = { a = x; }; in x`) would
+        // never end
+        let mut ancestors: Vec<NixValueWrapped> = vec![];
+        let mut steps = vec![Step::Enter(this.clone())];
+
+        while let Some(step) = steps.pop() {
+            let var = match step {
+                Step::Enter(var) => var,
+                Step::Exit => {
+                    ancestors.pop();
+                    continue;
                 }
-            }
-        } else if let Some(list) = value.borrow().as_list() {
-            list.0
+            };
+
+            let value = Self::resolve(&var, backtrace)?;
+
+            let Some(values) = Self::children(&value, true, backtrace)? else {
+                continue;
+            };
+
+            if ancestors
                 .iter()
-                .map(|var| {
-                    if recursive {
-                        var.resolve_set(true, backtrace)?;
-                    } else {
-                        var.resolve(backtrace)?;
-                    }
+                .any(|ancestor| Rc::ptr_eq(ancestor, &value))
+            {
+                return Err(backtrace.to_error(
+                    NixLabelKind::Error,
+                    NixLabelMessage::Empty,
+                    format!(
+                        "Infinite recursion detected. The {} contains itself",
+                        value.borrow().as_type()
+                    ),
+                ));
+            }
 
-                    Ok(())
-                })
-                .collect::<Result<(), _>>()?;
+            ancestors.push(value);
+            steps.push(Step::Exit);
+            steps.extend(values.into_iter().rev().map(|var| Step::Enter(var.0)));
         }
 
         Ok(value)
     }
+
+    /// Values inside a set or a list, `None` for anything else
+    fn children(
+        value: &NixValueWrapped,
+        recursive: bool,
+        backtrace: &NixBacktrace,
+    ) -> NixResult<Option<Vec<NixVar>>> {
+        Ok(match &*value.borrow() {
+            // Derivations are printed by their `.drv`, like in Nix
+            NixValue::AttrSet(set) if recursive && set.is_derivation(backtrace)? => Some(
+                ["drvPath", "outPath"]
+                    .iter()
+                    .filter_map(|attr| set.get(*attr).cloned())
+                    .collect(),
+            ),
+            NixValue::AttrSet(set) => Some(set.values().cloned().collect()),
+            NixValue::List(list) => Some(list.0.to_vec()),
+            _ => None,
+        })
+    }
 }
@@ -0,0 +1,151 @@
+//! Arithmetic and ordering shared by the operators and their builtins
+//! (`+` and `builtins.add`, `<` and `builtins.lessThan`, ...)
+
+use std::cmp::Ordering;
+
+use crate::{NixBacktrace, NixResult};
+
+use super::NixValue;
+
+/// Numbers of the arithmetic, anything involving a float is a float
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NixNumber {
+    Int(i64),
+    Float(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NixArithOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl NixArithOp {
+    fn verb(self) -> &'static str {
+        match self {
+            NixArithOp::Add => "add",
+            NixArithOp::Sub => "subtract",
+            NixArithOp::Mul => "multiply",
+            NixArithOp::Div => "divide",
+        }
+    }
+}
+
+impl NixNumber {
+    pub fn from_value(value: &NixValue) -> Option<Self> {
+        match value {
+            NixValue::Int(int) => Some(NixNumber::Int(*int)),
+            NixValue::Float(float) => Some(NixNumber::Float(*float)),
+            _ => None,
+        }
+    }
+
+    fn as_float(self) -> f64 {
+        match self {
+            NixNumber::Int(int) => int as f64,
+            NixNumber::Float(float) => float,
+        }
+    }
+
+    /// Integer division truncates toward zero, division by zero and integer
+    /// overflow are errors
+    pub fn arith(op: NixArithOp, lhs: Self, rhs: Self) -> Result<Self, String> {
+        if op == NixArithOp::Div && rhs.as_float() == 0.0 {
+            return Err(String::from("division by zero"));
+        }
+
+        match (lhs, rhs) {
+            (NixNumber::Int(lhs), NixNumber::Int(rhs)) => {
+                let result = match op {
+                    NixArithOp::Add => lhs.checked_add(rhs),
+                    NixArithOp::Sub => lhs.checked_sub(rhs),
+                    NixArithOp::Mul => lhs.checked_mul(rhs),
+                    NixArithOp::Div => lhs.checked_div(rhs),
+                };
+
+                result.map(NixNumber::Int).ok_or_else(|| {
+                    format!(
+                        "integer overflow when trying to {} {lhs} and {rhs}",
+                        op.verb()
+                    )
+                })
+            }
+            (lhs, rhs) => {
+                let (lhs, rhs) = (lhs.as_float(), rhs.as_float());
+
+                Ok(NixNumber::Float(match op {
+                    NixArithOp::Add => lhs + rhs,
+                    NixArithOp::Sub => lhs - rhs,
+                    NixArithOp::Mul => lhs * rhs,
+                    NixArithOp::Div => lhs / rhs,
+                }))
+            }
+        }
+    }
+}
+
+impl From<NixNumber> for NixValue {
+    fn from(number: NixNumber) -> Self {
+        match number {
+            NixNumber::Int(int) => NixValue::Int(int),
+            NixNumber::Float(float) => NixValue::Float(float),
+        }
+    }
+}
+
+impl NixValue {
+    /// `lhs <op> rhs` for numbers, the error describes the operand types otherwise
+    pub fn arith(op: NixArithOp, lhs: &NixValue, rhs: &NixValue) -> Result<NixValue, String> {
+        match (NixNumber::from_value(lhs), NixNumber::from_value(rhs)) {
+            (Some(lhs), Some(rhs)) => NixNumber::arith(op, lhs, rhs).map(NixValue::from),
+            _ => Err(format!(
+                "cannot {} {} and {}",
+                op.verb(),
+                lhs.as_type_with_article(),
+                rhs.as_type_with_article()
+            )),
+        }
+    }
+
+    /// Ordering of `<`, numbers of both kinds, strings, paths and lists can be
+    /// compared. The outer error comes from forcing list elements, the inner
+    /// one describes operands that can't be compared
+    pub fn less_than(
+        &self,
+        other: &NixValue,
+        backtrace: &NixBacktrace,
+    ) -> NixResult<Result<bool, String>> {
+        if let (Some(lhs), Some(rhs)) = (NixNumber::from_value(self), NixNumber::from_value(other))
+        {
+            return Ok(Ok(match (lhs, rhs) {
+                (NixNumber::Int(lhs), NixNumber::Int(rhs)) => lhs < rhs,
+                (lhs, rhs) => lhs.as_float().partial_cmp(&rhs.as_float()) == Some(Ordering::Less),
+            }));
+        }
+
+        match (self, other) {
+            (NixValue::String(lhs), NixValue::String(rhs)) => Ok(Ok(lhs.as_str() < rhs.as_str())),
+            (NixValue::Path(lhs), NixValue::Path(rhs)) => Ok(Ok(lhs.as_os_str() < rhs.as_os_str())),
+            // The first element that differs decides, a prefix is smaller
+            (NixValue::List(lhs), NixValue::List(rhs)) => {
+                for (lhs, rhs) in lhs.0.iter().zip(rhs.0.iter()) {
+                    if !lhs.try_eq(rhs, backtrace)? {
+                        let lhs = lhs.resolve(backtrace)?;
+                        let rhs = rhs.resolve(backtrace)?;
+
+                        return lhs.borrow().less_than(&rhs.borrow(), backtrace);
+                    }
+                }
+
+                Ok(Ok(lhs.0.len() < rhs.0.len()))
+            }
+            _ => Ok(Err(format!(
+                "cannot compare {} with {}",
+                self.as_type_with_article(),
+                other.as_type_with_article()
+            ))),
+        }
+    }
+}
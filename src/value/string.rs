@@ -0,0 +1,130 @@
+//! Strings remember the store paths they were built from (their context)
+//!
+//! https://nix.dev/manual/nix/2.24/language/string-context
+
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::Deref;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NixContextElem {
+    /// A store path by itself, `{ path = true; }` in `getContext`
+    Opaque(String),
+    /// A derivation with all of its outputs, `{ allOutputs = true; }`
+    DrvDeep(String),
+    /// A single output of a derivation, `{ outputs = [ output ]; }`
+    Built { drv_path: String, output: String },
+}
+
+impl NixContextElem {
+    /// The store path this element refers to, a `.drv` for derivations
+    pub fn path(&self) -> &str {
+        match self {
+            NixContextElem::Opaque(path) | NixContextElem::DrvDeep(path) => path,
+            NixContextElem::Built { drv_path, .. } => drv_path,
+        }
+    }
+
+    fn sort_key(&self) -> (&str, u8, &str) {
+        match self {
+            NixContextElem::Opaque(path) => (path, 0, ""),
+            NixContextElem::DrvDeep(path) => (path, 1, ""),
+            NixContextElem::Built { drv_path, output } => (drv_path, 2, output),
+        }
+    }
+}
+
+/// Sorted by path first, so the elements of a path are next to each other
+impl Ord for NixContextElem {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sort_key().cmp(&other.sort_key())
+    }
+}
+
+impl PartialOrd for NixContextElem {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+pub type NixStringContext = BTreeSet<NixContextElem>;
+
+/// Context is carried along but never affects comparisons
+#[derive(Clone, Default)]
+pub struct NixString {
+    value: String,
+    context: NixStringContext,
+}
+
+impl NixString {
+    pub fn new(value: String, context: NixStringContext) -> Self {
+        Self { value, context }
+    }
+
+    pub fn context(&self) -> &NixStringContext {
+        &self.context
+    }
+
+    pub fn has_context(&self) -> bool {
+        !self.context.is_empty()
+    }
+
+    /// Same context with another content, for builtins that derive a string from this one
+    pub fn with_value(&self, value: String) -> Self {
+        Self::new(value, self.context.clone())
+    }
+
+    pub fn push(&mut self, other: &NixString) {
+        self.value.push_str(&other.value);
+        self.context.extend(other.context.iter().cloned());
+    }
+
+    pub fn extend_context(&mut self, context: impl IntoIterator<Item = NixContextElem>) {
+        self.context.extend(context);
+    }
+
+    pub fn into_string(self) -> String {
+        self.value
+    }
+}
+
+impl Deref for NixString {
+    type Target = String;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl From<String> for NixString {
+    fn from(value: String) -> Self {
+        Self::new(value, NixStringContext::new())
+    }
+}
+
+impl From<&str> for NixString {
+    fn from(value: &str) -> Self {
+        Self::from(value.to_owned())
+    }
+}
+
+impl PartialEq for NixString {
+    fn eq(&self, other: &Self) -> bool {
+        self.value == other.value
+    }
+}
+
+impl Eq for NixString {}
+
+impl fmt::Debug for NixString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.value, f)
+    }
+}
+
+impl fmt::Display for NixString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.value)
+    }
+}
@@ -0,0 +1,209 @@
+//! Print resolved values back as Nix source (`--to-nix`)
+
+use std::fmt::Write;
+use std::path::Path;
+
+use super::{NixLambda, NixValue, NixVar};
+
+const KEYWORDS: &[&str] = &[
+    "assert", "else", "if", "in", "inherit", "let", "or", "rec", "then", "with",
+];
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ToNixMode {
+    /// Lambdas and derivations cannot be printed
+    Strict,
+    /// Derivations are printed as their `outPath` string
+    WithOutPaths,
+}
+
+pub fn is_valid_ident(key: &str) -> bool {
+    let mut chars = key.chars();
+
+    let Some(first) = chars.next() else {
+        return false;
+    };
+
+    (first.is_ascii_alphabetic() || first == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || "_'-".contains(c))
+        && !KEYWORDS.contains(&key)
+}
+
+pub fn escape_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+
+    out.push('"');
+    out
+}
+
+fn format_path(path: &Path) -> String {
+    let path = path.display().to_string();
+
+    let is_literal = path.len() > 1
+        && !path.ends_with('/')
+        && path
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "._-+/".contains(c));
+
+    if is_literal {
+        path
+    } else {
+        format!("(/. + {})", escape_string(&path))
+    }
+}
+
+/// Shortest representation that parses back to the same `f64`
+fn format_float(float: f64) -> Result<String, String> {
+    if !float.is_finite() {
+        return Err(format!("Cannot print {float} as Nix"));
+    }
+
+    // Nix floats always need a dot, `1e10` is not a float literal
+    let mut out = format!("{float:?}");
+    if let Some(idx) = out.find('e') {
+        if !out[..idx].contains('.') {
+            out.insert_str(idx, ".0");
+        }
+    }
+
+    Ok(out)
+}
+
+fn format_int(int: i64) -> String {
+    if int == i64::MIN {
+        // The literal doesn't fit before being negated
+        format!("({} - 1)", i64::MIN + 1)
+    } else {
+        int.to_string()
+    }
+}
+
+fn concrete(var: &NixVar) -> Result<std::rc::Rc<std::cell::RefCell<NixValue>>, String> {
+    var.as_concrete()
+        .ok_or_else(|| "Can't print something unresolved, run `.resolve_set()` first".to_owned())
+}
+
+impl NixValue {
+    fn derivation_out_path(&self) -> Result<Option<String>, String> {
+        let NixValue::AttrSet(set) = self else {
+            return Ok(None);
+        };
+
+        let Some(ty) = set.get("type") else {
+            return Ok(None);
+        };
+
+        if concrete(ty)?.borrow().as_string().map(String::as_str) != Some("derivation") {
+            return Ok(None);
+        }
+
+        let out_path = set
+            .get("outPath")
+            .map(concrete)
+            .transpose()?
+            .and_then(|out_path| out_path.borrow().cast_to_string());
+
+        Ok(Some(out_path.ok_or("Derivation has no outPath")?))
+    }
+
+    /// Print an already resolved value as Nix source that evaluates to an equal value
+    pub fn to_nix(&self, mode: ToNixMode) -> Result<String, String> {
+        let mut out = String::new();
+        self.write_nix(&mut out, mode, 0)?;
+        Ok(out)
+    }
+
+    fn write_nix(&self, out: &mut String, mode: ToNixMode, indent: usize) -> Result<(), String> {
+        let pad = "  ".repeat(indent + 1);
+        let outside_pad = "  ".repeat(indent);
+
+        if let Some(out_path) = self.derivation_out_path()? {
+            if mode == ToNixMode::Strict {
+                return Err(
+                    "Cannot print a derivation as Nix, use `--to-nix=with-out-paths`".to_owned(),
+                );
+            }
+
+            out.push_str(&escape_string(&out_path));
+            return Ok(());
+        }
+
+        match self {
+            NixValue::AttrSet(set) if set.is_empty() => out.push_str("{ }"),
+            NixValue::AttrSet(set) => {
+                out.push_str("{\n");
+
+                for (key, value) in set {
+                    out.push_str(&pad);
+
+                    if is_valid_ident(key) {
+                        out.push_str(key);
+                    } else {
+                        out.push_str(&escape_string(key));
+                    }
+
+                    out.push_str(" = ");
+                    concrete(value)?.borrow().write_nix(out, mode, indent + 1)?;
+                    out.push_str(";\n");
+                }
+
+                out.push_str(&outside_pad);
+                out.push('}');
+            }
+            NixValue::Bool(true) => out.push_str("true"),
+            NixValue::Bool(false) => out.push_str("false"),
+            NixValue::Float(float) => {
+                let float = format_float(*float)?;
+
+                if float.starts_with('-') {
+                    let _ = write!(out, "({float})");
+                } else {
+                    out.push_str(&float);
+                }
+            }
+            NixValue::Int(int) if *int < 0 => {
+                let _ = write!(out, "({})", format_int(*int));
+            }
+            NixValue::Int(int) => out.push_str(&format_int(*int)),
+            NixValue::Lambda(NixLambda::Apply(..)) => {
+                return Err("Cannot print a lambda as Nix".to_owned())
+            }
+            NixValue::Lambda(NixLambda::Builtin(builtin)) => {
+                return Err(format!("Cannot print the builtin {builtin} as Nix"))
+            }
+            NixValue::List(list) if list.0.is_empty() => out.push_str("[ ]"),
+            NixValue::List(list) => {
+                out.push_str("[\n");
+
+                for value in list.0.iter() {
+                    out.push_str(&pad);
+                    concrete(value)?.borrow().write_nix(out, mode, indent + 1)?;
+                    out.push('\n');
+                }
+
+                out.push_str(&outside_pad);
+                out.push(']');
+            }
+            NixValue::Null => out.push_str("null"),
+            NixValue::Path(path) => out.push_str(&format_path(path)),
+            NixValue::String(s) => out.push_str(&escape_string(s)),
+        }
+
+        Ok(())
+    }
+}
@@ -40,17 +40,7 @@ impl NixVar {
     }
 
     pub fn resolve(&self, backtrace: &NixBacktrace) -> NixResult {
-        if let LazyNixValue::Concrete(value) = &*self.0.borrow() {
-            return Ok(value.clone());
-        }
-
-        let mut out_value = LazyNixValue::resolve(&self.0, backtrace)?;
-
-        while matches!(&*self.0.borrow(), LazyNixValue::UpdateResolve { .. }) {
-            out_value = LazyNixValue::resolve(&self.0, backtrace)?;
-        }
-
-        Ok(out_value)
+        LazyNixValue::resolve(&self.0, backtrace)
     }
 
     pub fn resolve_set(&self, recursive: bool, backtrace: &NixBacktrace) -> NixResult {